@@ -0,0 +1,223 @@
+use aws_sdk_ec2::config::{RuntimeComponents, SharedHttpClient};
+use aws_smithy_http_client::tls::{rustls_provider::CryptoMode, Provider};
+use aws_smithy_runtime_api::client::{
+    http::{
+        HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector,
+    },
+    orchestrator::{HttpRequest, HttpResponse},
+};
+use aws_smithy_runtime_api::http::StatusCode;
+use aws_smithy_types::body::SdkBody;
+use std::hash::{BuildHasher, RandomState};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use tracing::warn;
+
+/// The fault rates set at startup, if faults are injected at all.
+static RATES: OnceLock<Rates> = OnceLock::new();
+
+/// The state of the random number generator deciding when to inject a fault.
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+/// How often each kind of fault is injected, as probabilities between 0 and 1.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rates {
+    /// The share of IP detections that time out.
+    detection: f64,
+    /// The share of EC2 requests answered with `RequestLimitExceeded`.
+    throttle: f64,
+    /// The share of prefix list modifications answered with `PrefixListVersionMismatch`.
+    conflict: f64,
+}
+
+impl FromStr for Rates {
+    type Err = String;
+
+    /// Parses rates given as `detection=0.1,throttle=0.05,conflict=0.05`, where kinds left
+    /// out are never injected.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rates = Rates::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (kind, rate) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected KIND=RATE, got {}", pair))?;
+            let rate = rate
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|rate| (0.0..=1.0).contains(rate))
+                .ok_or_else(|| format!("Rate of {} must be between 0 and 1", kind))?;
+            match kind.trim() {
+                "detection" => rates.detection = rate,
+                "throttle" => rates.throttle = rate,
+                "conflict" => rates.conflict = rate,
+                other => {
+                    return Err(format!(
+                        "Unknown fault {}; expected detection, throttle or conflict",
+                        other
+                    ))
+                }
+            }
+        }
+
+        Ok(rates)
+    }
+}
+
+/// Starts injecting faults at the given rates.
+///
+/// # Parameters
+///
+/// * `rates`: How often each kind of fault is injected.
+pub fn init(rates: Rates) {
+    STATE.store(RandomState::new().hash_one(0u64) | 1, Ordering::Relaxed);
+    if RATES.set(rates).is_ok() {
+        warn!(
+            "Chaos mode: injecting detection timeouts ({}), throttles ({}) and version conflicts \
+             ({})",
+            rates.detection, rates.throttle, rates.conflict
+        );
+    }
+}
+
+/// Decides whether to inject a fault.
+///
+/// # Parameters
+///
+/// * `rate`: The probability of injecting it.
+///
+/// # Returns
+///
+/// Whether the fault is injected this time.
+fn roll(rate: f64) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+
+    // xorshift64 is plenty for spreading faults, and needs no extra dependency
+    let mut x = STATE.load(Ordering::Relaxed);
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    STATE.store(x, Ordering::Relaxed);
+
+    ((x >> 11) as f64 / (1u64 << 53) as f64) < rate
+}
+
+/// Decides whether an IP detection times out.
+///
+/// # Returns
+///
+/// An error to return instead of detecting, or `None` to detect as usual.
+pub fn detection_fault() -> Option<String> {
+    let rates = RATES.get()?;
+    roll(rates.detection).then(|| {
+        warn!("Chaos mode: injecting an IP detection timeout");
+        "IP detection timed out (injected by --chaos)".to_string()
+    })
+}
+
+/// Builds the response EC2 gives for an error.
+///
+/// # Parameters
+///
+/// * `status`: The HTTP status.
+/// * `code`: The AWS error code.
+/// * `message`: The AWS error message.
+///
+/// # Returns
+///
+/// The error response.
+fn error_response(status: u16, code: &str, message: &str) -> HttpResponse {
+    HttpResponse::new(
+        StatusCode::try_from(status).expect("valid status code"),
+        SdkBody::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Response><Errors><Error><Code>{}</Code>\
+             <Message>{} (injected by --chaos)</Message></Error></Errors><RequestID>chaos\
+             </RequestID></Response>",
+            code, message
+        )),
+    )
+}
+
+/// An HTTP client that answers some requests with injected errors instead of sending them.
+#[derive(Clone, Debug)]
+struct ChaosClient {
+    /// The client sending the requests that get through.
+    inner: SharedHttpClient,
+}
+
+impl HttpClient for ChaosClient {
+    fn http_connector(
+        &self,
+        settings: &HttpConnectorSettings,
+        components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        SharedHttpConnector::new(ChaosConnector {
+            inner: self.inner.http_connector(settings, components),
+        })
+    }
+}
+
+/// The connector of a [`ChaosClient`].
+#[derive(Debug)]
+struct ChaosConnector {
+    /// The connector sending the requests that get through.
+    inner: SharedHttpConnector,
+}
+
+impl HttpConnector for ChaosConnector {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let rates = RATES.get().copied().unwrap_or_default();
+        let body = request
+            .body()
+            .bytes()
+            .map(String::from_utf8_lossy)
+            .unwrap_or_default();
+        let action = crate::replay::action(&body).to_string();
+
+        // Faults are answered without sending, so an injected failure never changes anything
+        if action == "ModifyManagedPrefixList" && roll(rates.conflict) {
+            warn!("Chaos mode: injecting a version conflict into {}", action);
+            return HttpConnectorFuture::ready(Ok(error_response(
+                400,
+                "PrefixListVersionMismatch",
+                "The prefix list has been modified",
+            )));
+        }
+        if roll(rates.throttle) {
+            warn!("Chaos mode: injecting a throttle into {}", action);
+            return HttpConnectorFuture::ready(Ok(error_response(
+                503,
+                "RequestLimitExceeded",
+                "Request limit exceeded.",
+            )));
+        }
+
+        self.inner.call(request)
+    }
+}
+
+/// Routes the requests of an EC2 client through fault injection, when enabled.
+///
+/// # Parameters
+///
+/// * `builder`: The EC2 client configuration.
+///
+/// # Returns
+///
+/// The configuration, sending through the fault injecting client if faults are injected.
+pub fn wrap(builder: aws_sdk_ec2::config::Builder) -> aws_sdk_ec2::config::Builder {
+    if RATES.get().is_none() {
+        return builder;
+    }
+
+    // Without a client of its own the SDK uses this one, which is hidden inside it
+    let inner = builder.clone().build().http_client().unwrap_or_else(|| {
+        aws_smithy_http_client::Builder::new()
+            .tls_provider(Provider::Rustls(CryptoMode::AwsLc))
+            .build_https()
+    });
+    builder.http_client(ChaosClient { inner })
+}
@@ -0,0 +1,155 @@
+use aws_smithy_async::time::{SharedTimeSource, TimeSource};
+use aws_smithy_types::date_time::{DateTime, Format};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// The smallest skew reported or corrected, as the `Date` header only has whole seconds.
+const MIN_SKEW: Duration = Duration::from_secs(2);
+
+/// The skew at which a warning is logged, well before AWS starts rejecting signatures.
+const WARN_SKEW: Duration = Duration::from_secs(60);
+
+/// The AWS clock minus the local clock in milliseconds, from the last response.
+static OFFSET_MILLIS: AtomicI64 = AtomicI64::new(0);
+
+/// Whether a response `Date` header has been seen.
+static MEASURED: AtomicBool = AtomicBool::new(false);
+
+/// Whether request signatures are corrected for the skew.
+static CORRECTING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the skew has been warned about.
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// A time source for request signing that follows the AWS clock rather than the local one.
+#[derive(Debug)]
+pub struct CorrectedClock {
+    /// The local clock the measured skew is applied to.
+    base: SharedTimeSource,
+}
+
+impl TimeSource for CorrectedClock {
+    fn now(&self) -> SystemTime {
+        let now = self.base.now();
+        match skew() {
+            Some(offset) if offset >= 0 => now + Duration::from_millis(offset.unsigned_abs()),
+            Some(offset) => now - Duration::from_millis(offset.unsigned_abs()),
+            None => now,
+        }
+    }
+}
+
+/// Signs requests with the AWS clock from now on, once a skew has been measured.
+///
+/// # Parameters
+///
+/// * `base`: The local clock the skew is applied to.
+///
+/// # Returns
+///
+/// The time source to sign requests with.
+pub fn correct(base: SharedTimeSource) -> CorrectedClock {
+    CORRECTING.store(true, Ordering::Relaxed);
+    CorrectedClock { base }
+}
+
+/// Measures the skew from the `Date` header of an AWS response.
+///
+/// # Parameters
+///
+/// * `date`: The value of the header, an HTTP date.
+pub fn observe(date: &str) {
+    let Ok(date) = DateTime::from_str(date, Format::HttpDate) else {
+        return;
+    };
+    let Ok(local) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) else {
+        return;
+    };
+    let offset = date.as_secs_f64() * 1000.0 - local.as_millis() as f64;
+    OFFSET_MILLIS.store(offset as i64, Ordering::Relaxed);
+    MEASURED.store(true, Ordering::Relaxed);
+
+    if offset.abs() >= WARN_SKEW.as_millis() as f64 && !WARNED.swap(true, Ordering::Relaxed) {
+        warn!(
+            "{}; {}",
+            describe().unwrap_or_default(),
+            if CORRECTING.load(Ordering::Relaxed) {
+                "correcting request signatures for it"
+            } else {
+                "AWS rejects requests signed more than 5 minutes off, set --correct-clock-skew \
+                 or sync the clock"
+            }
+        );
+    }
+}
+
+/// Returns the measured skew when it is large enough to matter.
+///
+/// # Returns
+///
+/// The AWS clock minus the local clock in milliseconds, or `None` if no response has been
+/// seen or the clocks agree.
+fn skew() -> Option<i64> {
+    let offset = OFFSET_MILLIS.load(Ordering::Relaxed);
+    (MEASURED.load(Ordering::Relaxed) && offset.unsigned_abs() >= MIN_SKEW.as_millis() as u64)
+        .then_some(offset)
+}
+
+/// Describes the measured skew.
+///
+/// # Returns
+///
+/// How far and which way the local clock is off, or `None` if it is not measurably off.
+pub fn describe() -> Option<String> {
+    let offset = skew()?;
+    let seconds = offset.unsigned_abs() / 1000;
+    let amount = match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    };
+
+    Some(format!(
+        "the local clock is {} {} AWS (from the response Date header)",
+        amount,
+        if offset > 0 { "behind" } else { "ahead of" }
+    ))
+}
+
+/// Identifies the errors AWS returns for a request signed at the wrong time.
+///
+/// # Parameters
+///
+/// * `code`: The AWS error code.
+/// * `message`: The AWS error message, if any.
+///
+/// # Returns
+///
+/// Whether the error is caused by clock skew.
+pub fn is_skew_error(code: &str, message: Option<&str>) -> bool {
+    matches!(
+        code,
+        "RequestTimeTooSkewed" | "RequestExpired" | "RequestInTheFuture"
+    ) || message.is_some_and(|message| {
+        message.contains("Signature expired") || message.contains("Signature not yet current")
+    })
+}
+
+/// Suggests how to fix a clock skew error.
+///
+/// # Returns
+///
+/// The measured skew when known, and how to fix it.
+pub fn hint() -> String {
+    let fix = if CORRECTING.load(Ordering::Relaxed) {
+        "signatures are corrected from the next request on"
+    } else {
+        "sync the clock with NTP (e.g. timedatectl set-ntp true) or set --correct-clock-skew"
+    };
+
+    match describe() {
+        Some(skew) => format!("{}; {}", skew, fix),
+        None => format!("the local clock is likely wrong; {}", fix),
+    }
+}
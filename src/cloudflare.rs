@@ -0,0 +1,241 @@
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::time::Duration;
+use tracing::info;
+
+/// The base URL of the Cloudflare API.
+const API_URL: &str = "https://api.cloudflare.com/client/v4";
+
+/// How long a Cloudflare API request may take.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A DNS record as returned by the Cloudflare API.
+#[derive(Deserialize)]
+struct Record {
+    /// The ID of the record.
+    id: String,
+    /// The address the record points to.
+    content: String,
+}
+
+/// The envelope of every Cloudflare API response.
+#[derive(Deserialize)]
+struct Response<T> {
+    /// Whether the request succeeded.
+    success: bool,
+    /// The errors, when it did not.
+    #[serde(default)]
+    errors: Vec<ApiError>,
+    /// The result of the request.
+    result: Option<T>,
+}
+
+/// An error reported by the Cloudflare API.
+#[derive(Deserialize)]
+struct ApiError {
+    /// The Cloudflare error code.
+    code: u64,
+    /// What went wrong.
+    message: String,
+}
+
+/// The A and AAAA records of a name in a Cloudflare zone, kept pointing at the current
+/// addresses as a dynamic DNS client would.
+pub struct CloudflareRecord {
+    /// The client instance used to call the Cloudflare API.
+    client: reqwest::Client,
+    /// The API token, which needs the `Zone.DNS` edit permission on the zone.
+    token: String,
+    /// The ID of the zone holding the record.
+    zone_id: String,
+    /// The fully qualified name of the record.
+    pub name: String,
+}
+
+impl CloudflareRecord {
+    /// Builds the record from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// The record, or `None` when no Cloudflare record is configured.
+    pub fn from_args(args: &crate::Args) -> Option<Self> {
+        Some(Self {
+            client: reqwest::Client::new(),
+            token: args.cloudflare_api_token.clone()?,
+            zone_id: args.cloudflare_zone_id.clone()?,
+            name: args.cloudflare_record.clone()?,
+        })
+    }
+
+    /// Sends a request to the Cloudflare API.
+    ///
+    /// # Parameters
+    ///
+    /// * `method`: The HTTP method.
+    /// * `path`: The path below the zone's DNS records.
+    /// * `body`: The JSON body, if any.
+    ///
+    /// # Returns
+    ///
+    /// The result of the request, or an error if it fails or Cloudflare reports one.
+    async fn request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/zones/{}/dns_records{}", API_URL, self.zone_id, path);
+        let mut request = self
+            .client
+            .request(method, &url)
+            .bearer_auth(&self.token)
+            .timeout(REQUEST_TIMEOUT);
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        let response: Response<T> = serde_json::from_slice(&response.bytes().await?)
+            .map_err(|e| format!("Unexpected Cloudflare response ({}): {}", status, e))?;
+        if !response.success {
+            let errors = response
+                .errors
+                .iter()
+                .map(|error| format!("{} (code {})", error.message, error.code))
+                .collect::<Vec<_>>()
+                .join("; ");
+            return Err(format!("Cloudflare rejected the request ({}): {}", status, errors).into());
+        }
+
+        Ok(response.result)
+    }
+
+    /// Points the record's entries of one type at the given addresses, reusing the existing
+    /// entries so their TTL and proxy settings are kept.
+    ///
+    /// # Parameters
+    ///
+    /// * `record_type`: `A` or `AAAA`.
+    /// * `addresses`: The addresses the record should hold.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the record was modified, or an error if a request fails.
+    async fn sync(
+        &self,
+        record_type: &str,
+        addresses: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let path = format!("?type={}&name={}", record_type, self.name);
+        let records: Vec<Record> = self
+            .request(reqwest::Method::GET, &path, None)
+            .await?
+            .unwrap_or_default();
+
+        let (kept, mut stale): (Vec<_>, Vec<_>) = records
+            .into_iter()
+            .partition(|record| addresses.contains(&record.content));
+        let missing = addresses
+            .iter()
+            .filter(|address| !kept.iter().any(|record| &record.content == *address));
+
+        let mut changed = false;
+        for address in missing {
+            match stale.pop() {
+                Some(record) => {
+                    self.request::<serde_json::Value>(
+                        reqwest::Method::PATCH,
+                        &format!("/{}", record.id),
+                        Some(serde_json::json!({ "content": address })),
+                    )
+                    .await?;
+                    info!(
+                        "Updated {} record {} from {} to {}",
+                        record_type, self.name, record.content, address
+                    );
+                }
+                None => {
+                    // TTL 1 lets Cloudflare choose, as it does for records made in its dashboard
+                    self.request::<serde_json::Value>(
+                        reqwest::Method::POST,
+                        "",
+                        Some(serde_json::json!({
+                            "type": record_type,
+                            "name": self.name,
+                            "content": address,
+                            "ttl": 1,
+                            "proxied": false,
+                        })),
+                    )
+                    .await?;
+                    info!(
+                        "Created {} record {} for {}",
+                        record_type, self.name, address
+                    );
+                }
+            }
+            changed = true;
+        }
+        for record in stale {
+            self.request::<serde_json::Value>(
+                reqwest::Method::DELETE,
+                &format!("/{}", record.id),
+                None,
+            )
+            .await?;
+            info!(
+                "Removed {} record {} for {}",
+                record_type, self.name, record.content
+            );
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    /// Points the record at the addresses of the CIDRs.
+    ///
+    /// Only single-address CIDRs become records, and only the families present are touched,
+    /// so monitors of the IPv4 and IPv6 prefix lists can share one name. A delegated IPv6
+    /// prefix is not an address, and leaves the AAAA record alone.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidrs`: The CIDRs the prefix lists hold.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the record was modified, or an error if a request fails.
+    pub async fn apply(
+        &self,
+        cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut ipv4 = Vec::new();
+        let mut ipv6 = Vec::new();
+        for cidr in cidrs {
+            let (address, length) = cidr.split_once('/').unwrap_or((cidr, ""));
+            match address.parse::<IpAddr>() {
+                Ok(IpAddr::V4(address)) => ipv4.push(address.to_string()),
+                Ok(IpAddr::V6(address)) if length == "128" => ipv6.push(address.to_string()),
+                _ => {}
+            }
+        }
+
+        let mut changed = false;
+        if !ipv4.is_empty() {
+            changed |= self.sync("A", &ipv4).await?;
+        }
+        if !ipv6.is_empty() {
+            changed |= self.sync("AAAA", &ipv6).await?;
+        }
+
+        Ok(changed)
+    }
+}
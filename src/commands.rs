@@ -0,0 +1,1058 @@
+use crate::{EntryChange, PrefixListMonitor, Target};
+use aws_config::SdkConfig;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+/// The file formats prefix list entries can be exported to.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ExportFormat {
+    /// A JSON document with the prefix list ID, version and entries
+    Json,
+    /// A `cidr,description` CSV file with a header row
+    Csv,
+    /// Terraform `aws_ec2_managed_prefix_list_entry` resources with matching import blocks
+    Terraform,
+}
+
+/// Asks the operator to confirm an action on the terminal.
+///
+/// # Parameters
+///
+/// * `prompt`: The question to ask.
+///
+/// # Returns
+///
+/// `Ok(true)` if the operator answered yes, or an error if stdin cannot be read.
+pub fn confirm(prompt: &str) -> io::Result<bool> {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Returns the single target a per-list command operates on.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the resolved targets.
+///
+/// # Returns
+///
+/// The target, or an error if several prefix lists are configured.
+fn single_target(
+    monitor: &PrefixListMonitor,
+) -> Result<&Target, Box<dyn std::error::Error + Send + Sync>> {
+    match monitor.targets.as_slice() {
+        [target] => Ok(target),
+        _ => Err(
+            "This command operates on a single prefix list; remove the multi-account options"
+                .into(),
+        ),
+    }
+}
+
+/// Formats prefix list entries as a comma-separated list of CIDRs.
+///
+/// # Parameters
+///
+/// * `entries`: The entries to format.
+///
+/// # Returns
+///
+/// The formatted CIDRs, or `(empty)` when there are none.
+fn format_cidrs(entries: &[aws_sdk_ec2::types::PrefixListEntry]) -> String {
+    if entries.is_empty() {
+        return "(empty)".to_string();
+    }
+
+    entries
+        .iter()
+        .filter_map(|e| e.cidr())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Restores the prefix list to a previous version, or lists the available versions when no
+/// version is given.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list target.
+/// * `version`: The version to restore, if any.
+/// * `limit`: The number of previous versions to list.
+/// * `yes`: Whether to skip the confirmation prompt.
+///
+/// # Returns
+///
+/// An error if a request fails, or `Ok(())` on success or when the operator declines.
+pub async fn restore_version(
+    monitor: &mut PrefixListMonitor,
+    version: Option<i64>,
+    limit: i64,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+    let target = single_target(monitor)?;
+    let current_version = target.get_prefix_list_version().await?;
+
+    let Some(version) = version else {
+        println!(
+            "Prefix list {} is at version {}",
+            target.prefix_list_id, current_version
+        );
+        for version in (1.max(current_version - limit)..current_version).rev() {
+            let entries = target.get_entries(Some(version)).await?;
+            println!(
+                "  version {}: {} entries: {}",
+                version,
+                entries.len(),
+                format_cidrs(&entries)
+            );
+        }
+        println!("Run again with --version <VERSION> to restore one of them");
+        return Ok(());
+    };
+
+    if version < 1 || version >= current_version {
+        return Err(format!(
+            "Version must be between 1 and {} (the current version is {})",
+            current_version - 1,
+            current_version
+        )
+        .into());
+    }
+
+    let current_entries = target.get_entries(None).await?;
+    let restored_entries = target.get_entries(Some(version)).await?;
+    println!(
+        "Current (version {}): {}",
+        current_version,
+        format_cidrs(&current_entries)
+    );
+    println!(
+        "Restored (version {}): {}",
+        version,
+        format_cidrs(&restored_entries)
+    );
+
+    if !yes
+        && !confirm(&format!(
+            "Restore prefix list {} to version {}?",
+            target.prefix_list_id, version
+        ))?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    target.restore_version(version, current_version).await?;
+    println!(
+        "Restored prefix list {} to the entries of version {}",
+        target.prefix_list_id, version
+    );
+
+    Ok(())
+}
+
+/// Lists the recent versions of the prefix list with their entries and what changed in each.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list target.
+/// * `limit`: The number of versions to list, newest first.
+///
+/// # Returns
+///
+/// An error if a request fails, or `Ok(())` on success.
+pub async fn versions(
+    monitor: &mut PrefixListMonitor,
+    limit: i64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+    let target = single_target(monitor)?;
+    let current_version = target.get_prefix_list_version().await?;
+    let oldest_version = 1.max(current_version - limit + 1);
+
+    println!(
+        "Prefix list {} (current version {})",
+        target.prefix_list_id, current_version
+    );
+
+    // Read one extra version so the oldest listed one can show its changes too
+    let mut newer = target.get_entries(Some(current_version)).await?;
+    for version in (oldest_version..=current_version).rev() {
+        let older = if version > 1 {
+            target.get_entries(Some(version - 1)).await?
+        } else {
+            Vec::new()
+        };
+
+        println!();
+        println!("version {}:", version);
+        if newer.is_empty() {
+            println!("  (empty)");
+        }
+        for entry in &newer {
+            println!(
+                "  {:<20} {}",
+                entry.cidr().unwrap_or_default(),
+                entry.description().unwrap_or_default()
+            );
+        }
+
+        let added: Vec<&str> = newer
+            .iter()
+            .filter_map(|e| e.cidr())
+            .filter(|cidr| !older.iter().any(|o| o.cidr() == Some(cidr)))
+            .collect();
+        let removed: Vec<&str> = older
+            .iter()
+            .filter_map(|e| e.cidr())
+            .filter(|cidr| !newer.iter().any(|n| n.cidr() == Some(cidr)))
+            .collect();
+        let changes: Vec<String> = added
+            .iter()
+            .map(|cidr| format!("+{}", cidr))
+            .chain(removed.iter().map(|cidr| format!("-{}", cidr)))
+            .collect();
+        if !changes.is_empty() {
+            println!("  changes: {}", changes.join(" "));
+        }
+
+        newer = older;
+    }
+
+    Ok(())
+}
+
+/// The output formats of the `diff` command.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum DiffFormat {
+    /// Human-readable lines per prefix list
+    Human,
+    /// A JSON document with the planned changes of every prefix list
+    Json,
+}
+
+/// The file formats prefix list entries can be imported from.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum ImportFormat {
+    /// A JSON document as written by `export --format json`
+    Json,
+    /// A `cidr,description` CSV file as written by `export --format csv`
+    Csv,
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+///
+/// # Parameters
+///
+/// * `field`: The field to quote.
+///
+/// # Returns
+///
+/// The field, quoted if needed.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes a value as an HCL string literal, escaping quotes, backslashes and control
+/// characters, and the `${` and `%{` sequences Terraform would otherwise interpolate.
+///
+/// # Parameters
+///
+/// * `value`: The value to write.
+///
+/// # Returns
+///
+/// The quoted string literal.
+fn hcl_string(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            // Doubling the marker makes Terraform read it literally
+            '$' | '%' if chars.peek() == Some(&'{') => {
+                literal.push(c);
+                literal.push(c);
+            }
+            c if c.is_control() => literal.push_str(&format!("\\u{:04x}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Exports the entries of the prefix list for backups or migration to another list.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list target.
+/// * `format`: The format to write.
+/// * `managed_only`: Whether to export only the entries carrying the monitor's description.
+/// * `output`: The file to write, or `None` for stdout.
+///
+/// # Returns
+///
+/// An error if a request or the write fails, or `Ok(())` on success.
+pub async fn export(
+    monitor: &mut PrefixListMonitor,
+    format: ExportFormat,
+    managed_only: bool,
+    output: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+    let target = single_target(monitor)?;
+    let version = target.get_prefix_list_version().await?;
+    let entries: Vec<_> = target
+        .get_entries(Some(version))
+        .await?
+        .into_iter()
+        .filter(|e| !managed_only || e.description() == Some(monitor.description.as_str()))
+        .collect();
+
+    let contents = match format {
+        ExportFormat::Json => {
+            let document = serde_json::json!({
+                "prefix_list_id": target.prefix_list_id,
+                "version": version,
+                "entries": entries
+                    .iter()
+                    .map(|e| serde_json::json!({
+                        "cidr": e.cidr(),
+                        "description": e.description(),
+                    }))
+                    .collect::<Vec<_>>(),
+            });
+            serde_json::to_string_pretty(&document)? + "\n"
+        }
+        ExportFormat::Csv => {
+            let mut contents = String::from("cidr,description\n");
+            for entry in &entries {
+                contents.push_str(&format!(
+                    "{},{}\n",
+                    csv_field(entry.cidr().unwrap_or_default()),
+                    csv_field(entry.description().unwrap_or_default())
+                ));
+            }
+            contents
+        }
+        ExportFormat::Terraform => {
+            let mut contents = String::new();
+            for (index, entry) in entries.iter().enumerate() {
+                let cidr = entry.cidr().unwrap_or_default();
+                contents.push_str(&format!(
+                    "import {{\n  to = aws_ec2_managed_prefix_list_entry.entry_{index}\n  id = {}\n}}\n\n",
+                    hcl_string(&format!("{},{}", target.prefix_list_id, cidr))
+                ));
+                contents.push_str(&format!(
+                    "resource \"aws_ec2_managed_prefix_list_entry\" \"entry_{index}\" {{\n  prefix_list_id = {}\n  cidr           = {}\n  description    = {}\n}}\n\n",
+                    hcl_string(&target.prefix_list_id),
+                    hcl_string(cidr),
+                    hcl_string(entry.description().unwrap_or_default())
+                ));
+            }
+            contents
+        }
+    };
+
+    match output {
+        Some(path) => {
+            std::fs::write(path, contents)?;
+            eprintln!(
+                "Exported {} entries of prefix list {} (version {}) to {}",
+                entries.len(),
+                target.prefix_list_id,
+                version,
+                path.display()
+            );
+        }
+        None => print!("{}", contents),
+    }
+
+    Ok(())
+}
+
+/// Splits a CSV line into fields, honouring double-quoted fields.
+///
+/// # Parameters
+///
+/// * `line`: The line to split.
+///
+/// # Returns
+///
+/// The unquoted fields.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => fields.last_mut().unwrap().push(c),
+        }
+    }
+
+    fields
+}
+
+/// Reads the entries of an exported file.
+///
+/// # Parameters
+///
+/// * `path`: The file to read.
+/// * `format`: The format of the file, or `None` to infer it from the extension.
+///
+/// # Returns
+///
+/// The CIDRs and descriptions of the entries, or an error if the file cannot be read or parsed.
+fn read_entries(
+    path: &Path,
+    format: Option<ImportFormat>,
+) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = std::fs::read_to_string(path)?;
+    let format = format.unwrap_or(match path.extension().and_then(|e| e.to_str()) {
+        Some("csv") => ImportFormat::Csv,
+        _ => ImportFormat::Json,
+    });
+
+    let mut entries = Vec::new();
+    match format {
+        ImportFormat::Json => {
+            let document: serde_json::Value = serde_json::from_str(&contents)?;
+            let items = document["entries"]
+                .as_array()
+                .ok_or_else(|| format!("{} has no entries array", path.display()))?;
+            for item in items {
+                let cidr = item["cidr"]
+                    .as_str()
+                    .ok_or_else(|| format!("Entry without a cidr in {}", path.display()))?;
+                let description = item["description"].as_str().unwrap_or_default();
+                entries.push((cidr.to_string(), description.to_string()));
+            }
+        }
+        ImportFormat::Csv => {
+            for (index, line) in contents.lines().enumerate() {
+                // Skip the header row and blank lines
+                if (index == 0 && line.starts_with("cidr")) || line.trim().is_empty() {
+                    continue;
+                }
+                let mut fields = split_csv_line(line).into_iter();
+                let cidr = fields.next().unwrap_or_default();
+                let description = fields.next().unwrap_or_default();
+                entries.push((cidr.trim().to_string(), description));
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Applies the entries of a previously exported file to the prefix list, after showing what
+/// would change.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list target.
+/// * `path`: The exported file to read.
+/// * `format`: The format of the file, or `None` to infer it from the extension.
+/// * `prune`: Whether to remove entries that are not in the file.
+/// * `dry_run`: Whether to only show the changes.
+/// * `yes`: Whether to skip the confirmation prompt.
+///
+/// # Returns
+///
+/// An error if the file or a request fails, or `Ok(())` on success or when the operator declines.
+pub async fn import(
+    monitor: &mut PrefixListMonitor,
+    path: &Path,
+    format: Option<ImportFormat>,
+    prune: bool,
+    dry_run: bool,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let entries = read_entries(path, format)?;
+    monitor.resolve_targets().await?;
+    let target = single_target(monitor)?;
+    let snapshot = target.snapshot().await?;
+    let current = &snapshot.entries;
+
+    let mut additions = Vec::new();
+    for (cidr, description) in &entries {
+        match current.iter().find(|e| e.cidr() == Some(cidr.as_str())) {
+            Some(existing) if existing.description().unwrap_or_default() == description => {}
+            Some(existing) => {
+                println!(
+                    "~ {:<20} {} (was {})",
+                    cidr,
+                    description,
+                    existing.description().unwrap_or_default()
+                );
+                additions.push((cidr.clone(), description.clone()));
+            }
+            None => {
+                println!("+ {:<20} {}", cidr, description);
+                additions.push((cidr.clone(), description.clone()));
+            }
+        }
+    }
+
+    let mut removals = Vec::new();
+    if prune {
+        for entry in current {
+            let cidr = entry.cidr().unwrap_or_default();
+            if !entries.iter().any(|(c, _)| c == cidr) {
+                println!("- {:<20} {}", cidr, entry.description().unwrap_or_default());
+                removals.push(cidr.to_string());
+            }
+        }
+    }
+
+    if additions.is_empty() && removals.is_empty() {
+        println!(
+            "Prefix list {} already matches {}",
+            target.prefix_list_id,
+            path.display()
+        );
+        return Ok(());
+    }
+    println!(
+        "{} to add or update, {} to remove",
+        additions.len(),
+        removals.len()
+    );
+
+    if dry_run {
+        println!(
+            "Dry run; prefix list {} not modified",
+            target.prefix_list_id
+        );
+        return Ok(());
+    }
+    if !yes
+        && !confirm(&format!(
+            "Apply these changes to prefix list {}?",
+            target.prefix_list_id
+        ))?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    target
+        .modify_entries(Some(snapshot), &additions, &removals)
+        .await?;
+    println!(
+        "Imported {} into prefix list {}",
+        path.display(),
+        target.prefix_list_id
+    );
+
+    Ok(())
+}
+
+/// Shows the changes the monitor would make to every prefix list right now, without applying
+/// them.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list targets.
+/// * `format`: The output format.
+///
+/// # Returns
+///
+/// `Ok(true)` if any prefix list would change, or `Ok(false)` if all are in sync.
+/// An error if a request fails.
+pub async fn diff(
+    monitor: &mut PrefixListMonitor,
+    format: DiffFormat,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+    let external_ips = monitor.get_external_ips().await?;
+    let expected_cidrs = monitor.to_cidrs(&external_ips);
+
+    let mut changed = false;
+    let mut plans = Vec::new();
+    for target in &monitor.targets {
+        let version = target.get_prefix_list_version().await?;
+        let entries = target.get_current_entries(&monitor.description).await?;
+        let change = EntryChange::between(&entries, &expected_cidrs);
+        let target_version = if change.is_empty() {
+            version
+        } else {
+            version + 1
+        };
+        changed |= !change.is_empty();
+
+        match format {
+            DiffFormat::Human => {
+                if change.is_empty() {
+                    println!(
+                        "{} ({}): no changes at version {}",
+                        target.prefix_list_id, target.name, version
+                    );
+                    continue;
+                }
+                println!(
+                    "{} ({}): version {} -> {}",
+                    target.prefix_list_id, target.name, version, target_version
+                );
+                for cidr in &change.added {
+                    println!("  + {:<20} {}", cidr, monitor.description);
+                }
+                for cidr in &change.removed {
+                    println!("  - {:<20} {}", cidr, monitor.description);
+                }
+            }
+            DiffFormat::Json => plans.push(serde_json::json!({
+                "name": target.name,
+                "prefix_list_id": target.prefix_list_id,
+                "current_version": version,
+                "target_version": target_version,
+                "add": change.added,
+                "remove": change.removed,
+            })),
+        }
+    }
+
+    if let DiffFormat::Json = format {
+        let document = serde_json::json!({
+            "description": monitor.description,
+            "expected": expected_cidrs,
+            "changed": changed,
+            "targets": plans,
+        });
+        println!("{}", serde_json::to_string_pretty(&document)?);
+    }
+
+    Ok(changed)
+}
+
+/// Normalizes an IP address or CIDR given on the command line.
+///
+/// # Parameters
+///
+/// * `value`: An IP address, which becomes a single-host CIDR, or a CIDR.
+///
+/// # Returns
+///
+/// The CIDR, or an error if the value is not a valid address or CIDR.
+pub fn parse_cidr(value: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (address, prefix) = match value.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (value, None),
+    };
+    let address: std::net::IpAddr = address
+        .parse()
+        .map_err(|_| format!("Invalid IP address or CIDR {}", value))?;
+    let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|p| *p <= max_prefix)
+            .ok_or_else(|| format!("Invalid prefix length in {}", value))?,
+        None => max_prefix,
+    };
+
+    Ok(format!("{}/{}", address, prefix))
+}
+
+/// Adds a single entry to the prefix list, or updates its description if it already exists.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list target.
+/// * `cidr`: The IP address or CIDR to add.
+///
+/// # Returns
+///
+/// An error if the CIDR is invalid or a request fails, or `Ok(())` on success.
+pub async fn add(
+    monitor: &mut PrefixListMonitor,
+    cidr: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cidr = parse_cidr(cidr)?;
+    monitor.resolve_targets().await?;
+    let target = single_target(monitor)?;
+
+    let snapshot = target.snapshot().await?;
+    if snapshot.entries.iter().any(|e| {
+        e.cidr() == Some(cidr.as_str()) && e.description() == Some(monitor.description.as_str())
+    }) {
+        println!(
+            "{} is already in prefix list {}",
+            cidr, target.prefix_list_id
+        );
+        return Ok(());
+    }
+
+    target
+        .modify_entries(
+            Some(snapshot),
+            &[(cidr.clone(), monitor.description.clone())],
+            &[],
+        )
+        .await?;
+    println!(
+        "Added {} (\"{}\") to prefix list {}",
+        cidr, monitor.description, target.prefix_list_id
+    );
+
+    Ok(())
+}
+
+/// Removes a single entry from the prefix list.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list target.
+/// * `cidr`: The IP address or CIDR to remove.
+///
+/// # Returns
+///
+/// An error if the CIDR is invalid, not in the prefix list, or a request fails, or `Ok(())` on
+/// success.
+pub async fn remove(
+    monitor: &mut PrefixListMonitor,
+    cidr: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let cidr = parse_cidr(cidr)?;
+    monitor.resolve_targets().await?;
+    let target = single_target(monitor)?;
+
+    let snapshot = target.snapshot().await?;
+    if !snapshot
+        .entries
+        .iter()
+        .any(|e| e.cidr() == Some(cidr.as_str()))
+    {
+        return Err(format!("{} is not in prefix list {}", cidr, target.prefix_list_id).into());
+    }
+
+    target
+        .modify_entries(Some(snapshot), &[], std::slice::from_ref(&cidr))
+        .await?;
+    println!(
+        "Removed {} from prefix list {}",
+        cidr, target.prefix_list_id
+    );
+
+    Ok(())
+}
+
+/// Forces the managed entries of every prefix list to a given IP address or CIDR, skipping
+/// detection.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list targets.
+/// * `value`: The IP address, formatted like a detected one, or CIDR to apply.
+///
+/// # Returns
+///
+/// An error if the value is invalid or of a family the prefix lists don't hold, or a request
+/// fails, or `Ok(())` on success.
+pub async fn set_ip(
+    monitor: &mut PrefixListMonitor,
+    value: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // The prefix lists' family is only known once they are located
+    monitor.resolve_targets().await?;
+    let (ip, cidrs) = monitor.requested_cidrs(value)?;
+    if let Some(family) = monitor.family().filter(|family| !family.matches(&ip)) {
+        return Err(format!(
+            "{} is not an {} address, which is all the prefix lists hold",
+            ip, family
+        )
+        .into());
+    }
+
+    let cidr = cidrs.join(", ");
+    if monitor.apply(vec![ip], cidrs).await? {
+        println!("Set managed entries to {}", cidr);
+    } else {
+        println!("Managed entries are already {}", cidr);
+    }
+
+    Ok(())
+}
+
+/// Checks that AWS credentials resolve and shows whose they are, looking for the usual
+/// container and instance metadata problems when they don't.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration holding the credential providers.
+///
+/// # Returns
+///
+/// An error if the credentials cannot be resolved or are rejected.
+pub async fn validate_credentials(
+    config: &SdkConfig,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let sts = crate::replay::sts_client(config.into());
+    let e: Box<dyn std::error::Error + Send + Sync> = match sts.get_caller_identity().send().await {
+        Ok(identity) => {
+            println!(
+                "Credentials: {}",
+                identity.arn().unwrap_or("unknown identity")
+            );
+            return Ok(());
+        }
+        Err(e) => e.into(),
+    };
+
+    println!("Credentials: {}", crate::errors::describe(&*e));
+    if crate::errors::is_credentials_failure(&*e) {
+        let findings = crate::credentials::diagnose().await;
+        if findings.is_empty() {
+            println!("  no container role or instance metadata problem found");
+        }
+        for finding in findings {
+            println!("  - {}", finding);
+        }
+    }
+
+    Err("AWS credentials are not usable".into())
+}
+
+/// Checks that every prefix list can be read and the IP address detected, without changing
+/// anything.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list targets.
+///
+/// # Returns
+///
+/// An error if a prefix list cannot be read or the IP address cannot be detected.
+pub async fn validate(
+    monitor: &mut PrefixListMonitor,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+    for target in &monitor.targets {
+        let version = target.get_prefix_list_version().await?;
+        let entries = target.get_current_entries(&monitor.description).await?;
+        println!(
+            "{} ({}): version {}, {} managed entries",
+            target.prefix_list_id,
+            target.name,
+            version,
+            entries.len()
+        );
+    }
+
+    let external_ips = monitor.get_external_ips().await?;
+    println!("IP: {}", external_ips.join(", "));
+    println!("Configuration is valid");
+
+    Ok(())
+}
+
+/// Shows the detected IP address and the managed entries of every prefix list, without
+/// changing anything.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list targets.
+///
+/// # Returns
+///
+/// An error if a prefix list cannot be read or the IP address cannot be detected.
+pub async fn status(
+    monitor: &mut PrefixListMonitor,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+    let external_ips = monitor.get_external_ips().await?;
+    let expected_cidrs = monitor.to_cidrs(&external_ips);
+    println!("IP: {}", external_ips.join(", "));
+
+    for target in &monitor.targets {
+        let snapshot = target.snapshot().await?;
+        let entries = snapshot.managed_cidrs(&monitor.description);
+        let state = if EntryChange::between(&entries, &expected_cidrs).is_empty() {
+            "in sync"
+        } else {
+            "out of sync"
+        };
+        println!(
+            "{} ({}): version {}, {}",
+            target.prefix_list_id, target.name, snapshot.version, state
+        );
+        if entries.is_empty() {
+            println!("  (no managed entries)");
+        }
+        for cidr in &entries {
+            println!("  {:<20} {}", cidr, monitor.description);
+        }
+    }
+
+    Ok(())
+}
+
+/// Lists every entry of every prefix list, marking those managed by the monitor.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list targets.
+///
+/// # Returns
+///
+/// An error if a request fails, or `Ok(())` on success.
+pub async fn list(
+    monitor: &mut PrefixListMonitor,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+    for target in &monitor.targets {
+        let snapshot = target.snapshot().await?;
+        println!(
+            "{} ({}): version {}, {} entries",
+            target.prefix_list_id,
+            target.name,
+            snapshot.version,
+            snapshot.entries.len()
+        );
+        for entry in &snapshot.entries {
+            let description = entry.description().unwrap_or_default();
+            println!(
+                "  {} {:<20} {}",
+                if description == monitor.description {
+                    "*"
+                } else {
+                    " "
+                },
+                entry.cidr().unwrap_or_default(),
+                description
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the entries managed by the monitor (matching the description) from every prefix
+/// list, leaving the others alone.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor holding the prefix list targets.
+/// * `yes`: Whether to skip the confirmation prompt.
+///
+/// # Returns
+///
+/// An error if a request fails, or `Ok(())` on success or when the operator declines.
+pub async fn clean(
+    monitor: &mut PrefixListMonitor,
+    yes: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    monitor.resolve_targets().await?;
+
+    let mut plans = Vec::new();
+    for target in &monitor.targets {
+        let snapshot = target.snapshot().await?;
+        let cidrs = snapshot.managed_cidrs(&monitor.description);
+        if cidrs.is_empty() {
+            println!(
+                "{} ({}): no managed entries",
+                target.prefix_list_id, target.name
+            );
+            continue;
+        }
+        println!("{} ({}):", target.prefix_list_id, target.name);
+        for cidr in &cidrs {
+            println!("  - {:<20} {}", cidr, monitor.description);
+        }
+        plans.push((target, snapshot, cidrs));
+    }
+    if plans.is_empty() {
+        return Ok(());
+    }
+
+    if !yes
+        && !confirm(&format!(
+            "Remove the entries described \"{}\"?",
+            monitor.description
+        ))?
+    {
+        println!("Aborted");
+        return Ok(());
+    }
+
+    for (target, snapshot, cidrs) in plans {
+        target.modify_entries(Some(snapshot), &[], &cidrs).await?;
+        println!(
+            "Removed {} entries from prefix list {}",
+            cidrs.len(),
+            target.prefix_list_id
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_and_cidrs_are_normalized() {
+        let cidr = |value| parse_cidr(value).unwrap();
+        assert_eq!(cidr("203.0.113.7"), "203.0.113.7/32");
+        assert_eq!(cidr("203.0.113.0/24"), "203.0.113.0/24");
+        assert_eq!(cidr("2001:0db8::0001"), "2001:db8::1/128");
+        assert_eq!(cidr("2001:db8::/56"), "2001:db8::/56");
+
+        for value in [
+            "home",
+            "203.0.113.7/33",
+            "2001:db8::/129",
+            "203.0.113.7/",
+            "10.0.0/8",
+        ] {
+            assert!(parse_cidr(value).is_err(), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn hcl_strings_are_escaped() {
+        assert_eq!(hcl_string("Home"), r#""Home""#);
+        assert_eq!(hcl_string(r#"say "hi" \o/"#), r#""say \"hi\" \\o/""#);
+        assert_eq!(hcl_string("a\nb\tc\u{7}"), r#""a\nb\tc\u0007""#);
+        assert_eq!(hcl_string("café ☕"), r#""café ☕""#);
+    }
+
+    #[test]
+    fn hcl_templates_are_not_interpolated() {
+        assert_eq!(hcl_string("${var.secret}"), r#""$${var.secret}""#);
+        assert_eq!(
+            hcl_string("%{ if true }x%{ endif }"),
+            r#""%%{ if true }x%%{ endif }""#
+        );
+        // Only a following brace starts a template
+        assert_eq!(hcl_string("$5 and 100%"), r#""$5 and 100%""#);
+    }
+}
@@ -0,0 +1,379 @@
+use http_body_util::Full;
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{header, Method, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+/// The number of requests a monitor can fall behind on before the oldest are dropped.
+const REQUEST_CAPACITY: usize = 16;
+
+/// The number of history entries returned when the request sets no limit.
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+/// Delivers requests to every monitor.
+static REQUESTS: LazyLock<broadcast::Sender<Request>> =
+    LazyLock::new(|| broadcast::channel(REQUEST_CAPACITY).0);
+
+/// Whether updates are paused, so changes are detected and reported but not written.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether updates are paused.
+pub fn paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Pauses or resumes updates.
+///
+/// Resuming checks right away, so changes held back while paused are applied.
+///
+/// # Parameters
+///
+/// * `paused`: Whether to pause updates.
+/// * `by`: Who asked, for the log.
+pub fn set_paused(paused: bool, by: &str) {
+    if PAUSED.swap(paused, Ordering::Relaxed) == paused {
+        return;
+    }
+    if paused {
+        warn!(
+            "Updates paused by {}; changes are detected but not written",
+            by
+        );
+    } else {
+        info!("Updates resumed by {}", by);
+        send(Request::Check);
+    }
+}
+
+/// Something a monitor is asked to do between its regular checks.
+#[derive(Clone, Debug)]
+pub enum Request {
+    /// Check the IP right away.
+    Check,
+    /// Rewrite the prefix lists right away, even if the IP is unchanged.
+    Reconcile,
+    /// Apply an IP address or CIDR without detecting it.
+    SetIp(String),
+}
+
+/// Returns a receiver for the requests made from now on.
+///
+/// Requests made while the monitor is checking are queued, so the wait after that check
+/// ends right away.
+pub fn subscribe() -> broadcast::Receiver<Request> {
+    REQUESTS.subscribe()
+}
+
+/// Sends a request to every monitor.
+///
+/// # Parameters
+///
+/// * `request`: The request.
+pub fn send(request: Request) {
+    // Without monitors running there is nobody to ask
+    let _ = REQUESTS.send(request);
+}
+
+/// Triggers checks on `SIGUSR1` and full reconciles on `SIGUSR2`, and pauses updates on
+/// `SIGTSTP` and resumes them on `SIGCONT`, in the background.
+///
+/// # Returns
+///
+/// An error if the signal handlers cannot be installed, or `Ok(())` on success.
+#[cfg(unix)]
+pub fn handle_signals() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut check = signal(SignalKind::user_defined1())?;
+    let mut reconcile = signal(SignalKind::user_defined2())?;
+    // Catching SIGTSTP keeps the process running, so the signals only pause the writes
+    let mut pause = signal(SignalKind::from_raw(libc::SIGTSTP))?;
+    let mut resume = signal(SignalKind::from_raw(libc::SIGCONT))?;
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(()) = check.recv() => {
+                    info!("Check requested by SIGUSR1");
+                    send(Request::Check);
+                }
+                Some(()) = reconcile.recv() => {
+                    info!("Full reconcile requested by SIGUSR2");
+                    send(Request::Reconcile);
+                }
+                Some(()) = pause.recv() => set_paused(true, "SIGTSTP"),
+                Some(()) = resume.recv() => set_paused(false, "SIGCONT"),
+                else => break,
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Starts the control server in the background.
+///
+/// # Parameters
+///
+/// * `address`: The address and port to listen on.
+/// * `token`: The bearer token that authenticates control requests.
+///
+/// # Returns
+///
+/// An error if the address cannot be bound, or `Ok(())` once the server is listening.
+pub async fn serve(
+    address: SocketAddr,
+    token: String,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listener = TcpListener::bind(address)
+        .await
+        .map_err(|e| format!("Cannot listen on {}: {}", address, e))?;
+    info!("Control server listening on {}", address);
+
+    let token = Arc::new(token);
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Control server failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let token = token.clone();
+            tokio::spawn(async move {
+                let service = service_fn(|request| handle(request, peer, token.clone()));
+                if let Err(e) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!("Control connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+/// Answers a control request.
+///
+/// # Parameters
+///
+/// * `request`: The HTTP request.
+/// * `peer`: The address the request came from.
+/// * `token`: The bearer token that authenticates control requests.
+///
+/// # Returns
+///
+/// The response: `GET /health` and `GET /metrics` always succeed, `GET /status`,
+/// `GET /status/version`, `GET /history`, `POST /check`, `POST /pause` and `POST /resume`
+/// need the token; `POST /check` wakes the monitors.
+async fn handle(
+    request: hyper::Request<Incoming>,
+    peer: SocketAddr,
+    token: Arc<String>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/health") => respond(StatusCode::OK, "ok"),
+        (&Method::GET, "/metrics") => {
+            let mut response = Response::new(Full::new(Bytes::from(crate::metrics::render())));
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                header::HeaderValue::from_static("text/plain; version=0.0.4"),
+            );
+            response
+        }
+        (&Method::GET, "/status") => {
+            if authorized(&request, &token) {
+                respond_json(serde_json::to_string(&crate::status::current()))
+            } else {
+                respond(StatusCode::UNAUTHORIZED, "unauthorized")
+            }
+        }
+        (&Method::GET, "/status/version") => {
+            if authorized(&request, &token) {
+                respond_json(serde_json::to_string(&crate::status::version()))
+            } else {
+                respond(StatusCode::UNAUTHORIZED, "unauthorized")
+            }
+        }
+        (&Method::GET, "/history") => {
+            if !authorized(&request, &token) {
+                respond(StatusCode::UNAUTHORIZED, "unauthorized")
+            } else {
+                match history_limit(request.uri().query()) {
+                    Some(limit) => respond_json(serde_json::to_string(
+                        &serde_json::json!({ "entries": crate::status::history(limit) }),
+                    )),
+                    None => respond(
+                        StatusCode::BAD_REQUEST,
+                        "limit must be a positive whole number",
+                    ),
+                }
+            }
+        }
+        (&Method::POST, "/check") => {
+            if authorized(&request, &token) {
+                info!("Check requested by {}", peer);
+                send(Request::Check);
+                respond(StatusCode::ACCEPTED, "check triggered")
+            } else {
+                warn!("Rejected unauthenticated check request from {}", peer);
+                respond(StatusCode::UNAUTHORIZED, "unauthorized")
+            }
+        }
+        (&Method::POST, path @ ("/pause" | "/resume")) => {
+            if authorized(&request, &token) {
+                let pause = path == "/pause";
+                set_paused(pause, &peer.to_string());
+                respond(
+                    StatusCode::OK,
+                    if pause {
+                        "updates paused"
+                    } else {
+                        "updates resumed"
+                    },
+                )
+            } else {
+                warn!("Rejected unauthenticated {} request from {}", path, peer);
+                respond(StatusCode::UNAUTHORIZED, "unauthorized")
+            }
+        }
+        (
+            _,
+            "/health" | "/metrics" | "/status" | "/status/version" | "/history" | "/check"
+            | "/pause" | "/resume",
+        ) => respond(StatusCode::METHOD_NOT_ALLOWED, "method not allowed"),
+        _ => respond(StatusCode::NOT_FOUND, "not found"),
+    };
+
+    Ok(response)
+}
+
+/// Reads the number of history entries asked for.
+///
+/// # Parameters
+///
+/// * `query`: The query string of the request, if any.
+///
+/// # Returns
+///
+/// The `limit` parameter, the default when it is absent, or `None` if it is not a positive
+/// number.
+fn history_limit(query: Option<&str>) -> Option<usize> {
+    let limit = query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("limit="));
+    match limit {
+        Some(limit) => limit.parse().ok().filter(|limit| *limit > 0),
+        None => Some(DEFAULT_HISTORY_LIMIT),
+    }
+}
+
+/// Returns whether a request carries the control token as a bearer token.
+///
+/// # Parameters
+///
+/// * `request`: The HTTP request.
+/// * `token`: The expected token.
+///
+/// # Returns
+///
+/// Whether the `Authorization` header holds the token; compared in constant time.
+fn authorized(request: &hyper::Request<Incoming>, token: &str) -> bool {
+    let Some(given) = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Builds a plain text response.
+///
+/// # Parameters
+///
+/// * `status`: The status code.
+/// * `body`: The response text.
+///
+/// # Returns
+///
+/// The response.
+fn respond(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+    *response.status_mut() = status;
+    response
+}
+
+/// Builds a JSON response.
+///
+/// # Parameters
+///
+/// * `body`: The serialized body.
+///
+/// # Returns
+///
+/// The response, or an internal server error if serialization failed.
+fn respond_json(body: serde_json::Result<String>) -> Response<Full<Bytes>> {
+    let Ok(body) = body else {
+        return respond(StatusCode::INTERNAL_SERVER_ERROR, "internal server error");
+    };
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// Pauses or resumes the updates of the running daemon through its control server.
+///
+/// # Parameters
+///
+/// * `address`: The address of the control server.
+/// * `token`: The bearer token that authenticates control requests.
+/// * `pause`: Whether to pause updates rather than resume them.
+///
+/// # Returns
+///
+/// An error if the daemon cannot be reached or answers with an error, or `Ok(())` on success.
+pub async fn request_pause(
+    address: SocketAddr,
+    token: &str,
+    pause: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = if pause { "pause" } else { "resume" };
+    let response = reqwest::Client::new()
+        .post(format!("http://{}/{}", address, path))
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| {
+            format!(
+                "Cannot reach the daemon's control server at {}: {}",
+                address, e
+            )
+        })?
+        .error_for_status()?;
+    println!("{}", response.text().await?);
+
+    Ok(())
+}
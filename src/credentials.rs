@@ -0,0 +1,175 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// The default address of the EC2 instance metadata service.
+const IMDS_ENDPOINT: &str = "http://169.254.169.254";
+
+/// How long the instance metadata service is given to answer each probe.
+const IMDS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether the causes of a credential failure have been looked for.
+static EXPLAINED: AtomicBool = AtomicBool::new(false);
+
+/// What the instance metadata service answered.
+enum Imds {
+    /// Nothing answered, so this is not an EC2 instance or metadata is turned off.
+    Absent,
+    /// Plain requests are answered but the IMDSv2 token response is lost on the way.
+    TokenDropped,
+    /// The service works but the instance has no IAM role.
+    NoRole,
+    /// The service serves credentials for an instance role.
+    Available,
+}
+
+/// Returns whether the process runs in a container.
+fn in_container() -> bool {
+    Path::new("/.dockerenv").exists()
+        || Path::new("/run/.containerenv").exists()
+        || std::env::var_os("KUBERNETES_SERVICE_HOST").is_some()
+        || std::fs::read_to_string("/proc/1/cgroup").is_ok_and(|cgroup| {
+            ["docker", "kubepods", "containerd", "libpod"]
+                .iter()
+                .any(|runtime| cgroup.contains(runtime))
+        })
+}
+
+/// Returns whether an environment variable is set to a non-empty value.
+fn is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| !value.is_empty())
+}
+
+/// Asks the instance metadata service for a session token and the instance's role.
+///
+/// # Returns
+///
+/// What the service answered.
+async fn probe_imds() -> Imds {
+    let Ok(client) = reqwest::Client::builder()
+        .no_proxy()
+        .timeout(IMDS_TIMEOUT)
+        .build()
+    else {
+        return Imds::Absent;
+    };
+    let endpoint = std::env::var("AWS_EC2_METADATA_SERVICE_ENDPOINT")
+        .unwrap_or_else(|_| IMDS_ENDPOINT.to_string());
+    let endpoint = endpoint.trim_end_matches('/');
+
+    let token = client
+        .put(format!("{}/latest/api/token", endpoint))
+        .header("X-aws-ec2-metadata-token-ttl-seconds", "60")
+        .send()
+        .await;
+    let token = match token {
+        Ok(response) if response.status().is_success() => response.text().await.unwrap_or_default(),
+        Ok(_) => return Imds::Absent,
+        // Only the token response is limited in hops, so a reply to a plain request shows
+        // the service is there and the token was dropped on the way back
+        Err(_) => {
+            return match client
+                .get(format!("{}/latest/meta-data/", endpoint))
+                .send()
+                .await
+            {
+                Ok(_) => Imds::TokenDropped,
+                Err(_) => Imds::Absent,
+            };
+        }
+    };
+
+    let roles = client
+        .get(format!(
+            "{}/latest/meta-data/iam/security-credentials/",
+            endpoint
+        ))
+        .header("X-aws-ec2-metadata-token", token)
+        .send()
+        .await;
+    match roles {
+        Ok(response) if response.status().is_success() => match response.text().await {
+            Ok(roles) if !roles.trim().is_empty() => Imds::Available,
+            _ => Imds::NoRole,
+        },
+        Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => Imds::NoRole,
+        _ => Imds::Absent,
+    }
+}
+
+/// Looks for the common reasons a container or instance finds no AWS credentials.
+///
+/// # Returns
+///
+/// A description of each problem found, with how to fix it.
+pub async fn diagnose() -> Vec<String> {
+    let mut findings = Vec::new();
+    let container = in_container();
+    let static_keys = is_set("AWS_ACCESS_KEY_ID");
+
+    if (is_set("ECS_CONTAINER_METADATA_URI_V4") || is_set("ECS_CONTAINER_METADATA_URI"))
+        && !is_set("AWS_CONTAINER_CREDENTIALS_RELATIVE_URI")
+        && !is_set("AWS_CONTAINER_CREDENTIALS_FULL_URI")
+        && !static_keys
+    {
+        findings.push(
+            "this ECS task has no task role (AWS_CONTAINER_CREDENTIALS_RELATIVE_URI is not \
+             set); set taskRoleArn in the task definition, as the execution role is only used \
+             by ECS itself"
+                .to_string(),
+        );
+    }
+    if is_set("KUBERNETES_SERVICE_HOST")
+        && !is_set("AWS_WEB_IDENTITY_TOKEN_FILE")
+        && !is_set("AWS_CONTAINER_CREDENTIALS_FULL_URI")
+        && !static_keys
+    {
+        findings.push(
+            "this pod has no IAM role of its own; annotate its service account with \
+             eks.amazonaws.com/role-arn (IRSA) or create an EKS Pod Identity association"
+                .to_string(),
+        );
+    }
+
+    if std::env::var("AWS_EC2_METADATA_DISABLED").is_ok_and(|v| v.eq_ignore_ascii_case("true")) {
+        findings.push(
+            "instance metadata is turned off by AWS_EC2_METADATA_DISABLED, so an instance \
+             role cannot be used"
+                .to_string(),
+        );
+        return findings;
+    }
+    match probe_imds().await {
+        Imds::TokenDropped => findings.push(format!(
+            "the instance metadata service answers but its IMDSv2 token never arrives{}; the \
+             instance's metadata hop limit is 1, which a container network adds a hop to. Raise \
+             it with `aws ec2 modify-instance-metadata-options --instance-id <id> \
+             --http-put-response-hop-limit 2`, or run the container with host networking",
+            if container { " in this container" } else { "" }
+        )),
+        Imds::NoRole => findings.push(
+            "this EC2 instance has no IAM role; attach an instance profile with the \
+             permissions listed under IAM Permissions in the README"
+                .to_string(),
+        ),
+        Imds::Absent | Imds::Available => {}
+    }
+
+    findings
+}
+
+/// Logs the likely causes of a credential failure, the first time one happens.
+pub async fn explain_once() {
+    if EXPLAINED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let findings = diagnose().await;
+    if findings.is_empty() {
+        info!("No container role or instance metadata problem found for the credential failure");
+    }
+    for finding in findings {
+        warn!("Credential troubleshooting: {}", finding);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::cloudflare::CloudflareRecord;
+use crate::dyndns::DynDnsRecord;
+use crate::endpoint_policy::EndpointPolicy;
+use crate::guardduty::TrustedIpList;
+use crate::iam_policy::SourceIpStatement;
+use crate::network_firewall::RuleGroupIpSet;
+use crate::opensearch::DomainAccessPolicy;
+use crate::security_group::SecurityGroups;
+use aws_config::SdkConfig;
+use std::fmt;
+
+/// A resource other than a prefix list that holds the current CIDRs.
+enum Resource {
+    /// Ingress rules in security groups.
+    SecurityGroups(SecurityGroups),
+    /// A GuardDuty trusted IP list.
+    TrustedIpList(TrustedIpList),
+    /// The `aws:SourceIp` condition of an IAM policy statement.
+    IamPolicy(SourceIpStatement),
+    /// The address condition of a VPC endpoint policy statement.
+    EndpointPolicy(EndpointPolicy),
+    /// An IP set variable of a Network Firewall rule group.
+    FirewallIpSet(RuleGroupIpSet),
+    /// The `aws:SourceIp` condition of an OpenSearch domain access policy.
+    OpenSearchPolicy(DomainAccessPolicy),
+    /// A DNS record in a Cloudflare zone.
+    CloudflareRecord(CloudflareRecord),
+    /// A name at a DuckDNS, No-IP or FreeDNS account.
+    DynDnsRecord(DynDnsRecord),
+}
+
+/// A resource updated alongside the prefix lists, with what was last applied to it.
+///
+/// Like a prefix list target, a destination that was updated is not touched again until the
+/// CIDRs change, so a failed update only retries the destinations that failed.
+pub struct Destination {
+    /// The resource holding the CIDRs.
+    resource: Resource,
+    /// The CIDRs last applied to the resource.
+    pub applied_cidrs: Option<Vec<String>>,
+}
+
+impl Destination {
+    /// Builds every destination configured in the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct service clients.
+    /// * `args`: The arguments passed to the program.
+    /// * `description`: The description identifying the managed security group rules.
+    ///
+    /// # Returns
+    ///
+    /// The destinations, in the order they are updated.
+    pub fn from_args(config: &SdkConfig, args: &crate::Args, description: &str) -> Vec<Self> {
+        let resources = [
+            SecurityGroups::from_args(config, args, description).map(Resource::SecurityGroups),
+            TrustedIpList::from_args(config, args).map(Resource::TrustedIpList),
+            SourceIpStatement::from_args(config, args).map(Resource::IamPolicy),
+            EndpointPolicy::from_args(config, args).map(Resource::EndpointPolicy),
+            RuleGroupIpSet::from_args(config, args).map(Resource::FirewallIpSet),
+            DomainAccessPolicy::from_args(config, args).map(Resource::OpenSearchPolicy),
+            CloudflareRecord::from_args(args).map(Resource::CloudflareRecord),
+        ];
+
+        resources
+            .into_iter()
+            .flatten()
+            .chain(
+                DynDnsRecord::from_args(args)
+                    .into_iter()
+                    .map(Resource::DynDnsRecord),
+            )
+            .map(|resource| Self {
+                resource,
+                applied_cidrs: None,
+            })
+            .collect()
+    }
+
+    /// Looks up the resources again when they are selected dynamically.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the set of resources changed, so the CIDRs must be applied again, or an
+    /// error if the lookup fails.
+    pub async fn resolve(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let changed = match &mut self.resource {
+            Resource::SecurityGroups(groups) => groups.resolve().await?,
+            _ => false,
+        };
+        if changed {
+            self.applied_cidrs = None;
+        }
+        Ok(changed)
+    }
+
+    /// Applies the CIDRs to the resource.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidrs`: The CIDRs the resource should hold.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the resource was modified, `Ok(false)` if it was already in sync, or an
+    /// error if the update fails.
+    pub async fn apply(
+        &self,
+        cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        match &self.resource {
+            Resource::SecurityGroups(groups) => groups.apply(cidrs).await,
+            // The list is rewritten on every change, which is not counted as an update
+            Resource::TrustedIpList(list) => list.apply(cidrs).await.map(|()| false),
+            Resource::IamPolicy(statement) => statement.apply(cidrs).await,
+            Resource::EndpointPolicy(policy) => policy.apply(cidrs).await,
+            Resource::FirewallIpSet(ip_set) => ip_set.apply(cidrs).await,
+            Resource::OpenSearchPolicy(policy) => policy.apply(cidrs).await,
+            Resource::CloudflareRecord(record) => record.apply(cidrs).await,
+            Resource::DynDnsRecord(record) => record.apply(cidrs).await,
+        }
+    }
+}
+
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.resource {
+            Resource::SecurityGroups(_) => write!(f, "security groups"),
+            Resource::TrustedIpList(list) => write!(f, "GuardDuty IP set {}", list.ip_set_id),
+            Resource::IamPolicy(statement) => write!(f, "IAM policy {}", statement.policy_arn),
+            Resource::EndpointPolicy(policy) => {
+                write!(f, "VPC endpoint {}", policy.endpoint_id)
+            }
+            Resource::FirewallIpSet(ip_set) => {
+                write!(f, "rule group {}", ip_set.rule_group_arn)
+            }
+            Resource::OpenSearchPolicy(policy) => {
+                write!(f, "OpenSearch domain {}", policy.domain_name)
+            }
+            Resource::CloudflareRecord(record) => write!(f, "Cloudflare record {}", record.name),
+            Resource::DynDnsRecord(record) => write!(f, "{}", record),
+        }
+    }
+}
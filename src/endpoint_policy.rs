@@ -0,0 +1,93 @@
+use aws_config::SdkConfig;
+use tracing::info;
+
+/// The address condition of one named statement in a VPC endpoint policy.
+pub struct EndpointPolicy {
+    /// The client instance used to read and modify the endpoint.
+    client: aws_sdk_ec2::Client,
+    /// The ID of the VPC endpoint.
+    pub endpoint_id: String,
+    /// The `Sid` of the statement holding the condition.
+    sid: String,
+    /// The condition key, `aws:SourceIp` or `aws:VpcSourceIp`.
+    condition_key: String,
+}
+
+impl EndpointPolicy {
+    /// Builds the endpoint policy from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct the EC2 client.
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// The endpoint policy, or `None` when no endpoint is configured.
+    pub fn from_args(config: &SdkConfig, args: &crate::Args) -> Option<Self> {
+        Some(Self {
+            client: crate::replay::ec2_client(config.into()),
+            endpoint_id: args.vpc_endpoint_id.clone()?,
+            sid: args.vpc_endpoint_statement.clone()?,
+            condition_key: args.vpc_endpoint_condition_key.clone(),
+        })
+    }
+
+    /// Sets the statement's address condition to the CIDRs.
+    ///
+    /// The policy is read, modified and written back whole, as VPC endpoints offer no way to
+    /// change part of a policy.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidrs`: The CIDRs the condition should allow.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the policy was modified, `Ok(false)` if it was already in sync, or an
+    /// error if the statement cannot be changed safely or a request fails.
+    pub async fn apply(
+        &self,
+        cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self
+            .client
+            .describe_vpc_endpoints()
+            .vpc_endpoint_ids(&self.endpoint_id)
+            .send()
+            .await?;
+        let document = response
+            .vpc_endpoints()
+            .first()
+            .ok_or_else(|| format!("VPC endpoint {} not found", self.endpoint_id))?
+            .policy_document()
+            .ok_or_else(|| format!("VPC endpoint {} has no policy", self.endpoint_id))?;
+
+        let Some(change) = crate::policy::replace_condition(
+            document,
+            Some(&self.sid),
+            &self.condition_key,
+            cidrs,
+        )?
+        else {
+            return Ok(false);
+        };
+
+        self.client
+            .modify_vpc_endpoint()
+            .vpc_endpoint_id(&self.endpoint_id)
+            .policy_document(change.document)
+            .send()
+            .await?;
+        info!(
+            "Updated VPC endpoint {} policy statement {}: {} {} -> {}",
+            self.endpoint_id,
+            self.sid,
+            self.condition_key,
+            change.previous.join(", "),
+            cidrs.join(", ")
+        );
+
+        Ok(true)
+    }
+}
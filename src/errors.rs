@@ -0,0 +1,153 @@
+use aws_sdk_ec2::operation::RequestId;
+use aws_smithy_types::error::ErrorMetadata;
+use std::error::Error;
+
+/// The IAM actions the monitor needs on the prefix list for routine updates.
+const REQUIRED_ACTIONS: &str =
+    "ec2:DescribeManagedPrefixLists, ec2:GetManagedPrefixListEntries and ec2:ModifyManagedPrefixList";
+
+/// Formats an error and its sources as one line.
+///
+/// # Parameters
+///
+/// * `error`: The error to format.
+///
+/// # Returns
+///
+/// The messages of the error and its sources, separated by colons.
+fn chain(error: &(dyn Error + 'static)) -> String {
+    let mut messages: Vec<String> = Vec::new();
+    let mut current = Some(error);
+    while let Some(error) = current {
+        let message = error.to_string();
+        if messages.last() != Some(&message) {
+            messages.push(message);
+        }
+        current = error.source();
+    }
+
+    messages.join(": ")
+}
+
+/// Finds the AWS error code and message among an error's sources.
+///
+/// # Parameters
+///
+/// * `error`: The error to search.
+///
+/// # Returns
+///
+/// The error metadata returned by AWS, if the error came from a service response.
+fn metadata<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a ErrorMetadata> {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if let Some(metadata) = error.downcast_ref::<ErrorMetadata>() {
+            return Some(metadata);
+        }
+        current = error.source();
+    }
+
+    None
+}
+
+/// Suggests how to fix a failure identified by its AWS error code.
+///
+/// # Parameters
+///
+/// * `code`: The AWS error code.
+///
+/// # Returns
+///
+/// The suggestion, or `None` for codes without one.
+fn hint(code: &str) -> Option<String> {
+    let hint = match code {
+        "UnauthorizedOperation" | "AccessDenied" | "AccessDeniedException" => format!(
+            "the credentials lack permission; grant {} on the prefix list, as listed under IAM \
+             Permissions in the README",
+            REQUIRED_ACTIONS
+        ),
+        "ExpiredToken" | "ExpiredTokenException" | "TokenRefreshRequired" => {
+            "the AWS credentials have expired; re-authenticate (e.g. aws sso login or a fresh \
+             AWS_SESSION_TOKEN) or check the instance or container role"
+                .to_string()
+        }
+        "InvalidClientTokenId"
+        | "AuthFailure"
+        | "UnrecognizedClientException"
+        | "SignatureDoesNotMatch" => "the AWS credentials are not valid; check \
+             AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY or the profile in use, and that they \
+             belong to the region's partition, as GovCloud and China accounts have their own"
+            .to_string(),
+        "Throttling" | "ThrottlingException" | "RequestLimitExceeded" => {
+            "AWS is throttling requests; raise --interval, or back off more with \
+             AWS_MAX_ATTEMPTS and AWS_RETRY_MODE=adaptive"
+                .to_string()
+        }
+        "InvalidPrefixListID.NotFound"
+        | "InvalidPrefixListId.NotFound"
+        | "InvalidPrefixListID.Malformed" => {
+            "check the prefix list ID and that --region is the prefix list's region".to_string()
+        }
+        _ => return None,
+    };
+
+    Some(hint)
+}
+
+/// Describes an error for an operator, translating common AWS failures into actionable
+/// messages.
+///
+/// # Parameters
+///
+/// * `error`: The error to describe.
+///
+/// # Returns
+///
+/// The AWS error code, message and request ID with a suggested fix when the error came from
+/// AWS, otherwise the messages of the error and its sources.
+pub fn describe(error: &(dyn Error + 'static)) -> String {
+    if let Some(metadata) = metadata(error) {
+        let code = metadata.code().unwrap_or("UnknownError");
+        let mut message = match metadata.message() {
+            Some(message) => format!("{}: {}", code, message),
+            None => code.to_string(),
+        };
+        // Support cases are resolved much faster with the request ID
+        if let Some(request_id) = metadata.request_id() {
+            message = format!("{} [request ID {}]", message, request_id);
+        }
+        let hint = if crate::clock::is_skew_error(code, metadata.message()) {
+            Some(crate::clock::hint())
+        } else {
+            hint(code)
+        };
+        return match hint {
+            Some(hint) => format!("{} ({})", message, hint),
+            None => message,
+        };
+    }
+
+    let message = chain(error);
+    if message.contains("no providers in chain provided credentials") {
+        return format!(
+            "{} (no AWS credentials were found; set AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY, \
+             AWS_PROFILE or --role-arn, or run with an instance or container role)",
+            message
+        );
+    }
+
+    message
+}
+
+/// Identifies a failure to find or load AWS credentials, as opposed to AWS rejecting them.
+///
+/// # Parameters
+///
+/// * `error`: The error to check.
+///
+/// # Returns
+///
+/// Whether the error came from the credential providers rather than an AWS response.
+pub fn is_credentials_failure(error: &(dyn Error + 'static)) -> bool {
+    metadata(error).is_none() && chain(error).to_lowercase().contains("credential")
+}
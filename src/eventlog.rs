@@ -0,0 +1,94 @@
+#[cfg(windows)]
+use std::sync::OnceLock;
+
+/// The event source the monitor reports under, registered with `New-EventLog`.
+#[cfg(windows)]
+const SOURCE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// The handle of the registered event source, once opened.
+#[cfg(windows)]
+static SOURCE: OnceLock<usize> = OnceLock::new();
+
+/// The significant events written to the Windows Event Log, with their event IDs.
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    /// A monitor started.
+    Started = 1000,
+    /// A prefix list was updated to new addresses.
+    Changed = 1001,
+    /// Checks kept failing.
+    Failing = 1002,
+    /// Checks succeeded again after failing.
+    Recovered = 1003,
+}
+
+/// Opens the event source, so significant events are written to the Windows Event Log.
+///
+/// # Returns
+///
+/// An error if the source cannot be opened or the platform has no Event Log, or `Ok(())`
+/// on success.
+pub fn open() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::EventLog::RegisterEventSourceW;
+
+        let name: Vec<u16> = SOURCE_NAME.encode_utf16().chain([0]).collect();
+        // SAFETY: the source name is a NUL-terminated UTF-16 string that outlives the call
+        let handle = unsafe { RegisterEventSourceW(std::ptr::null(), name.as_ptr()) };
+        if handle.is_null() {
+            return Err(format!(
+                "Cannot open event source {}: {}",
+                SOURCE_NAME,
+                std::io::Error::last_os_error()
+            )
+            .into());
+        }
+        let _ = SOURCE.set(handle as usize);
+        Ok(())
+    }
+    #[cfg(not(windows))]
+    Err("--event-log is only supported on Windows".into())
+}
+
+/// Writes an event to the Windows Event Log, if the event source is open.
+///
+/// # Parameters
+///
+/// * `event`: The event, which determines the event ID and type.
+/// * `message`: The event text.
+pub fn report(event: Event, message: &str) {
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::System::EventLog::{
+            ReportEventW, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE,
+        };
+
+        let Some(&handle) = SOURCE.get() else {
+            return;
+        };
+        let kind = match event {
+            Event::Failing => EVENTLOG_ERROR_TYPE,
+            Event::Started | Event::Changed | Event::Recovered => EVENTLOG_INFORMATION_TYPE,
+        };
+        let text: Vec<u16> = message.encode_utf16().chain([0]).collect();
+        let strings = [text.as_ptr()];
+        // SAFETY: the handle came from RegisterEventSourceW and the one string is
+        // NUL-terminated and outlives the call; failures have nowhere to be reported
+        unsafe {
+            ReportEventW(
+                handle as _,
+                kind,
+                0,
+                event as u32,
+                std::ptr::null_mut(),
+                1,
+                0,
+                strings.as_ptr(),
+                std::ptr::null(),
+            );
+        }
+    }
+    #[cfg(not(windows))]
+    let _ = (event, message);
+}
@@ -0,0 +1,44 @@
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Whether events are written to stdout, set once at startup.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Starts writing events to stdout.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Writes a lifecycle event to stdout as one JSON line, when the event stream is enabled.
+///
+/// # Parameters
+///
+/// * `event`: The name of the event, e.g. `check_started`.
+/// * `monitor`: The name of the monitor the event is about.
+/// * `time`: When the event happened, by the monitor's clock.
+/// * `fields`: The details of the event, merged into the line when it is an object.
+pub fn emit(event: &str, monitor: &str, time: SystemTime, fields: Value) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let timestamp = time
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default();
+    let mut line = json!({
+        "timestamp_ms": timestamp,
+        "event": event,
+        "monitor": monitor,
+    });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+
+    // Lines from concurrent monitors must not interleave, and consumers read them as they come
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
@@ -0,0 +1,118 @@
+use aws_config::SdkConfig;
+use aws_sdk_s3::primitives::ByteStream;
+use tracing::info;
+
+/// A GuardDuty trusted IP list regenerated with the current CIDRs.
+///
+/// GuardDuty reads a trusted IP list from S3 only when the list is updated, so the monitor
+/// rewrites the object and then updates the list to make GuardDuty reload it.
+pub struct TrustedIpList {
+    /// The client instance used to update the IP set.
+    guardduty: aws_sdk_guardduty::Client,
+    /// The client instance used to write the list.
+    s3: aws_sdk_s3::Client,
+    /// The GuardDuty detector, or `None` to use the region's only detector.
+    detector_id: Option<String>,
+    /// The ID of the trusted IP set.
+    pub ip_set_id: String,
+    /// The bucket the list is written to.
+    bucket: String,
+    /// The object key of the list; `{host}` is substituted.
+    key: String,
+}
+
+impl TrustedIpList {
+    /// Builds the trusted IP list from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct the clients.
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// The trusted IP list, or `None` when no IP set is configured.
+    pub fn from_args(config: &SdkConfig, args: &crate::Args) -> Option<Self> {
+        let ip_set_id = args.guardduty_ip_set_id.clone()?;
+        let host = gethostname::gethostname().to_string_lossy().into_owned();
+
+        Some(Self {
+            guardduty: aws_sdk_guardduty::Client::new(config),
+            s3: aws_sdk_s3::Client::new(config),
+            detector_id: args.guardduty_detector_id.clone(),
+            ip_set_id,
+            bucket: args.guardduty_s3_bucket.clone().unwrap_or_default(),
+            key: args.guardduty_s3_key.replace("{host}", &host),
+        })
+    }
+
+    /// Returns the GuardDuty detector that owns the IP set.
+    ///
+    /// # Returns
+    ///
+    /// The configured detector, or the region's only detector, or an error if the region has
+    /// none or the request fails.
+    async fn detector_id(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(detector_id) = &self.detector_id {
+            return Ok(detector_id.clone());
+        }
+
+        // GuardDuty allows at most one detector per account and region
+        let response = self.guardduty.list_detectors().send().await?;
+        response
+            .detector_ids()
+            .first()
+            .cloned()
+            .ok_or_else(|| "GuardDuty is not enabled in this region; no detector found".into())
+    }
+
+    /// Writes the CIDRs to the list's S3 object and makes GuardDuty reload and activate it.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidrs`: The CIDRs the trusted IP list should consist of.
+    ///
+    /// # Returns
+    ///
+    /// An error if the object cannot be written or the IP set cannot be updated, or `Ok(())`
+    /// on success.
+    pub async fn apply(
+        &self,
+        cidrs: &[String],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let detector_id = self.detector_id().await?;
+
+        // The plaintext format lists one address or CIDR per line
+        let body = cidrs
+            .iter()
+            .map(|cidr| format!("{}\n", cidr))
+            .collect::<String>();
+        self.s3
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&self.key)
+            .content_type("text/plain")
+            .body(ByteStream::from(body.into_bytes()))
+            .send()
+            .await?;
+
+        self.guardduty
+            .update_ip_set()
+            .detector_id(&detector_id)
+            .ip_set_id(&self.ip_set_id)
+            .location(format!(
+                "https://s3.amazonaws.com/{}/{}",
+                self.bucket, self.key
+            ))
+            .activate(true)
+            .send()
+            .await?;
+        info!(
+            "Updated GuardDuty trusted IP list {} with {}",
+            self.ip_set_id,
+            cidrs.join(", ")
+        );
+
+        Ok(())
+    }
+}
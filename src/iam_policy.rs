@@ -0,0 +1,165 @@
+use aws_config::SdkConfig;
+use tracing::{info, warn};
+
+/// The number of versions IAM keeps of a managed policy.
+const MAX_POLICY_VERSIONS: usize = 5;
+
+/// The `aws:SourceIp` condition of one named statement in a customer managed IAM policy.
+///
+/// Restricting a break-glass role to the office address is only useful if the policy cannot
+/// be damaged, so only the named statement's condition is ever changed, and changes are
+/// previewed without being written unless applying is explicitly enabled.
+pub struct SourceIpStatement {
+    /// The client instance used to read and write the policy.
+    client: aws_sdk_iam::Client,
+    /// The ARN of the managed policy.
+    pub policy_arn: String,
+    /// The `Sid` of the statement holding the condition.
+    sid: String,
+    /// Whether to write the policy rather than only preview the change.
+    apply: bool,
+}
+
+impl SourceIpStatement {
+    /// Builds the policy statement from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct the IAM client.
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// The policy statement, or `None` when no policy is configured.
+    pub fn from_args(config: &SdkConfig, args: &crate::Args) -> Option<Self> {
+        Some(Self {
+            client: aws_sdk_iam::Client::new(config),
+            policy_arn: args.iam_policy_arn.clone()?,
+            sid: args.iam_policy_statement.clone()?,
+            apply: args.iam_policy_apply,
+        })
+    }
+
+    /// Reads the default version of the policy.
+    ///
+    /// # Returns
+    ///
+    /// The versions of the policy and the decoded default document, or an error if a request
+    /// fails.
+    async fn read(
+        &self,
+    ) -> Result<
+        (Vec<aws_sdk_iam::types::PolicyVersion>, String),
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let versions = self
+            .client
+            .list_policy_versions()
+            .policy_arn(&self.policy_arn)
+            .send()
+            .await?
+            .versions()
+            .to_vec();
+        let default_version = versions
+            .iter()
+            .find(|version| version.is_default_version())
+            .and_then(|version| version.version_id())
+            .ok_or_else(|| format!("Policy {} has no default version", self.policy_arn))?;
+
+        let response = self
+            .client
+            .get_policy_version()
+            .policy_arn(&self.policy_arn)
+            .version_id(default_version)
+            .send()
+            .await?;
+        // IAM returns the document URL-encoded
+        let document = response
+            .policy_version()
+            .and_then(|version| version.document())
+            .ok_or_else(|| format!("Policy {} has no document", self.policy_arn))?;
+        let document = percent_encoding::percent_decode_str(document)
+            .decode_utf8()?
+            .into_owned();
+
+        Ok((versions, document))
+    }
+
+    /// Sets the statement's `aws:SourceIp` condition to the CIDRs, or previews doing so.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidrs`: The CIDRs the condition should allow.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the policy was modified, `Ok(false)` if it was already in sync or the
+    /// change was only previewed, or an error if the statement cannot be changed safely or a
+    /// request fails.
+    pub async fn apply(
+        &self,
+        cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let (versions, document) = self.read().await?;
+        let Some(change) =
+            crate::policy::replace_condition(&document, Some(&self.sid), "aws:SourceIp", cidrs)?
+        else {
+            return Ok(false);
+        };
+
+        info!(
+            "IAM policy {} statement {}: aws:SourceIp {} -> {}",
+            self.policy_arn,
+            self.sid,
+            change.previous.join(", "),
+            cidrs.join(", ")
+        );
+        if !self.apply {
+            warn!(
+                "Dry run: IAM policy {} not modified; pass --iam-policy-apply to write it",
+                self.policy_arn
+            );
+            return Ok(false);
+        }
+
+        // A full version history is pruned of its oldest non-default version
+        if versions.len() >= MAX_POLICY_VERSIONS {
+            if let Some(oldest) = versions
+                .iter()
+                .filter(|version| !version.is_default_version())
+                .min_by_key(|version| version.create_date().map(|date| date.secs()))
+                .and_then(|version| version.version_id())
+            {
+                self.client
+                    .delete_policy_version()
+                    .policy_arn(&self.policy_arn)
+                    .version_id(oldest)
+                    .send()
+                    .await?;
+                info!(
+                    "Deleted version {} of IAM policy {}",
+                    oldest, self.policy_arn
+                );
+            }
+        }
+
+        let response = self
+            .client
+            .create_policy_version()
+            .policy_arn(&self.policy_arn)
+            .policy_document(change.document)
+            .set_as_default(true)
+            .send()
+            .await?;
+        info!(
+            "Updated IAM policy {} to version {}",
+            self.policy_arn,
+            response
+                .policy_version()
+                .and_then(|version| version.version_id())
+                .unwrap_or_default()
+        );
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,494 @@
+use crate::commands::confirm;
+use aws_config::SdkConfig;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+/// The region suggested when neither the command line nor the environment names one.
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// The name of the systemd unit written next to the environment file.
+const UNIT_NAME: &str = "aws-vpc-prefix-list-monitor.service";
+
+/// Asks a question on the terminal, offering a default answer.
+///
+/// # Parameters
+///
+/// * `question`: The question to ask.
+/// * `default`: The answer used when the operator just presses Enter, shown if not empty.
+///
+/// # Returns
+///
+/// The answer, or an error if stdin cannot be read.
+fn ask(question: &str, default: &str) -> io::Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "init needs answers on stdin",
+        ));
+    }
+
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Quotes a value for an environment file, so spaces and special characters survive.
+///
+/// # Parameters
+///
+/// * `value`: The value to quote.
+///
+/// # Returns
+///
+/// The value in single quotes, or in double quotes with escapes when it contains one.
+fn quote(value: &str) -> String {
+    if !value.contains('\'') {
+        return format!("'{}'", value);
+    }
+
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('$', "\\$")
+    )
+}
+
+/// Lists the region's customer-managed prefix lists and lets the operator pick one.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration used to list the prefix lists.
+///
+/// # Returns
+///
+/// The chosen prefix list ID, or an error if stdin cannot be read.
+async fn choose_prefix_list(
+    config: &SdkConfig,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let client = crate::replay::ec2_client(config.into());
+    let lists: Vec<_> = match client
+        .describe_managed_prefix_lists()
+        .into_paginator()
+        .items()
+        .send()
+        .collect::<Result<Vec<_>, _>>()
+        .await
+    {
+        // AWS-managed lists, such as those of S3 or CloudFront, cannot be modified
+        Ok(lists) => lists
+            .into_iter()
+            .filter(|list| list.owner_id() != Some("AWS"))
+            .collect(),
+        Err(e) => {
+            let e: Box<dyn std::error::Error + Send + Sync> = e.into();
+            println!("Cannot list prefix lists: {}", crate::errors::describe(&*e));
+            Vec::new()
+        }
+    };
+
+    if lists.is_empty() {
+        println!(
+            "No prefix lists to choose from; create one with `aws ec2 create-managed-prefix-list \
+             --prefix-list-name home --address-family IPv4 --max-entries 10` or enter its ID"
+        );
+    }
+    for (number, list) in lists.iter().enumerate() {
+        let prefix_list_id = list.prefix_list_id().unwrap_or_default();
+        // Counting costs a call per list, worth it only for a handful listed to a person
+        let entries = match client
+            .get_managed_prefix_list_entries()
+            .prefix_list_id(prefix_list_id)
+            .into_paginator()
+            .items()
+            .send()
+            .collect::<Result<Vec<_>, _>>()
+            .await
+        {
+            Ok(entries) => entries.len().to_string(),
+            Err(_) => "?".to_string(),
+        };
+        println!(
+            "  {}) {} {} ({}, {} of {} entries, owner {})",
+            number + 1,
+            prefix_list_id,
+            list.prefix_list_name().unwrap_or_default(),
+            list.address_family().unwrap_or_default(),
+            entries,
+            list.max_entries().unwrap_or_default(),
+            list.owner_id().unwrap_or("unknown")
+        );
+    }
+
+    loop {
+        let answer = ask("Prefix list (number or ID)", "")?;
+        if let Some(list) = answer
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| lists.get(number.checked_sub(1)?))
+        {
+            return Ok(list.prefix_list_id().unwrap_or_default().to_string());
+        }
+        match crate::target::parse_prefix_list_id(&answer) {
+            Ok(prefix_list_id) => return Ok(prefix_list_id),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+/// Sets a variable in an environment file, replacing its earlier value or adding it at the end.
+///
+/// # Parameters
+///
+/// * `path`: The environment file, created readable only by its owner if missing.
+/// * `name`: The name of the variable.
+/// * `value`: The value of the variable.
+///
+/// # Returns
+///
+/// An error if the file cannot be read or written.
+fn save_setting(
+    path: &Path,
+    name: &str,
+    value: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Cannot read {}: {}", path.display(), e).into()),
+    };
+
+    let setting = format!("{}={}", name, quote(value));
+    let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+    let existing = lines.iter().position(|line| {
+        let line = line.trim_start();
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        line.split_once('=')
+            .is_some_and(|(key, _)| key.trim() == name)
+    });
+    match existing {
+        Some(index) => lines[index] = setting,
+        None => lines.push(setting),
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all((lines.join("\n") + "\n").as_bytes()))
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+/// Writes a file readable only by its owner, asking before replacing an existing one.
+///
+/// # Parameters
+///
+/// * `path`: The file to write.
+/// * `contents`: The contents of the file.
+///
+/// # Returns
+///
+/// `Ok(true)` if the file was written, `Ok(false)` if the operator kept the existing file, or
+/// an error if it cannot be written.
+fn write_file(
+    path: &Path,
+    contents: &str,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    if path.exists() && !confirm(&format!("{} exists. Replace it?", path.display()))? {
+        return Ok(false);
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        // Webhook URLs carry their own credentials
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+
+    Ok(true)
+}
+
+/// Lets the operator pick the prefix list when none is configured, and optionally saves the
+/// choice to the environment file so later runs use it.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration used to list the prefix lists.
+/// * `env_file`: The environment file to save the choice to; `.env` if not set.
+///
+/// # Returns
+///
+/// The chosen prefix list ID, or an error if stdin cannot be read or the file cannot be
+/// written.
+pub async fn pick_prefix_list(
+    config: &SdkConfig,
+    env_file: Option<&Path>,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    println!("No prefix list is configured; choose the one to update.");
+    let prefix_list_id = choose_prefix_list(config).await?;
+
+    let path = env_file.unwrap_or(Path::new(".env"));
+    if confirm(&format!(
+        "Save PREFIX_LIST_ID={} to {}?",
+        prefix_list_id,
+        path.display()
+    ))? {
+        save_setting(path, "PREFIX_LIST_ID", &prefix_list_id)?;
+        println!("Saved to {}", path.display());
+    }
+
+    Ok(prefix_list_id)
+}
+
+/// Renders the answers of the setup wizard as an environment file.
+///
+/// # Parameters
+///
+/// * `region`: The AWS region.
+/// * `prefix_list_id`: The chosen prefix list ID.
+/// * `description`: The entry description.
+/// * `interval`: The check interval in seconds.
+/// * `profile`: The AWS profile in effect, if any.
+/// * `webhooks`: The comma-separated webhook URLs, or an empty string for none.
+///
+/// # Returns
+///
+/// The file contents, one setting per line.
+fn render_settings(
+    region: &str,
+    prefix_list_id: &str,
+    description: &str,
+    interval: u64,
+    profile: Option<&str>,
+    webhooks: &str,
+) -> String {
+    let mut settings = vec![
+        format!("AWS_REGION={}", quote(region)),
+        format!("PREFIX_LIST_ID={}", quote(prefix_list_id)),
+        format!("ENTRY_DESCRIPTION={}", quote(description)),
+        format!("CHECK_INTERVAL={}", interval),
+    ];
+    if let Some(profile) = profile {
+        settings.push(format!("AWS_PROFILE={}", quote(profile)));
+    }
+    if !webhooks.is_empty() {
+        settings.push(format!("NOTIFY_WEBHOOKS={}", quote(webhooks)));
+    }
+
+    settings.join("\n") + "\n"
+}
+
+/// Walks the operator through the settings of a monitor and writes them to an environment
+/// file, and optionally a systemd unit that runs it.
+///
+/// # Parameters
+///
+/// * `args`: The arguments passed to the program, whose values are offered as defaults.
+/// * `output`: The environment file to write.
+///
+/// # Returns
+///
+/// An error if stdin cannot be read or a file cannot be written, or `Ok(())` on success.
+pub async fn run(
+    args: &crate::Args,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    println!(
+        "Settings are written to {}; press Enter to keep the [default].",
+        output.display()
+    );
+
+    let mut args = args.clone();
+    let region = ask(
+        "AWS region",
+        args.region.as_deref().unwrap_or(DEFAULT_REGION),
+    )?;
+    args.region = Some(region.clone());
+    let config = crate::load_aws_config(&args, None).await;
+    let prefix_list_id = choose_prefix_list(&config).await?;
+    let description = ask("Entry description", &args.description)?;
+    let interval = loop {
+        let answer = ask("Check interval in seconds", &args.interval.to_string())?;
+        match answer.parse::<u64>() {
+            Ok(interval) if interval > 0 => break interval,
+            _ => println!("Enter a whole number of seconds"),
+        }
+    };
+    let webhooks = ask(
+        "Webhook URLs to notify of changes, comma-separated (blank for none)",
+        &args.notify_webhooks.join(","),
+    )?;
+
+    let settings = render_settings(
+        &region,
+        &prefix_list_id,
+        &description,
+        interval,
+        args.profile.as_deref(),
+        &webhooks,
+    );
+    if !write_file(output, &settings)? {
+        return Err("Kept the existing file; nothing was written".into());
+    }
+    println!("Wrote {}", output.display());
+
+    if confirm("Write a systemd unit that runs the monitor with these settings?")? {
+        let env_file = std::path::absolute(output)?;
+        let unit = env_file
+            .parent()
+            .map_or_else(|| PathBuf::from(UNIT_NAME), |dir| dir.join(UNIT_NAME));
+        let arguments = ["--env-file".to_string(), env_file.display().to_string()];
+        if write_file(&unit, &crate::service::systemd_unit(&arguments)?)? {
+            println!(
+                "Wrote {}; install it with:\n  sudo cp {} /etc/systemd/system/\n  sudo systemctl \
+                 enable --now {}",
+                unit.display(),
+                unit.display(),
+                UNIT_NAME
+            );
+        }
+    }
+
+    println!(
+        "Check the settings and credentials with `{} validate --env-file {}`",
+        env!("CARGO_PKG_NAME"),
+        output.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads a written environment file back through the options it is meant to set.
+    fn parse_back(contents: &str) -> crate::Args {
+        let name = env!("CARGO_PKG_NAME");
+        let command = <crate::Args as clap::Args>::augment_args(clap::Command::new(name));
+        let mut argv = vec![name.to_string()];
+        for item in dotenvy::from_read_iter(contents.as_bytes()) {
+            let (key, value) = item.unwrap();
+            let arg = command
+                .get_arguments()
+                .find(|arg| arg.get_env().is_some_and(|env| env == key.as_str()))
+                .unwrap_or_else(|| panic!("{} is not read by any option", key));
+            argv.push(format!("--{}", arg.get_long().unwrap()));
+            argv.push(value);
+        }
+
+        // Only the file's values count, not those of the environment running the test
+        let matches = command
+            .mut_args(|arg| arg.env(None))
+            .try_get_matches_from(argv)
+            .unwrap();
+        <crate::Args as clap::FromArgMatches>::from_arg_matches(&matches).unwrap()
+    }
+
+    #[test]
+    fn written_settings_are_read_back() {
+        let contents = render_settings(
+            "eu-west-1",
+            "pl-0123456789abcdef0",
+            "Home 'lab' $HOST",
+            600,
+            Some("homelab"),
+            "https://hooks.example.com/a,https://hooks.example.com/b",
+        );
+
+        let args = parse_back(&contents);
+        assert_eq!(args.region.as_deref(), Some("eu-west-1"));
+        assert_eq!(args.prefix_list_id.as_deref(), Some("pl-0123456789abcdef0"));
+        assert_eq!(args.description, "Home 'lab' $HOST");
+        assert_eq!(args.interval, 600);
+        assert_eq!(args.profile.as_deref(), Some("homelab"));
+        assert_eq!(args.notify_webhooks.len(), 2);
+    }
+
+    #[test]
+    fn optional_settings_are_left_out() {
+        let contents = render_settings("us-east-1", "pl-0123456789abcdef0", "Home", 300, None, "");
+
+        assert!(!contents.contains("AWS_PROFILE"));
+        assert!(!contents.contains("NOTIFY_WEBHOOKS"));
+        assert_eq!(parse_back(&contents).description, "Home");
+    }
+
+    #[test]
+    fn quoted_values_are_read_back_unchanged() {
+        assert_eq!(quote("Home office"), "'Home office'");
+        assert_eq!(quote(r#"Bob's "lab""#), r#""Bob's \"lab\"""#);
+        for value in [
+            "",
+            "a b",
+            "$HOME",
+            "Bob's",
+            r#"it's "$5" \ ${X}"#,
+            "#not a comment",
+        ] {
+            let (_, read) = dotenvy::from_read_iter(format!("KEY={}", quote(value)).as_bytes())
+                .next()
+                .unwrap()
+                .unwrap();
+            assert_eq!(read, value);
+        }
+    }
+
+    #[test]
+    fn saved_setting_replaces_only_its_own_line() {
+        let path = std::env::temp_dir().join(format!("settings-{}.env", std::process::id()));
+        std::fs::write(
+            &path,
+            "# Monitor settings\nAWS_REGION='eu-west-1'\n  export PREFIX_LIST_ID=pl-0123456789abcdef0\nPREFIX_LIST_ID_OLD=x\n",
+        )
+        .unwrap();
+
+        save_setting(&path, "PREFIX_LIST_ID", "pl-0fedcba9876543210").unwrap();
+        save_setting(&path, "INTERVAL", "600").unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            contents,
+            "# Monitor settings\nAWS_REGION='eu-west-1'\nPREFIX_LIST_ID='pl-0fedcba9876543210'\nPREFIX_LIST_ID_OLD=x\nINTERVAL='600'\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saved_settings_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!("private-{}.env", std::process::id()));
+        save_setting(&path, "PREFIX_LIST_ID", "pl-0123456789abcdef0").unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(contents, "PREFIX_LIST_ID='pl-0123456789abcdef0'\n");
+    }
+}
@@ -0,0 +1,1232 @@
+use aws_config::{Region, SdkConfig};
+use aws_sdk_ec2::{types::Filter, Client};
+use futures::future::BoxFuture;
+use hickory_resolver::{
+    config::{LookupIpStrategy, NameServerConfig, ResolverConfig},
+    name_server::TokioConnectionProvider,
+    system_conf::read_system_conf,
+    TokioResolver,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// The environment variable ECS sets to the container's task metadata endpoint.
+const ECS_METADATA_VARIABLE: &str = "ECS_CONTAINER_METADATA_URI_V4";
+
+/// The largest weight of an IP service, which keeps the weighted schedule of a round small.
+const MAX_SERVICE_WEIGHT: u32 = 100;
+
+/// An IP address family, for prefix lists that only hold one of them.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// IPv4 addresses
+    V4,
+    /// IPv6 addresses
+    V6,
+}
+
+impl AddressFamily {
+    /// Reads the address family of a prefix list as EC2 reports it.
+    ///
+    /// # Parameters
+    ///
+    /// * `name`: The family, `IPv4` or `IPv6`.
+    ///
+    /// # Returns
+    ///
+    /// The family, or `None` if it is not one of them.
+    pub fn of_prefix_list(name: &str) -> Option<Self> {
+        match name {
+            "IPv4" => Some(Self::V4),
+            "IPv6" => Some(Self::V6),
+            _ => None,
+        }
+    }
+
+    /// Returns whether an IP address belongs to this family.
+    ///
+    /// # Parameters
+    ///
+    /// * `ip`: The IP address.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the address belongs to this family, `false` otherwise.
+    pub fn matches(self, ip: &str) -> bool {
+        match self {
+            Self::V4 => ip.parse::<std::net::Ipv4Addr>().is_ok(),
+            Self::V6 => ip.parse::<std::net::Ipv6Addr>().is_ok(),
+        }
+    }
+}
+
+impl std::fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V4 => write!(f, "IPv4"),
+            Self::V6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// The address family detection requests are sent over.
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectFamily {
+    /// IPv4 only
+    V4,
+    /// IPv6 only
+    V6,
+    /// Whichever the system prefers
+    #[default]
+    Both,
+}
+
+/// How the HTTP service asked first is chosen when several are configured.
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServiceSelection {
+    /// The first service, falling back to the next ones in order
+    #[default]
+    Failover,
+    /// Each service in turn as often as its weight, falling back to the others
+    Weighted,
+}
+
+/// The consecutive failures or outlier answers after which a service is quarantined.
+const QUARANTINE_AFTER: u32 = 3;
+
+/// How long a quarantined service is skipped before it is asked again.
+const QUARANTINE_TIME: Duration = Duration::from_secs(600);
+
+/// An HTTP service that echoes back the caller's public IP address.
+pub struct HttpService {
+    /// The URL of the service.
+    url: String,
+    /// How many of every round of checks ask the service first.
+    weight: u32,
+    /// How the service has answered so far.
+    health: Mutex<Health>,
+}
+
+/// How an IP service has answered since startup.
+#[derive(Default)]
+struct Health {
+    /// The requests answered with an address.
+    successes: u64,
+    /// The requests that failed or were answered with something other than an address.
+    failures: u64,
+    /// The answers contradicted by another service.
+    outliers: u64,
+    /// The time taken by the successful requests, for the average latency.
+    latency: Duration,
+    /// The failures and outliers since the last good answer.
+    consecutive_failures: u32,
+    /// When the service is asked again, while it is quarantined.
+    quarantined_until: Option<Instant>,
+}
+
+/// How a request to an IP service went.
+enum Answer {
+    /// The service answered with an address, taking this long.
+    Success(Duration),
+    /// The request failed.
+    Failure,
+    /// The service answered with an address another service contradicted.
+    Outlier,
+}
+
+impl HttpService {
+    /// Returns whether the service is skipped because it kept failing.
+    fn quarantined(&self) -> bool {
+        self.health.lock().is_ok_and(|health| {
+            health
+                .quarantined_until
+                .is_some_and(|until| Instant::now() < until)
+        })
+    }
+
+    /// Records how a request went, quarantining the service after repeated bad answers.
+    ///
+    /// # Parameters
+    ///
+    /// * `answer`: How the request went.
+    fn record(&self, answer: Answer) {
+        let Ok(mut health) = self.health.lock() else {
+            return;
+        };
+        match answer {
+            Answer::Success(latency) => {
+                health.successes += 1;
+                health.latency += latency;
+                if health.quarantined_until.take().is_some() {
+                    info!("IP service {} answers again; quarantine lifted", self.url);
+                }
+                health.consecutive_failures = 0;
+                return;
+            }
+            Answer::Failure => health.failures += 1,
+            Answer::Outlier => health.outliers += 1,
+        }
+
+        health.consecutive_failures += 1;
+        // A failed probe after the quarantine puts the service straight back
+        if health.consecutive_failures >= QUARANTINE_AFTER {
+            health.quarantined_until = Some(Instant::now() + QUARANTINE_TIME);
+            warn!(
+                "IP service {} quarantined for {}s after {} bad answers in a row",
+                self.url,
+                QUARANTINE_TIME.as_secs(),
+                health.consecutive_failures
+            );
+        }
+    }
+
+    /// Asks the service for the caller's public IP address, recording how it went.
+    ///
+    /// # Parameters
+    ///
+    /// * `client`: The client instance used to query the service.
+    ///
+    /// # Returns
+    ///
+    /// The IP address, or an error if the request fails or the answer is not an address.
+    async fn ask(
+        &self,
+        client: &reqwest::Client,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let started = Instant::now();
+        let result = query_service(client, &self.url).await;
+        self.record(match &result {
+            Ok(_) => Answer::Success(started.elapsed()),
+            Err(_) => Answer::Failure,
+        });
+        result
+    }
+
+    /// Summarizes how the service has answered, for the status.
+    fn health(&self) -> crate::status::ServiceHealth {
+        let health = self.health.lock();
+        let health = health.as_deref().ok();
+        let successes = health.map_or(0, |health| health.successes);
+        crate::status::ServiceHealth {
+            url: self.url.clone(),
+            successes,
+            failures: health.map_or(0, |health| health.failures),
+            outliers: health.map_or(0, |health| health.outliers),
+            average_latency_ms: health
+                .filter(|_| successes > 0)
+                .map(|health| (health.latency / successes as u32).as_millis() as u64),
+            quarantined: self.quarantined(),
+        }
+    }
+}
+
+/// Parses the IP service URLs given on the command line.
+///
+/// # Parameters
+///
+/// * `services`: Comma-separated URLs, each optionally followed by ` weight=<n>`.
+///
+/// # Returns
+///
+/// The services, or an error if a weight is not a number from 1 to `MAX_SERVICE_WEIGHT`.
+fn parse_services(
+    services: &str,
+) -> Result<Vec<HttpService>, Box<dyn std::error::Error + Send + Sync>> {
+    services
+        .split(',')
+        .map(str::trim)
+        .filter(|service| !service.is_empty())
+        .map(|service| {
+            let (url, weight) = match service.split_once(char::is_whitespace) {
+                Some((url, weight)) => {
+                    let weight = weight
+                        .trim()
+                        .strip_prefix("weight=")
+                        .and_then(|weight| weight.parse::<u32>().ok())
+                        .filter(|weight| (1..=MAX_SERVICE_WEIGHT).contains(weight))
+                        .ok_or_else(|| {
+                            format!(
+                                "Invalid IP service {}; expected <url> or <url> weight=<n> with n \
+                                 from 1 to {}",
+                                service, MAX_SERVICE_WEIGHT
+                            )
+                        })?;
+                    (url, weight)
+                }
+                None => (service, 1),
+            };
+            Ok(HttpService {
+                url: url.to_string(),
+                weight,
+                health: Mutex::default(),
+            })
+        })
+        .collect()
+}
+
+/// Orders a round of checks so each service is asked first as often as its weight, spread
+/// out rather than in runs (smooth weighted round-robin).
+///
+/// # Parameters
+///
+/// * `services`: The services with their weights.
+///
+/// # Returns
+///
+/// The index of the service asked first at each check of a round.
+fn weighted_schedule(services: &[HttpService]) -> Vec<usize> {
+    let total: i64 = services.iter().map(|s| i64::from(s.weight)).sum();
+    let mut current = vec![0i64; services.len()];
+    let mut schedule = Vec::with_capacity(total as usize);
+    for _ in 0..total {
+        for (credit, service) in current.iter_mut().zip(services) {
+            *credit += i64::from(service.weight);
+        }
+        let Some((chosen, _)) = current
+            .iter()
+            .enumerate()
+            .max_by_key(|(index, credit)| (**credit, std::cmp::Reverse(*index)))
+        else {
+            break;
+        };
+        current[chosen] -= total;
+        schedule.push(chosen);
+    }
+
+    schedule
+}
+
+/// An uplink of a multi-homed host, whose address is detected through its own interface or
+/// source address.
+#[derive(Clone, Debug)]
+pub struct Uplink {
+    /// The name identifying the uplink in entry descriptions and logs.
+    pub name: String,
+    /// The local address detection requests for the uplink are sent from.
+    pub bind_address: Option<IpAddr>,
+    /// The network interface detection requests for the uplink are sent through.
+    pub bind_interface: Option<String>,
+}
+
+/// Parses an uplink given on the command line.
+///
+/// # Parameters
+///
+/// * `value`: The uplink as `name=interface` or `name=address`.
+///
+/// # Returns
+///
+/// The uplink, or an error if the value has no name or no interface or address.
+pub fn parse_uplink(value: &str) -> Result<Uplink, String> {
+    let (name, bind) = value
+        .split_once('=')
+        .filter(|(name, bind)| !name.is_empty() && !bind.is_empty())
+        .ok_or_else(|| {
+            format!(
+                "Invalid uplink {}; expected name=interface or name=address",
+                value
+            )
+        })?;
+
+    Ok(match bind.parse::<IpAddr>() {
+        Ok(address) => Uplink {
+            name: name.to_string(),
+            bind_address: Some(address),
+            bind_interface: None,
+        },
+        Err(_) => Uplink {
+            name: name.to_string(),
+            bind_address: None,
+            bind_interface: Some(bind.to_string()),
+        },
+    })
+}
+
+/// A source of the public IP addresses provided by a program embedding the monitor, in place
+/// of the built-in ones.
+///
+/// The addresses pass the same verification and family selection as detected ones.
+pub trait DetectIp: Send + Sync {
+    /// Detects the current IP addresses.
+    ///
+    /// # Returns
+    ///
+    /// The IP addresses, or an error if they cannot be detected, which fails the check.
+    fn detect(
+        &self,
+    ) -> BoxFuture<'_, Result<Vec<IpAddr>, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+/// Where the IP address to publish in the prefix list comes from.
+pub enum IpSource {
+    /// HTTP services that echo back the caller's public IP address.
+    Http {
+        /// The client instance used to query the services, bound to the detection family.
+        client: reqwest::Client,
+        /// The services, in the order they are configured.
+        services: Vec<HttpService>,
+        /// The service asked first at each check of a round, when weighted.
+        schedule: Option<Vec<usize>>,
+        /// The number of checks made, for the position in the schedule.
+        checks: AtomicUsize,
+        /// The address last detected, against which a different answer is cross-checked.
+        last_ip: Mutex<Option<String>>,
+    },
+    /// An address supplied on the command line, reused by every check.
+    Fixed(String),
+    /// Scripted addresses returned one per check without network calls, the last one repeating.
+    Sequence {
+        /// The addresses, in the order they are returned.
+        ips: Vec<String>,
+        /// The index of the next address to return.
+        next: AtomicUsize,
+    },
+    /// The A and AAAA records of a hostname maintained elsewhere, such as by a dynamic DNS client.
+    Dns {
+        /// The resolver used to look the hostname up.
+        resolver: Box<TokioResolver>,
+        /// The hostname whose records are mirrored.
+        hostname: String,
+        /// When the records last resolved expire.
+        valid_until: Mutex<Option<Instant>>,
+    },
+    /// The public IPv4 address of an EC2 instance, which changes on stop/start without an EIP.
+    Instance {
+        /// The client instance used to describe the instance.
+        client: Client,
+        /// The ID of the instance.
+        instance_id: String,
+    },
+    /// The public addresses of the ECS task the monitor runs in, such as a Fargate task with a
+    /// public IP.
+    Ecs {
+        /// The client instance used to query the task metadata endpoint.
+        http: reqwest::Client,
+        /// The task metadata endpoint of the container.
+        metadata_uri: String,
+        /// The client instance used to describe the task's network interfaces.
+        client: Client,
+    },
+    /// The public IPs of one or more NAT gateways, selected by ID or by tag.
+    NatGateway {
+        /// The client instance used to describe the NAT gateways.
+        client: Client,
+        /// The IDs of the NAT gateways, if selected by ID.
+        nat_gateway_ids: Vec<String>,
+        /// The tag key and value the NAT gateways carry, if selected by tag.
+        tag: Option<(String, String)>,
+    },
+    /// The address of an Elastic IP, selected by allocation ID or by tag.
+    ElasticIp {
+        /// The client instance used to describe the Elastic IPs.
+        client: Client,
+        /// The allocation ID of the Elastic IP, if selected by ID.
+        allocation_id: Option<String>,
+        /// The tag key and value the Elastic IPs carry, if selected by tag.
+        tag: Option<(String, String)>,
+    },
+    /// A source provided by a program embedding the monitor.
+    Custom(Box<dyn DetectIp>),
+}
+
+/// Validates an IP address given on the command line.
+///
+/// # Parameters
+///
+/// * `ip`: The IP address.
+///
+/// # Returns
+///
+/// The IP address, or an error if it is not valid.
+fn parse_ip(ip: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    ip.trim()
+        .parse::<std::net::IpAddr>()
+        .map(|ip| ip.to_string())
+        .map_err(|_| format!("Invalid IP address {}", ip).into())
+}
+
+/// Determines the local address detection requests are sent from.
+///
+/// # Parameters
+///
+/// * `args`: The arguments passed to the program.
+///
+/// # Returns
+///
+/// The address to bind to, if any, or an error if the bind address does not belong to the
+/// detection family.
+fn local_address(
+    args: &crate::Args,
+) -> Result<Option<IpAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    // Binding to the unspecified address of a family only connects to addresses of that family
+    match (args.bind_address, args.detect_family) {
+        (Some(address), DetectFamily::V4) if address.is_ipv6() => {
+            Err(format!("Bind address {} is not an IPv4 address", address).into())
+        }
+        (Some(address), DetectFamily::V6) if address.is_ipv4() => {
+            Err(format!("Bind address {} is not an IPv6 address", address).into())
+        }
+        (Some(address), _) => Ok(Some(address)),
+        (None, DetectFamily::V4) => Ok(Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED))),
+        (None, DetectFamily::V6) => Ok(Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED))),
+        (None, DetectFamily::Both) => Ok(None),
+    }
+}
+
+/// Builds the HTTP client used for detection, sending requests over one address family, from
+/// one address or through one interface when requested.
+///
+/// # Parameters
+///
+/// * `args`: The arguments passed to the program.
+///
+/// # Returns
+///
+/// The HTTP client, or an error if it cannot be built.
+fn http_client(
+    args: &crate::Args,
+) -> Result<reqwest::Client, Box<dyn std::error::Error + Send + Sync>> {
+    let mut builder = reqwest::Client::builder().local_address(local_address(args)?);
+
+    if let Some(interface) = &args.bind_interface {
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            builder = builder.interface(interface);
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        return Err(format!(
+            "Binding to interface {} is only supported on Linux and macOS",
+            interface
+        )
+        .into());
+    }
+
+    if args.small_footprint {
+        // One HTTP/1.1 connection per service avoids the HTTP/2 flow-control buffers
+        builder = builder.http1_only().pool_max_idle_per_host(1);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Builds the resolver used for DNS detection, limited to the detection family and sending
+/// queries from the bind address when requested.
+///
+/// # Parameters
+///
+/// * `args`: The arguments passed to the program.
+///
+/// # Returns
+///
+/// The resolver, or an error if the system configuration cannot be read, has no name server
+/// reachable from the bind address, or the queries should go through an interface.
+fn dns_resolver(
+    args: &crate::Args,
+) -> Result<TokioResolver, Box<dyn std::error::Error + Send + Sync>> {
+    // The resolver can only bind its sockets to an address, not to an interface
+    if let Some(interface) = &args.bind_interface {
+        return Err(format!(
+            "dns: detection cannot be sent through interface {}; use --bind-address with the \
+             interface's address instead",
+            interface
+        )
+        .into());
+    }
+    // The detection family alone is applied through the lookup strategy instead
+    let bind_address = local_address(args)?.filter(|address| !address.is_unspecified());
+    let mut builder = match bind_address {
+        Some(address) => {
+            let (config, options) = read_system_conf()?;
+            let name_servers: Vec<NameServerConfig> = config
+                .name_servers()
+                .iter()
+                .filter(|n| n.socket_addr.is_ipv4() == address.is_ipv4())
+                .cloned()
+                .map(|mut n| {
+                    n.bind_addr = Some(SocketAddr::new(address, 0));
+                    n
+                })
+                .collect();
+            if name_servers.is_empty() {
+                return Err(format!("No name server is reachable from {}", address).into());
+            }
+
+            TokioResolver::builder_with_config(
+                ResolverConfig::from_parts(
+                    config.domain().cloned(),
+                    config.search().to_vec(),
+                    name_servers,
+                ),
+                TokioConnectionProvider::default(),
+            )
+            .with_options(options)
+        }
+        None => TokioResolver::builder_tokio()?,
+    };
+    builder.options_mut().ip_strategy = match args.detect_family {
+        DetectFamily::V4 => LookupIpStrategy::Ipv4Only,
+        DetectFamily::V6 => LookupIpStrategy::Ipv6Only,
+        DetectFamily::Both => LookupIpStrategy::Ipv4AndIpv6,
+    };
+
+    Ok(builder.build())
+}
+
+/// Splits a `Key=Value` tag selector.
+///
+/// # Parameters
+///
+/// * `tag`: The tag selector.
+///
+/// # Returns
+///
+/// The tag key and value, or an error if the selector has no `=`.
+fn parse_tag(tag: &str) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+    let (key, value) = tag
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid tag {}; expected Key=Value", tag))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+impl IpSource {
+    /// Builds the IP source from its command line specification.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct service clients.
+    /// * `args`: The arguments passed to the program; `ip_source` is one of `http`,
+    ///   `dns:<hostname>`, `instance:<instance-id>`, `ecs`, `nat-gateway:<id>[,<id>...]`, `nat-gateway:tag:Key=Value`,
+    ///   `eip:<allocation-id>` or `eip:tag:Key=Value`.
+    ///
+    /// # Returns
+    ///
+    /// The IP source, or an error if the specification is not recognized.
+    pub fn from_args(
+        config: &SdkConfig,
+        args: &crate::Args,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(ip) = &args.ip {
+            return Ok(Self::Fixed(parse_ip(ip)?));
+        }
+
+        let source = args.ip_source.as_str();
+        if source == "http" {
+            // Mock services return scripted addresses for tests and demos
+            if let Some(ip) = args.ip_service.strip_prefix("mock:") {
+                return Ok(Self::Fixed(parse_ip(ip)?));
+            }
+            if let Some(ips) = args.ip_service.strip_prefix("mock-sequence:") {
+                return Ok(Self::Sequence {
+                    ips: ips.split(',').map(parse_ip).collect::<Result<_, _>>()?,
+                    next: AtomicUsize::new(0),
+                });
+            }
+            let services = parse_services(&args.ip_service)?;
+            if services.is_empty() {
+                return Err("No IP service URL configured".into());
+            }
+            let schedule = (args.ip_service_selection == ServiceSelection::Weighted)
+                .then(|| weighted_schedule(&services));
+            return Ok(Self::Http {
+                client: http_client(args)?,
+                services,
+                schedule,
+                checks: AtomicUsize::new(0),
+                last_ip: Mutex::new(None),
+            });
+        }
+
+        if let Some(hostname) = source.strip_prefix("dns:") {
+            if hostname.is_empty() {
+                return Err("DNS IP source needs a hostname".into());
+            }
+            return Ok(Self::Dns {
+                resolver: Box::new(dns_resolver(args)?),
+                hostname: hostname.to_string(),
+                valid_until: Mutex::new(None),
+            });
+        }
+
+        // The tracked resources may live in a different region than the prefix lists
+        let client = match &args.ip_source_region {
+            Some(region) => crate::replay::ec2_client(
+                aws_sdk_ec2::config::Builder::from(config).region(Region::new(region.clone())),
+            ),
+            None => crate::replay::ec2_client(config.into()),
+        };
+
+        if source == "ecs" {
+            let metadata_uri = std::env::var(ECS_METADATA_VARIABLE).map_err(|_| {
+                format!(
+                    "ECS IP source needs {}; is the monitor running in an ECS task?",
+                    ECS_METADATA_VARIABLE
+                )
+            })?;
+            return Ok(Self::Ecs {
+                http: reqwest::Client::new(),
+                metadata_uri,
+                client,
+            });
+        }
+
+        match source.split_once(':') {
+            Some(("instance", instance_id)) if !instance_id.is_empty() => Ok(Self::Instance {
+                client,
+                instance_id: instance_id.to_string(),
+            }),
+            Some(("nat-gateway", selector)) => match selector.strip_prefix("tag:") {
+                Some(tag) => Ok(Self::NatGateway {
+                    client,
+                    nat_gateway_ids: Vec::new(),
+                    tag: Some(parse_tag(tag)?),
+                }),
+                None if !selector.is_empty() => Ok(Self::NatGateway {
+                    client,
+                    nat_gateway_ids: selector.split(',').map(str::to_string).collect(),
+                    tag: None,
+                }),
+                None => Err("NAT gateway IP source needs at least one ID or a tag".into()),
+            },
+            Some(("eip", selector)) => match selector.strip_prefix("tag:") {
+                Some(tag) => Ok(Self::ElasticIp {
+                    client,
+                    allocation_id: None,
+                    tag: Some(parse_tag(tag)?),
+                }),
+                None if !selector.is_empty() => Ok(Self::ElasticIp {
+                    client,
+                    allocation_id: Some(selector.to_string()),
+                    tag: None,
+                }),
+                None => Err("Elastic IP source needs an allocation ID or a tag".into()),
+            },
+            _ => Err(format!(
+                "Unknown IP source {}; expected http, dns:<hostname>, instance:<instance-id>, ecs, nat-gateway:<id,...|tag:Key=Value> or eip:<allocation-id|tag:Key=Value>",
+                source
+            )
+            .into()),
+        }
+    }
+
+    /// Retrieves the current IP addresses from the source.
+    ///
+    /// # Returns
+    ///
+    /// The IP addresses, sorted and deduplicated, or an error if the lookup fails.
+    pub async fn detect(&self) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        match self {
+            Self::Http {
+                client,
+                services,
+                schedule,
+                checks,
+                last_ip,
+            } => {
+                let ip =
+                    detect_http(client, services, schedule.as_deref(), checks, last_ip).await?;
+                Ok(vec![ip])
+            }
+            Self::Fixed(ip) => Ok(vec![ip.clone()]),
+            Self::Sequence { ips, next } => {
+                let index = next.fetch_add(1, Ordering::Relaxed).min(ips.len() - 1);
+                Ok(vec![ips[index].clone()])
+            }
+            Self::Dns {
+                resolver,
+                hostname,
+                valid_until,
+            } => {
+                let lookup = resolver
+                    .lookup_ip(hostname.as_str())
+                    .await
+                    .map_err(|e| format!("Cannot resolve {}: {}", hostname, e))?;
+                if let Ok(mut valid_until) = valid_until.lock() {
+                    *valid_until = Some(
+                        Instant::now()
+                            + lookup
+                                .valid_until()
+                                .saturating_duration_since(std::time::Instant::now()),
+                    );
+                }
+
+                let mut ips: Vec<String> = lookup.iter().map(|ip| ip.to_string()).collect();
+                ips.sort();
+                ips.dedup();
+
+                if ips.is_empty() {
+                    return Err(format!("No A or AAAA records found for {}", hostname).into());
+                }
+                Ok(ips)
+            }
+            Self::Instance {
+                client,
+                instance_id,
+            } => {
+                let response = client
+                    .describe_instances()
+                    .instance_ids(instance_id)
+                    .send()
+                    .await?;
+                let instance = response
+                    .reservations()
+                    .iter()
+                    .flat_map(|r| r.instances())
+                    .next()
+                    .ok_or_else(|| format!("Instance {} not found", instance_id))?;
+
+                instance
+                    .public_ip_address()
+                    .map(|ip| vec![ip.to_string()])
+                    .ok_or_else(|| {
+                        format!(
+                            "Instance {} has no public IPv4 address (state: {})",
+                            instance_id,
+                            instance
+                                .state()
+                                .and_then(|s| s.name())
+                                .map(|n| n.as_str())
+                                .unwrap_or("unknown")
+                        )
+                        .into()
+                    })
+            }
+            Self::Ecs {
+                http,
+                metadata_uri,
+                client,
+            } => {
+                let task: serde_json::Value = serde_json::from_str(
+                    &http
+                        .get(format!("{}/task", metadata_uri))
+                        .send()
+                        .await?
+                        .error_for_status()?
+                        .text()
+                        .await?,
+                )?;
+                let networks: Vec<&serde_json::Value> = task["Containers"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .flat_map(|container| container["Networks"].as_array().into_iter().flatten())
+                    .collect();
+                let addresses = |key: &str| -> Vec<String> {
+                    networks
+                        .iter()
+                        .flat_map(|network| network[key].as_array().into_iter().flatten())
+                        .filter_map(|address| address.as_str())
+                        .map(str::to_string)
+                        .collect()
+                };
+
+                // The metadata only holds private IPv4 addresses; their interface has the public one
+                let mut private_ips = addresses("IPv4Addresses");
+                private_ips.sort();
+                private_ips.dedup();
+                let mut ips = if private_ips.is_empty() {
+                    Vec::new()
+                } else {
+                    client
+                        .describe_network_interfaces()
+                        .filters(
+                            Filter::builder()
+                                .name("addresses.private-ip-address")
+                                .set_values(Some(private_ips.clone()))
+                                .build(),
+                        )
+                        .send()
+                        .await?
+                        .network_interfaces()
+                        .iter()
+                        .filter_map(|interface| interface.association())
+                        .filter_map(|association| association.public_ip())
+                        .map(str::to_string)
+                        .collect()
+                };
+                // Task IPv6 addresses are globally routable as they are
+                ips.extend(addresses("IPv6Addresses"));
+                ips.sort();
+                ips.dedup();
+
+                if ips.is_empty() {
+                    return Err(format!(
+                        "ECS task has no public IP (private addresses: {})",
+                        if private_ips.is_empty() {
+                            "none".to_string()
+                        } else {
+                            private_ips.join(", ")
+                        }
+                    )
+                    .into());
+                }
+                Ok(ips)
+            }
+            Self::NatGateway {
+                client,
+                nat_gateway_ids,
+                tag,
+            } => {
+                let mut request = client
+                    .describe_nat_gateways()
+                    .set_nat_gateway_ids(
+                        (!nat_gateway_ids.is_empty()).then(|| nat_gateway_ids.clone()),
+                    )
+                    .filter(Filter::builder().name("state").values("available").build());
+                if let Some((key, value)) = tag {
+                    request = request.filter(
+                        Filter::builder()
+                            .name(format!("tag:{}", key))
+                            .values(value)
+                            .build(),
+                    );
+                }
+                let response = request.send().await?;
+
+                let mut ips: Vec<String> = response
+                    .nat_gateways()
+                    .iter()
+                    .flat_map(|g| g.nat_gateway_addresses())
+                    .filter_map(|a| a.public_ip())
+                    .map(str::to_string)
+                    .collect();
+                ips.sort();
+                ips.dedup();
+
+                if ips.is_empty() {
+                    return Err(format!("No available NAT gateways found for {}", self).into());
+                }
+                Ok(ips)
+            }
+            Self::ElasticIp {
+                client,
+                allocation_id,
+                tag,
+            } => {
+                let mut request = client
+                    .describe_addresses()
+                    .set_allocation_ids(allocation_id.clone().map(|id| vec![id]));
+                if let Some((key, value)) = tag {
+                    request = request.filters(
+                        Filter::builder()
+                            .name(format!("tag:{}", key))
+                            .values(value)
+                            .build(),
+                    );
+                }
+                // A released allocation is reported as an error rather than an empty result
+                let response = request.send().await.map_err(|e| {
+                    match e.as_service_error().and_then(|e| e.meta().code()) {
+                        Some("InvalidAllocationID.NotFound") => {
+                            format!("{} no longer exists; was it released?", self)
+                        }
+                        _ => e.to_string(),
+                    }
+                })?;
+
+                let mut ips: Vec<String> = response
+                    .addresses()
+                    .iter()
+                    .filter_map(|a| a.public_ip())
+                    .map(str::to_string)
+                    .collect();
+                ips.sort();
+                ips.dedup();
+
+                if ips.is_empty() {
+                    return Err(format!("No Elastic IPs found for {}", self).into());
+                }
+                Ok(ips)
+            }
+            Self::Custom(source) => {
+                let mut ips: Vec<String> = source
+                    .detect()
+                    .await?
+                    .iter()
+                    .map(IpAddr::to_string)
+                    .collect();
+                ips.sort();
+                ips.dedup();
+
+                if ips.is_empty() {
+                    return Err("The IP source returned no addresses".into());
+                }
+                Ok(ips)
+            }
+        }
+    }
+
+    /// Summarizes how each HTTP service has answered.
+    ///
+    /// # Returns
+    ///
+    /// The health of each configured service, or nothing for other sources.
+    pub fn service_health(&self) -> Vec<crate::status::ServiceHealth> {
+        match self {
+            Self::Http { services, .. } => services.iter().map(HttpService::health).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns how long the last detected addresses stay valid, for sources that publish a TTL.
+    ///
+    /// # Returns
+    ///
+    /// The time until the DNS records last resolved expire, or `None` for other sources and
+    /// before the first lookup.
+    pub fn refresh_after(&self) -> Option<Duration> {
+        match self {
+            Self::Dns { valid_until, .. } => valid_until
+                .lock()
+                .ok()
+                .and_then(|v| *v)
+                .map(|v| v.saturating_duration_since(Instant::now())),
+            _ => None,
+        }
+    }
+}
+
+/// Asks the configured HTTP services for the caller's public IP address, skipping quarantined
+/// ones and cross-checking an answer that differs from the last one with another service.
+///
+/// # Parameters
+///
+/// * `client`: The client instance used to query the services.
+/// * `services`: The services, in the order they are configured.
+/// * `schedule`: The service asked first at each check of a round, when weighted.
+/// * `checks`: The number of checks made, for the position in the schedule.
+/// * `last_ip`: The address last detected.
+///
+/// # Returns
+///
+/// The IP address, or an error if every service fails.
+async fn detect_http(
+    client: &reqwest::Client,
+    services: &[HttpService],
+    schedule: Option<&[usize]>,
+    checks: &AtomicUsize,
+    last_ip: &Mutex<Option<String>>,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let first = match schedule {
+        Some(schedule) if !schedule.is_empty() => {
+            schedule[checks.fetch_add(1, Ordering::Relaxed) % schedule.len()]
+        }
+        _ => 0,
+    };
+    let order: Vec<usize> = std::iter::once(first)
+        .chain((0..services.len()).filter(|i| *i != first))
+        .collect();
+    // With every service quarantined, asking them anyway beats not detecting at all
+    let mut order: Vec<usize> = match order
+        .iter()
+        .copied()
+        .filter(|i| !services[*i].quarantined())
+        .collect::<Vec<_>>()
+    {
+        available if available.is_empty() => order,
+        available => available,
+    };
+
+    let previous = last_ip.lock().ok().and_then(|ip| ip.clone());
+    let mut failures = Vec::new();
+    while !order.is_empty() {
+        let service = &services[order.remove(0)];
+        let ip = match service.ask(client).await {
+            Ok(ip) => ip,
+            Err(e) if services.len() == 1 => return Err(e),
+            Err(e) => {
+                debug!("IP service {} failed: {}", service.url, e);
+                failures.push(format!("{}: {}", service.url, e));
+                continue;
+            }
+        };
+
+        // A change is confirmed by a second service, so one answering wrongly is caught
+        if let (Some(previous), Some(&other)) = (&previous, order.first()) {
+            if *previous != ip {
+                let witness = &services[other];
+                match witness.ask(client).await {
+                    Ok(confirmed) if confirmed == *previous => {
+                        warn!(
+                            "IP service {} answered {} but {} still answers {}; ignoring it",
+                            service.url, ip, witness.url, previous
+                        );
+                        service.record(Answer::Outlier);
+                        return Ok(confirmed);
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!("IP service {} failed: {}", witness.url, e),
+                }
+            }
+        }
+
+        if let Ok(mut last_ip) = last_ip.lock() {
+            *last_ip = Some(ip.clone());
+        }
+        return Ok(ip);
+    }
+
+    Err(format!("Every IP service failed ({})", failures.join("; ")).into())
+}
+
+/// Asks an HTTP service for the caller's public IP address.
+///
+/// # Parameters
+///
+/// * `client`: The client instance used to query the service.
+/// * `url`: The URL of the service.
+///
+/// # Returns
+///
+/// The IP address, or an error if the request fails or the answer is not an address.
+async fn query_service(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let response = client.get(url).send().await?.text().await?;
+    let ip = response.trim().to_string();
+
+    // Basic IP validation; IPv6-only services such as api6.ipify.org are supported
+    if ip.parse::<std::net::IpAddr>().is_ok() {
+        Ok(ip)
+    } else {
+        Err("Invalid IP address format".into())
+    }
+}
+
+impl std::fmt::Display for IpSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http {
+                services, schedule, ..
+            } => {
+                let urls: Vec<String> = services
+                    .iter()
+                    .map(|service| match schedule {
+                        Some(_) => format!("{} (weight {})", service.url, service.weight),
+                        None => service.url.clone(),
+                    })
+                    .collect();
+                write!(f, "{}", urls.join(", "))
+            }
+            Self::Fixed(ip) => write!(f, "fixed address {}", ip),
+            Self::Sequence { ips, .. } => write!(f, "mock sequence {}", ips.join(", ")),
+            Self::Dns { hostname, .. } => write!(f, "A/AAAA records of {}", hostname),
+            Self::Instance { instance_id, .. } => {
+                write!(f, "public IP of instance {}", instance_id)
+            }
+            Self::Ecs { .. } => write!(f, "public IPs of the ECS task"),
+            Self::NatGateway {
+                nat_gateway_ids,
+                tag: Some((key, value)),
+                ..
+            } if nat_gateway_ids.is_empty() => {
+                write!(f, "public IPs of NAT gateways tagged {}={}", key, value)
+            }
+            Self::NatGateway {
+                nat_gateway_ids, ..
+            } => write!(
+                f,
+                "public IPs of NAT gateways {}",
+                nat_gateway_ids.join(", ")
+            ),
+            Self::ElasticIp {
+                allocation_id: Some(allocation_id),
+                ..
+            } => write!(f, "Elastic IP {}", allocation_id),
+            Self::ElasticIp {
+                tag: Some((key, value)),
+                ..
+            } => write!(f, "Elastic IPs tagged {}={}", key, value),
+            Self::ElasticIp { .. } => write!(f, "Elastic IPs"),
+            Self::Custom(_) => write!(f, "custom IP source"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_selectors_are_split_at_the_first_equals_sign() {
+        assert_eq!(
+            parse_tag("Name=egress").unwrap(),
+            ("Name".to_string(), "egress".to_string())
+        );
+        assert_eq!(
+            parse_tag("Role=nat=primary").unwrap(),
+            ("Role".to_string(), "nat=primary".to_string())
+        );
+        assert!(parse_tag("Name").is_err());
+    }
+
+    #[test]
+    fn uplinks_bind_to_an_address_or_an_interface() {
+        let uplink = parse_uplink("fiber=203.0.113.7").unwrap();
+        assert_eq!(uplink.name, "fiber");
+        assert_eq!(uplink.bind_address, Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(uplink.bind_interface, None);
+
+        let uplink = parse_uplink("lte=wwan0").unwrap();
+        assert_eq!(uplink.name, "lte");
+        assert_eq!(uplink.bind_address, None);
+        assert_eq!(uplink.bind_interface.as_deref(), Some("wwan0"));
+
+        for value in ["fiber", "=eth0", "fiber="] {
+            assert!(parse_uplink(value).is_err(), "{} was accepted", value);
+        }
+    }
+
+    #[test]
+    fn services_are_parsed_with_their_weights() {
+        let services = parse_services(
+            " https://a.example.com weight=3 ,https://b.example.com,, https://c.example.com  weight=100",
+        )
+        .unwrap();
+        let parsed: Vec<_> = services
+            .iter()
+            .map(|service| (service.url.as_str(), service.weight))
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                ("https://a.example.com", 3),
+                ("https://b.example.com", 1),
+                ("https://c.example.com", 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_service_weights_are_rejected() {
+        for services in [
+            "https://a.example.com weight=0",
+            "https://a.example.com weight=101",
+            "https://a.example.com weight=-1",
+            "https://a.example.com weight=",
+            "https://a.example.com weight=heavy",
+            "https://a.example.com heavy",
+        ] {
+            assert!(
+                parse_services(services).is_err(),
+                "{} was accepted",
+                services
+            );
+        }
+    }
+
+    #[test]
+    fn weighted_schedule_spreads_out_the_heavier_service() {
+        let services = parse_services("https://a weight=3,https://b").unwrap();
+        assert_eq!(weighted_schedule(&services), [0, 0, 1, 0]);
+
+        let services = parse_services("https://a weight=2,https://b weight=2").unwrap();
+        assert_eq!(weighted_schedule(&services), [0, 1, 0, 1]);
+    }
+}
@@ -0,0 +1,674 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+
+/// The keys of a job, for suggesting the intended key when a file misspells one.
+const JOB_KEYS: &[&str] = &[
+    "name",
+    "region",
+    "profile",
+    "role_arn",
+    "prefix_list_id",
+    "prefix_list_tag",
+    "description",
+    "cidr_suffix",
+    "ipv6_prefix_length",
+    "interval",
+    "ip_source",
+    "ip_service",
+    "ip_service_selection",
+    "detect_family",
+    "bind_address",
+    "bind_interface",
+    "uplinks",
+    "targets",
+    "ip",
+    "ip_source_region",
+    "ipv6",
+    "security_group_ids",
+    "security_group_tag",
+    "security_group_rules",
+    "prefix_length",
+];
+
+/// The contents of a configuration file.
+///
+/// TOML files keep where each job is defined, for pointing at the line of an invalid value.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, bound(deserialize = "J: Deserialize<'de>"))]
+struct Config<J> {
+    /// Options applied as if given in the environment, named as on the command line.
+    #[serde(default)]
+    settings: BTreeMap<String, Setting>,
+    /// The jobs run by the daemon.
+    #[serde(default)]
+    jobs: Vec<J>,
+}
+
+/// The value of an option in the `settings` table.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Setting {
+    /// A flag.
+    Bool(bool),
+    /// A number, such as an interval.
+    Integer(i64),
+    /// A fractional number.
+    Float(f64),
+    /// Any other value.
+    String(String),
+    /// The values of an option taking several.
+    List(Vec<Setting>),
+}
+
+impl Setting {
+    /// Formats the value as it would be given in the environment.
+    ///
+    /// # Returns
+    ///
+    /// The value, with the items of a list separated by commas.
+    fn to_env(&self) -> String {
+        match self {
+            Self::Bool(value) => value.to_string(),
+            Self::Integer(value) => value.to_string(),
+            Self::Float(value) => value.to_string(),
+            Self::String(value) => value.clone(),
+            Self::List(values) => values
+                .iter()
+                .map(Self::to_env)
+                .collect::<Vec<_>>()
+                .join(","),
+        }
+    }
+}
+
+/// A configuration file, read and parsed.
+struct File {
+    /// The contents of the file.
+    contents: String,
+    /// The options of the `settings` table.
+    settings: BTreeMap<String, Setting>,
+    /// The jobs, with where each is defined when the file is TOML.
+    jobs: Vec<(Job, Option<Range<usize>>)>,
+}
+
+/// The prefix list that receives a job's IPv6 entries, apart from its IPv4 prefix list.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Ipv6Section {
+    /// The ID of the IPv6 prefix list.
+    prefix_list_id: String,
+    /// The description for the IPv6 entries.
+    description: Option<String>,
+    /// The length of the delegated IPv6 prefix written in place of IPv6 host addresses.
+    prefix_length: Option<u8>,
+    /// The IP detection service URL used for the IPv6 entries.
+    ip_service: Option<String>,
+}
+
+/// An independently monitored prefix list, with the options that differ from the command line.
+///
+/// Options a job leaves out are taken from the command line and environment.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// The name used to identify the job in logs.
+    pub name: String,
+    /// The AWS region of the job's prefix list.
+    region: Option<String>,
+    /// The named profile providing the job's credentials.
+    profile: Option<String>,
+    /// The IAM role the job assumes.
+    role_arn: Option<String>,
+    /// The prefix list ID to update, or `ssm:<parameter-name>`.
+    prefix_list_id: Option<String>,
+    /// The `Key=Value` tag locating the prefix list instead of its ID.
+    prefix_list_tag: Option<String>,
+    /// The description for the prefix list entry.
+    description: Option<String>,
+    /// The CIDR suffix used to format the IP address.
+    cidr_suffix: Option<u8>,
+    /// The length of the delegated IPv6 prefix written in place of IPv6 host addresses.
+    ipv6_prefix_length: Option<u8>,
+    /// The check interval in seconds.
+    interval: Option<u64>,
+    /// Where the IP comes from.
+    ip_source: Option<String>,
+    /// The IP detection service URLs.
+    ip_service: Option<String>,
+    /// How the IP service asked first is chosen.
+    ip_service_selection: Option<crate::ip_source::ServiceSelection>,
+    /// The address family detection requests are sent over.
+    detect_family: Option<crate::ip_source::DetectFamily>,
+    /// The local address detection requests are sent from.
+    bind_address: Option<std::net::IpAddr>,
+    /// The network interface HTTP detection requests are sent through.
+    bind_interface: Option<String>,
+    /// The uplinks monitored, each as `name=interface` or `name=address`.
+    uplinks: Option<Vec<String>>,
+    /// The prefix lists in other regions, each as `region:prefix-list-id`.
+    targets: Option<Vec<String>>,
+    /// A fixed IP address to use instead of detecting it.
+    ip: Option<String>,
+    /// The region of the resource tracked by the IP source.
+    ip_source_region: Option<String>,
+    /// The separate prefix list for IPv6 entries, if any.
+    ipv6: Option<Ipv6Section>,
+    /// The security groups that receive ingress rules.
+    security_group_ids: Option<Vec<String>>,
+    /// The `Key=Value` tag selecting additional security groups.
+    security_group_tag: Option<String>,
+    /// The protocols and ports opened in the security groups, e.g. `tcp/443`.
+    security_group_rules: Option<Vec<String>>,
+}
+
+/// A problem with one key of a job.
+struct Invalid {
+    /// The key at fault.
+    key: &'static str,
+    /// What is wrong with it.
+    message: String,
+}
+
+impl Invalid {
+    /// Creates a problem with a key.
+    fn new(key: &'static str, message: impl ToString) -> Self {
+        Self {
+            key,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Job {
+    /// Checks the values of the job on their own, and the options that cannot be combined.
+    ///
+    /// # Returns
+    ///
+    /// The first problem found, or `Ok(())` if the job is valid.
+    fn check(&self) -> Result<(), Invalid> {
+        let exclusive = [
+            (
+                "prefix_list_tag",
+                self.prefix_list_id.is_some() && self.prefix_list_tag.is_some(),
+                "prefix_list_id",
+            ),
+            (
+                "ip",
+                self.ip.is_some() && self.ip_source.is_some(),
+                "ip_source",
+            ),
+            (
+                "prefix_length",
+                self.ipv6_prefix_length.is_some()
+                    && self
+                        .ipv6
+                        .as_ref()
+                        .is_some_and(|ipv6| ipv6.prefix_length.is_some()),
+                "ipv6_prefix_length",
+            ),
+        ];
+        if let Some((key, _, other)) = exclusive.into_iter().find(|(_, both, _)| *both) {
+            return Err(Invalid::new(
+                key,
+                format!("cannot be combined with {}", other),
+            ));
+        }
+
+        if let Some(prefix_list_id) = &self.prefix_list_id {
+            crate::target::parse_prefix_list_id(prefix_list_id)
+                .map_err(|e| Invalid::new("prefix_list_id", e))?;
+        }
+        if let Some(ipv6) = &self.ipv6 {
+            crate::target::parse_prefix_list_id(&ipv6.prefix_list_id)
+                .map_err(|e| Invalid::new("prefix_list_id", e))?;
+        }
+        let ipv6_prefix_length = self
+            .ipv6
+            .as_ref()
+            .and_then(|ipv6| ipv6.prefix_length)
+            .map(|length| ("prefix_length", length))
+            .or(self
+                .ipv6_prefix_length
+                .map(|length| ("ipv6_prefix_length", length)));
+        if let Some((key, length)) = ipv6_prefix_length {
+            if !(1..=128).contains(&length) {
+                return Err(Invalid::new(key, "must be between 1 and 128"));
+            }
+        }
+        if self.interval == Some(0) {
+            return Err(Invalid::new("interval", "must be at least 1 second"));
+        }
+        for uplink in self.uplinks.iter().flatten() {
+            crate::ip_source::parse_uplink(uplink).map_err(|e| Invalid::new("uplinks", e))?;
+        }
+        for target in self.targets.iter().flatten() {
+            crate::target::parse_region_target(target).map_err(|e| Invalid::new("targets", e))?;
+        }
+        if let Some(tag) = &self.security_group_tag {
+            crate::security_group::parse_tag(tag)
+                .map_err(|e| Invalid::new("security_group_tag", e))?;
+        }
+        for rule in self.security_group_rules.iter().flatten() {
+            crate::security_group::parse_rule(rule)
+                .map_err(|e| Invalid::new("security_group_rules", e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the job uses a different region or identity than the command line.
+    pub fn has_own_credentials(&self) -> bool {
+        self.region.is_some() || self.profile.is_some() || self.role_arn.is_some()
+    }
+
+    /// Combines the job with the command line arguments it overrides.
+    ///
+    /// # Parameters
+    ///
+    /// * `base`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// The arguments the job runs with, or an error if the job's prefix list, IPv6 prefix length,
+    /// interval, uplinks, targets or security group options are not valid.
+    pub fn args(
+        &self,
+        base: &crate::Args,
+    ) -> Result<crate::Args, Box<dyn std::error::Error + Send + Sync>> {
+        let mut args = base.clone();
+
+        if self.region.is_some() {
+            args.region = self.region.clone();
+        }
+        if self.profile.is_some() {
+            args.profile = self.profile.clone();
+        }
+        if self.role_arn.is_some() {
+            args.role_arn = self.role_arn.clone();
+        }
+
+        // A job naming its prefix list replaces the command line's, however it was given
+        if self.prefix_list_id.is_some() || self.prefix_list_tag.is_some() {
+            args.prefix_list_id = self
+                .prefix_list_id
+                .as_deref()
+                .map(crate::target::parse_prefix_list_id)
+                .transpose()
+                .map_err(|e| format!("Job {}: {}", self.name, e))?;
+            args.prefix_list_tag = self.prefix_list_tag.clone();
+        }
+        if let Some(description) = &self.description {
+            args.description = description.clone();
+        }
+        if let Some(cidr_suffix) = self.cidr_suffix {
+            args.cidr_suffix = cidr_suffix;
+        }
+        let ipv6_prefix_length = self
+            .ipv6
+            .as_ref()
+            .and_then(|ipv6| ipv6.prefix_length)
+            .or(self.ipv6_prefix_length);
+        if let Some(length) = ipv6_prefix_length {
+            if !(1..=128).contains(&length) {
+                return Err(format!(
+                    "Job {}: IPv6 prefix length must be between 1 and 128",
+                    self.name
+                )
+                .into());
+            }
+            args.ipv6_prefix_length = Some(length);
+        }
+        if let Some(ipv6) = &self.ipv6 {
+            args.ipv6_prefix_list_id = Some(
+                crate::target::parse_prefix_list_id(&ipv6.prefix_list_id)
+                    .map_err(|e| format!("Job {}: {}", self.name, e))?,
+            );
+            if ipv6.description.is_some() {
+                args.ipv6_description = ipv6.description.clone();
+            }
+            if let Some(ip_service) = &ipv6.ip_service {
+                args.ipv6_ip_service = ip_service.clone();
+            }
+        }
+        if let Some(interval) = self.interval {
+            if interval == 0 {
+                return Err(
+                    format!("Job {}: interval must be at least 1 second", self.name).into(),
+                );
+            }
+            args.interval = interval;
+        }
+        if self.ip_source.is_some() || self.ip.is_some() {
+            args.ip_source = self.ip_source.clone().unwrap_or_else(|| "http".to_string());
+            args.ip = self.ip.clone();
+        }
+        if let Some(ip_service) = &self.ip_service {
+            args.ip_service = ip_service.clone();
+        }
+        if let Some(selection) = self.ip_service_selection {
+            args.ip_service_selection = selection;
+        }
+        if let Some(detect_family) = self.detect_family {
+            args.detect_family = detect_family;
+        }
+        if self.bind_address.is_some() {
+            args.bind_address = self.bind_address;
+        }
+        if self.bind_interface.is_some() {
+            args.bind_interface = self.bind_interface.clone();
+        }
+        if let Some(uplinks) = &self.uplinks {
+            args.uplinks = uplinks
+                .iter()
+                .map(|uplink| crate::ip_source::parse_uplink(uplink))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Job {}: {}", self.name, e))?;
+        }
+        if let Some(targets) = &self.targets {
+            args.targets = targets
+                .iter()
+                .map(|target| crate::target::parse_region_target(target))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Job {}: {}", self.name, e))?;
+        }
+        if self.ip_source_region.is_some() {
+            args.ip_source_region = self.ip_source_region.clone();
+        }
+        if let Some(ids) = &self.security_group_ids {
+            args.security_group_ids = ids.clone();
+        }
+        if let Some(tag) = &self.security_group_tag {
+            args.security_group_tag = Some(
+                crate::security_group::parse_tag(tag)
+                    .map_err(|e| format!("Job {}: {}", self.name, e))?,
+            );
+        }
+        if let Some(rules) = &self.security_group_rules {
+            args.security_group_rules = rules
+                .iter()
+                .map(|rule| crate::security_group::parse_rule(rule))
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("Job {}: {}", self.name, e))?;
+        }
+
+        if args.prefix_list_id.is_none()
+            && args.prefix_list_tag.is_none()
+            && args.targets.is_empty()
+        {
+            return Err(format!(
+                "Job {} has no prefix_list_id, prefix_list_tag or targets and none is set globally",
+                self.name
+            )
+            .into());
+        }
+
+        Ok(args)
+    }
+}
+
+/// Returns the line of a file a key is set on, searching the part that defines one job.
+///
+/// # Parameters
+///
+/// * `contents`: The contents of the file.
+/// * `header`: Where the job's `[[jobs]]` header is in the file.
+/// * `key`: The key to find.
+///
+/// # Returns
+///
+/// The 1-based line of the key, or of the job's header when the key is not found.
+fn line_of(contents: &str, header: &Range<usize>, key: &str) -> usize {
+    let header_line = contents[..header.start].matches('\n').count() + 1;
+
+    // The job runs from its header to the next array table, taking in its subtables
+    contents[header.end..]
+        .lines()
+        .skip(1)
+        .take_while(|line| !line.trim_start().starts_with("[["))
+        .position(|line| {
+            line.trim_start()
+                .strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with('='))
+        })
+        .map_or(header_line, |index| header_line + index + 1)
+}
+
+/// Suggests the key a misspelled one was meant to be.
+///
+/// # Parameters
+///
+/// * `message`: The parse error, which names an unknown field.
+///
+/// # Returns
+///
+/// The closest known key, if one is within two edits.
+fn suggestion(message: &str) -> Option<&'static str> {
+    let unknown = message.split_once("unknown field `")?.1.split_once('`')?.0;
+
+    JOB_KEYS
+        .iter()
+        .map(|key| (edit_distance(unknown, key), *key))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, key)| key)
+}
+
+/// Counts the single-character insertions, deletions and substitutions between two words.
+///
+/// # Parameters
+///
+/// * `a`: The first word.
+/// * `b`: The second word.
+///
+/// # Returns
+///
+/// The Levenshtein distance between the words.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Returns whether a configuration file is YAML rather than TOML, by its extension.
+///
+/// # Parameters
+///
+/// * `path`: The configuration file.
+///
+/// # Returns
+///
+/// `true` for `.yaml` and `.yml` files.
+fn is_yaml(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "yaml" | "yml"))
+}
+
+/// Reads and parses a TOML or YAML configuration file.
+///
+/// Unknown keys and values of the wrong type are reported with their line and column by the
+/// parser, with a suggestion for a likely typo.
+///
+/// # Parameters
+///
+/// * `path`: The configuration file.
+///
+/// # Returns
+///
+/// The parsed file, or an error if it cannot be read or is malformed.
+fn read(path: &Path) -> Result<File, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read configuration file {}: {}", path.display(), e))?;
+    let invalid = |message: &str, error: &dyn std::fmt::Display| {
+        // TOML errors end with the offending line, YAML errors with its position
+        let error = error.to_string();
+        let separator = if error.ends_with('\n') { "" } else { "\n" };
+        let hint = suggestion(message)
+            .map(|key| format!("{}did you mean `{}`?\n", separator, key))
+            .unwrap_or_default();
+        format!(
+            "Invalid configuration file {}: {}{}",
+            path.display(),
+            error,
+            hint
+        )
+    };
+
+    if is_yaml(path) {
+        let config: Config<Job> =
+            serde_norway::from_str(&contents).map_err(|e| invalid(&e.to_string(), &e))?;
+        return Ok(File {
+            contents,
+            settings: config.settings,
+            jobs: config.jobs.into_iter().map(|job| (job, None)).collect(),
+        });
+    }
+
+    let config: Config<toml::Spanned<Job>> =
+        toml::from_str(&contents).map_err(|e| invalid(e.message(), &e))?;
+    Ok(File {
+        contents,
+        settings: config.settings,
+        jobs: config
+            .jobs
+            .into_iter()
+            .map(|job| {
+                let span = job.span();
+                (job.into_inner(), Some(span))
+            })
+            .collect(),
+    })
+}
+
+/// Applies the `settings` table of a configuration file as if its options were given in the
+/// environment, so the environment and the command line take precedence over it.
+///
+/// # Parameters
+///
+/// * `path`: The configuration file.
+/// * `command`: The command line definition, mapping option names to their variables.
+///
+/// # Returns
+///
+/// An error if the file cannot be read or names an option that is unknown, has no
+/// environment variable or cannot take a list.
+pub fn apply_settings(
+    path: &Path,
+    command: &clap::Command,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let file = read(path)?;
+
+    for (key, value) in &file.settings {
+        let invalid = |message: String| {
+            format!(
+                "Invalid configuration file {}: settings: {}: {}",
+                path.display(),
+                key,
+                message
+            )
+        };
+        let names = |arg: &clap::Arg| {
+            let long = arg.get_long().map(|long| long.replace('-', "_"));
+            [Some(arg.get_id().to_string()), long]
+        };
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| names(arg).contains(&Some(key.clone())))
+        else {
+            let closest = command
+                .get_arguments()
+                .filter(|arg| arg.get_env().is_some())
+                .filter_map(|arg| arg.get_long())
+                .map(|long| long.replace('-', "_"))
+                .map(|name| (edit_distance(key, &name), name))
+                .filter(|(distance, _)| *distance <= 2)
+                .min();
+            let hint = closest
+                .map(|(_, name)| format!("; did you mean `{}`?", name))
+                .unwrap_or_default();
+            return Err(invalid(format!("unknown option{}", hint)).into());
+        };
+        if matches!(arg.get_id().as_str(), "config" | "env_file") {
+            return Err(invalid("cannot be set in the configuration file itself".into()).into());
+        }
+        let Some(variable) = arg.get_env() else {
+            return Err(invalid("can only be given on the command line".into()).into());
+        };
+        if matches!(value, Setting::List(_)) && arg.get_value_delimiter() != Some(',') {
+            return Err(invalid("takes a single value, not a list".into()).into());
+        }
+
+        if std::env::var_os(variable).is_none() {
+            std::env::set_var(variable, value.to_env());
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the jobs from a TOML or YAML configuration file.
+///
+/// Values that parse but are not valid, and options that cannot be combined, are reported
+/// with the line they are set on in TOML files.
+///
+/// # Parameters
+///
+/// * `path`: The configuration file.
+///
+/// # Returns
+///
+/// The jobs, none when the file only holds settings, or an error if the file cannot be read,
+/// is malformed, has an invalid job, defines neither settings nor jobs, or two jobs with the
+/// same name.
+pub fn load(path: &Path) -> Result<Vec<Job>, Box<dyn std::error::Error + Send + Sync>> {
+    let file = read(path)?;
+
+    if file.jobs.is_empty() && file.settings.is_empty() {
+        return Err(format!(
+            "Configuration file {} defines no settings or jobs",
+            path.display()
+        )
+        .into());
+    }
+
+    let location = |span: &Option<Range<usize>>, key: &str| match span {
+        Some(span) => format!("line {}: ", line_of(&file.contents, span, key)),
+        None => String::new(),
+    };
+    let mut names = HashSet::new();
+    for (job, span) in &file.jobs {
+        if let Err(invalid) = job.check() {
+            return Err(format!(
+                "Invalid configuration file {}: {}job {}: {}: {}",
+                path.display(),
+                location(span, invalid.key),
+                job.name,
+                invalid.key,
+                invalid.message
+            )
+            .into());
+        }
+        if !names.insert(job.name.as_str()) {
+            return Err(format!(
+                "Invalid configuration file {}: {}job name {} is used more than once",
+                path.display(),
+                location(span, "name"),
+                job.name
+            )
+            .into());
+        }
+    }
+
+    Ok(file.jobs.into_iter().map(|(job, _)| job).collect())
+}
@@ -0,0 +1,2214 @@
+//! Keeps AWS VPC managed prefix lists holding the current public IP addresses of a host.
+//!
+//! The `aws-vpc-prefix-list-monitor` binary is a thin wrapper around [`cli::main`]. Programs
+//! embedding the monitor build a [`PrefixListMonitor`] and check whenever they see fit, on
+//! their own tasks if they like, optionally detecting the addresses themselves through
+//! [`DetectIp`]:
+//!
+//! ```no_run
+//! use aws_vpc_prefix_list_monitor::{DetectIp, PrefixListMonitor};
+//! use futures::future::BoxFuture;
+//! use std::net::IpAddr;
+//!
+//! struct Router;
+//!
+//! impl DetectIp for Router {
+//!     fn detect(&self) -> BoxFuture<'_, Result<Vec<IpAddr>, Box<dyn std::error::Error + Send + Sync>>> {
+//!         Box::pin(async { Ok(vec!["203.0.113.7".parse()?]) })
+//!     }
+//! }
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let config = aws_config::load_from_env().await;
+//! let mut monitor = PrefixListMonitor::builder(&config)
+//!     .prefix_list_id("pl-0123456789abcdef0")
+//!     .description("Office uplink")
+//!     .ip_source(Router)
+//!     .build()
+//!     .await?;
+//! tokio::spawn(async move { monitor.check_and_update().await }).await??;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "chaos")]
+mod chaos;
+pub mod cli;
+mod clock;
+mod cloudflare;
+mod commands;
+mod control;
+mod credentials;
+mod destination;
+mod dyndns;
+mod endpoint_policy;
+mod errors;
+mod eventlog;
+mod events;
+mod guardduty;
+mod iam_policy;
+mod init;
+mod ip_source;
+mod jobs;
+mod metrics;
+#[cfg(target_os = "linux")]
+mod netwatch;
+mod network_firewall;
+mod notify;
+mod opensearch;
+mod partition;
+mod policy;
+mod publish;
+mod queue;
+mod replay;
+mod secrets;
+mod security_group;
+mod service;
+mod simulate;
+mod status;
+mod syslog;
+mod target;
+mod update;
+mod verify;
+
+use aws_config::{
+    provider_config::ProviderConfig,
+    sts::AssumeRoleProvider,
+    web_identity_token::{StaticConfiguration, WebIdentityTokenCredentialsProvider},
+    BehaviorVersion, SdkConfig,
+};
+use aws_sdk_ec2::config::SharedCredentialsProvider;
+use aws_smithy_async::time::{SharedTimeSource, TimeSource};
+use cli::Args;
+use control::Request;
+use destination::Destination;
+use futures::{stream, FutureExt, StreamExt, TryStreamExt};
+pub use ip_source::DetectIp;
+use ip_source::{AddressFamily, IpSource};
+use notify::Notifier;
+use publish::Publisher;
+use std::io::IsTerminal;
+use std::net::Ipv6Addr;
+use std::time::Duration;
+use target::{EntryChange, PrefixListRef, Target, UpdateOrder};
+use tokio::time::{self, Instant};
+use tracing::{debug, error, info, info_span, warn, Instrument, Span};
+use verify::Verifier;
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("Enable a TLS backend with the `rustls` or `native-tls` feature");
+
+/// The shortest wait between checks of DNS records with a very short or zero TTL.
+const MIN_TTL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The number of consecutive failed checks reported to the Windows Event Log as a failure.
+const PERSISTENT_FAILURES: u32 = 3;
+
+/// How many check intervals a check may take before the watchdog cancels it.
+const STALL_INTERVALS: u32 = 3;
+
+/// The least time a check is given before the watchdog cancels it, for short intervals.
+const MIN_STALL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The maximum length AWS accepts for a prefix list entry description.
+const MAX_DESCRIPTION_LENGTH: usize = 255;
+
+/// A struct representing a prefix list monitor.
+///
+/// This struct is used to monitor an external IP and update AWS VPC prefix list accordingly.
+/// Build one with [`PrefixListMonitor::builder`] and call
+/// [`check_and_update`](PrefixListMonitor::check_and_update) whenever the addresses should be
+/// checked.
+pub struct PrefixListMonitor {
+    /// The prefix lists that receive the managed entry.
+    targets: Vec<Target>,
+    /// How the prefix list is located in each target.
+    prefix_list_ref: PrefixListRef,
+    /// The role to assume in the owning account of a RAM-shared prefix list.
+    owner_role_name: Option<String>,
+    /// The session name used when assuming roles.
+    role_session_name: String,
+    /// Whether to roll back successfully updated targets when others fail.
+    rollback_on_partial_failure: bool,
+    /// The account updated and verified before the other targets, if any.
+    canary_account_id: Option<String>,
+    /// The maximum number of targets resolved or updated concurrently.
+    max_concurrency: usize,
+    /// The targets whose prefix list could not be located on the last check, with the errors.
+    unresolved: Vec<(usize, String)>,
+    /// Whether to ask the operator before modifying prefix lists.
+    confirm: bool,
+    /// Whether the operator is being asked, which holds off the watchdog.
+    prompting: tokio::sync::watch::Sender<bool>,
+    /// The order in which new entries are added and old ones removed.
+    update_order: UpdateOrder,
+    /// The shortest and longest check intervals when the interval adapts to changes.
+    adaptive_interval: Option<(Duration, Duration)>,
+    /// The description of the prefix list entry.
+    description: String,
+    /// The current external IP addresses, once applied to every target.
+    current_ips: Option<Vec<String>>,
+    /// The CIDR suffix used to format the IP address.
+    cidr_suffix: u8,
+    /// The length of the delegated IPv6 prefix written in place of IPv6 host addresses.
+    ipv6_prefix_length: Option<u8>,
+    /// The address family kept from the detected addresses, if limited to one.
+    address_family: Option<AddressFamily>,
+    /// The address family of the prefix list, once read.
+    list_family: Option<AddressFamily>,
+    /// The source of the IP address being published.
+    ip_source: IpSource,
+    /// The checks detected addresses must pass before they are applied.
+    verifier: Verifier,
+    /// The publisher that shares the current IP with other automation.
+    publisher: Publisher,
+    /// The resources other than prefix lists that hold the CIDRs.
+    destinations: Vec<Destination>,
+    /// The notifier that reports changes and failures to webhooks.
+    notifier: Notifier,
+    /// The name identifying the monitor in notifications and events.
+    name: String,
+    /// How often a status summary is logged, if at all.
+    summary_interval: Option<Duration>,
+    /// The clock stamping the history, events and published addresses.
+    time_source: SharedTimeSource,
+}
+
+impl PrefixListMonitor {
+    /// Creates a new instance of `PrefixListMonitor`.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct service clients.
+    /// * `targets`: The prefix lists that receive the managed entry.
+    /// * `prefix_list_ref`: How the prefix list is located in each target.
+    /// * `description`: The rendered description of the prefix list entry.
+    /// * `ip_source`: The source of the IP address being published.
+    /// * `verifier`: The checks detected addresses must pass before they are applied.
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `PrefixListMonitor`.
+    fn new(
+        config: &SdkConfig,
+        targets: Vec<Target>,
+        prefix_list_ref: PrefixListRef,
+        description: String,
+        ip_source: IpSource,
+        verifier: Verifier,
+        args: &Args,
+    ) -> Self {
+        let name = format!("{} ({})", prefix_list_ref, description);
+        let notifier = Notifier::new(args, name.clone());
+        let destinations = Destination::from_args(config, args, &description);
+
+        Self {
+            targets,
+            prefix_list_ref,
+            owner_role_name: args.owner_role_name.clone(),
+            role_session_name: args.role_session_name.clone(),
+            rollback_on_partial_failure: args.rollback_on_partial_failure,
+            canary_account_id: args.canary_account_id.clone(),
+            max_concurrency: args.max_concurrency.into(),
+            unresolved: Vec::new(),
+            confirm: args.confirm && !args.yes,
+            prompting: tokio::sync::watch::Sender::new(false),
+            update_order: args.update_order,
+            adaptive_interval: args.adaptive_interval.then(|| {
+                (
+                    Duration::from_secs(args.min_interval),
+                    Duration::from_secs(args.max_interval),
+                )
+            }),
+            description,
+            current_ips: None,
+            cidr_suffix: args.cidr_suffix,
+            ipv6_prefix_length: args.ipv6_prefix_length,
+            address_family: args.address_family,
+            list_family: None,
+            ip_source,
+            verifier,
+            publisher: Publisher::new(config, args),
+            destinations,
+            notifier,
+            name,
+            summary_interval: (args.summary_interval > 0)
+                .then(|| Duration::from_secs(args.summary_interval)),
+            time_source: config.time_source().unwrap_or_default(),
+        }
+    }
+
+    /// Retrieves the external IP addresses from the configured IP source.
+    ///
+    /// # Returns
+    ///
+    /// The external IP addresses of the monitored family, sorted, or an error if the request
+    /// fails or detects none of that family.
+    async fn get_external_ips(
+        &self,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        #[cfg(feature = "chaos")]
+        let detected = match chaos::detection_fault() {
+            Some(fault) => Err(fault.into()),
+            None => self.ip_source.detect().await,
+        };
+        #[cfg(not(feature = "chaos"))]
+        let detected = self.ip_source.detect().await;
+        metrics::record_detection(detected.is_ok());
+        status::record_services(&self.name, self.ip_source.service_health());
+        let ips = self.select_family(detected?)?;
+        events::emit(
+            "ip_detected",
+            &self.name,
+            self.time_source.now(),
+            serde_json::json!({ "ips": ips }),
+        );
+
+        Ok(ips)
+    }
+
+    /// Keeps the detected addresses of the monitored family.
+    ///
+    /// # Parameters
+    ///
+    /// * `ips`: The detected IP addresses.
+    ///
+    /// # Returns
+    ///
+    /// The addresses of the monitored family, or an error if there are none.
+    fn select_family(
+        &self,
+        mut ips: Vec<String>,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(family) = self.family() {
+            ips.retain(|ip| family.matches(ip));
+            if ips.is_empty() {
+                return Err(
+                    format!("No {} address detected from {}", family, self.ip_source).into(),
+                );
+            }
+        }
+
+        Ok(ips)
+    }
+
+    /// Formats IP addresses as CIDRs using the configured suffix.
+    ///
+    /// The suffix applies to IPv4 addresses. IPv6 addresses are formatted as the delegated
+    /// prefix containing them when its length is configured, and as single hosts otherwise.
+    ///
+    /// # Parameters
+    ///
+    /// * `ips`: The IP addresses to format.
+    ///
+    /// # Returns
+    ///
+    /// The CIDRs, in the same order, without duplicates from addresses in the same prefix.
+    fn to_cidrs(&self, ips: &[String]) -> Vec<String> {
+        let mut cidrs: Vec<String> = Vec::with_capacity(ips.len());
+        for ip in ips {
+            let cidr = match (ip.parse::<Ipv6Addr>(), self.ipv6_prefix_length) {
+                (Ok(ip), Some(length)) => format!("{}/{}", ipv6_network(ip, length), length),
+                (Ok(ip), None) => format!("{}/128", ip),
+                (Err(_), _) => format!("{}/{}", ip, self.cidr_suffix),
+            };
+            if !cidrs.contains(&cidr) {
+                cidrs.push(cidr);
+            }
+        }
+        cidrs
+    }
+
+    /// Brings a single target's prefix list in line with the new CIDRs.
+    ///
+    /// # Parameters
+    ///
+    /// * `target`: The prefix list to update.
+    /// * `new_cidrs`: The CIDRs the managed entries should consist of.
+    /// * `description`: The description identifying the managed entries.
+    /// * `order`: Whether to add and remove in one modification or one after the other.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(Some(change))` with the added and removed entries if the prefix list was modified, or
+    /// `Ok(None)` if it already contained exactly the CIDRs. An error if the request fails.
+    async fn update_target(
+        target: &Target,
+        new_cidrs: &[String],
+        description: &str,
+        order: UpdateOrder,
+    ) -> Result<Option<EntryChange>, Box<dyn std::error::Error + Send + Sync>> {
+        // Read the prefix list once; the update reuses its version unless it conflicts
+        let snapshot = target.snapshot().await?;
+        let current_entries = snapshot.managed_cidrs(description);
+        let change = EntryChange::between(&current_entries, new_cidrs);
+
+        // Check if the new CIDRs are already in the list
+        if change.is_empty() {
+            info!(
+                "CIDR {} already exists in prefix list {}",
+                new_cidrs.join(", "),
+                target.prefix_list_id
+            );
+            return Ok(None);
+        }
+
+        // Update prefix list
+        if !change.removed.is_empty() {
+            info!(
+                "Replacing {} old entries with new CIDR {}",
+                change.removed.len(),
+                change.added.join(", ")
+            );
+        } else {
+            info!("Adding new CIDR {} to prefix list", change.added.join(", "));
+        }
+
+        target
+            .update_prefix_list(
+                Some(snapshot),
+                &change.added,
+                &change.removed,
+                description,
+                order,
+            )
+            .await?;
+
+        Ok(Some(change))
+    }
+
+    /// Updates the canary target on its own and reads its entries back, before the others.
+    ///
+    /// # Parameters
+    ///
+    /// * `new_cidrs`: The CIDRs the managed entries should consist of.
+    ///
+    /// # Returns
+    ///
+    /// The index of the canary and its change if it was modified, `Ok(None)` without a canary
+    /// or if it already holds the CIDRs, or an error if it failed to update or does not hold
+    /// them afterwards.
+    async fn update_canary(
+        &mut self,
+        new_cidrs: &[String],
+    ) -> Result<Option<(usize, EntryChange)>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(index) = self.canary_account_id.as_ref().and_then(|account_id| {
+            self.targets
+                .iter()
+                .position(|target| &target.name == account_id)
+        }) else {
+            return Ok(None);
+        };
+        let target = &self.targets[index];
+        if target.applied_cidrs.as_deref() == Some(new_cidrs) {
+            return Ok(None);
+        }
+        let unresolved = self
+            .unresolved
+            .iter()
+            .find(|(unresolved, _)| *unresolved == index)
+            .map(|(_, e)| e.clone());
+
+        let result = async {
+            if let Some(e) = unresolved {
+                return Err(e.into());
+            }
+            let change =
+                Self::update_target(target, new_cidrs, &self.description, self.update_order)
+                    .await?;
+            let mut expected = new_cidrs.to_vec();
+            // The snapshot waits for the change to settle before reading every page back
+            let mut entries = target.snapshot().await?.managed_cidrs(&self.description);
+            expected.sort();
+            entries.sort();
+            if entries != expected {
+                return Err(format!(
+                    "read back {} instead of {}",
+                    entries.join(", "),
+                    expected.join(", ")
+                )
+                .into());
+            }
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(change)
+        }
+        .instrument(target_span(target, true))
+        .await;
+
+        match result {
+            Ok(change) => {
+                info!(
+                    "Canary {} holds {}; updating the other targets",
+                    target_label(target),
+                    new_cidrs.join(", ")
+                );
+                self.targets[index].applied_cidrs = Some(new_cidrs.to_vec());
+                Ok(change.map(|change| (index, change)))
+            }
+            Err(e) => {
+                // The other targets keep their entries; the canary is retried on the next check
+                let label = target_label(target);
+                let e = errors::describe(&*e);
+                error!(
+                    "Canary {} failed, other targets left unchanged: {}",
+                    label, e
+                );
+                self.record_targets(new_cidrs);
+                Err(format!("Canary {} failed, update aborted: {}", label, e).into())
+            }
+        }
+    }
+
+    /// Logs a structured summary of a partially failed update and, when configured, rolls the
+    /// targets modified during this check back to their previous entries.
+    ///
+    /// # Parameters
+    ///
+    /// * `new_cidrs`: The CIDRs that were being applied.
+    /// * `modified`: The targets modified during this check, with their changes.
+    /// * `failed`: The targets that failed, with their errors.
+    async fn report_partial_failure(
+        &mut self,
+        new_cidrs: &[String],
+        modified: &[(usize, EntryChange)],
+        failed: &[(usize, String)],
+    ) {
+        let applied = self
+            .targets
+            .iter()
+            .filter(|t| t.applied_cidrs.as_deref() == Some(new_cidrs))
+            .count();
+        error!(
+            "Partial failure applying {}: {} of {} prefix lists updated, {} failed",
+            new_cidrs.join(", "),
+            applied,
+            self.targets.len(),
+            failed.len()
+        );
+        for (index, e) in failed {
+            let target = &self.targets[*index];
+            error!(
+                "  failed: {} ({}): {}",
+                target.prefix_list_id, target.name, e
+            );
+        }
+
+        if !self.rollback_on_partial_failure {
+            for target in self
+                .targets
+                .iter()
+                .filter(|t| t.applied_cidrs.as_deref() == Some(new_cidrs))
+            {
+                info!(
+                    "  applied: {} ({}), only failed prefix lists will be retried",
+                    target.prefix_list_id, target.name
+                );
+            }
+            return;
+        }
+
+        for (index, change) in modified {
+            let target = &mut self.targets[*index];
+            match target
+                .update_prefix_list(
+                    None,
+                    &change.removed,
+                    &change.added,
+                    &self.description,
+                    self.update_order,
+                )
+                .await
+            {
+                Ok(()) => {
+                    warn!(
+                        "  rolled back: {} ({}) to {}",
+                        target.prefix_list_id,
+                        target.name,
+                        if change.removed.is_empty() {
+                            "no entry".to_string()
+                        } else {
+                            change.removed.join(", ")
+                        }
+                    );
+                    target.applied_cidrs = None;
+                }
+                Err(e) => error!(
+                    "  rollback failed: {} ({}): {}",
+                    target.prefix_list_id,
+                    target.name,
+                    errors::describe(&*e)
+                ),
+            }
+        }
+    }
+
+    /// Resolves the prefix list ID of every target, carrying on past those that fail.
+    ///
+    /// # Returns
+    ///
+    /// The indexes of the targets whose prefix list cannot be located, with the errors, or an
+    /// error if the located lists hold different address families.
+    async fn resolve_each_target(
+        &mut self,
+    ) -> Result<
+        Vec<(usize, Box<dyn std::error::Error + Send + Sync>)>,
+        Box<dyn std::error::Error + Send + Sync>,
+    > {
+        let multiple_targets = self.targets.len() > 1;
+        let owner_role = self
+            .owner_role_name
+            .as_deref()
+            .map(|role_name| (role_name, self.role_session_name.as_str()));
+
+        let prefix_list_ref = &self.prefix_list_ref;
+        // Collected first: a lazily mapped stream makes the check's future lose Send
+        let resolving: Vec<_> = self
+            .targets
+            .iter_mut()
+            .enumerate()
+            .map(|(index, target)| {
+                let span = target_span(target, multiple_targets);
+                target
+                    .resolve(prefix_list_ref, owner_role)
+                    .instrument(span)
+                    .map(move |result| (index, result))
+            })
+            .collect();
+        let mut results: Vec<_> = stream::iter(resolving)
+            .buffer_unordered(self.max_concurrency)
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+
+        let mut changed = false;
+        let mut failed = Vec::new();
+        for (index, result) in results {
+            match result {
+                Ok(target_changed) => changed |= target_changed,
+                Err(e) => failed.push((index, e)),
+            }
+        }
+        if changed {
+            // Re-run the update so the new list is populated
+            self.current_ips = None;
+            self.check_list_family()?;
+        }
+
+        Ok(failed)
+    }
+
+    /// Resolves the prefix list ID of every target.
+    ///
+    /// # Returns
+    ///
+    /// An error if any prefix list cannot be located, or `Ok(())` on success.
+    async fn resolve_targets(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let failed = self.resolve_each_target().await?;
+        self.unresolved = failed
+            .iter()
+            .map(|(index, e)| (*index, errors::describe(&**e)))
+            .collect();
+
+        match failed.into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Resolves the prefix list ID of every target for a check, which updates the targets
+    /// that resolve and reports the others as failed, so one unreachable account does not hold
+    /// back the rest.
+    ///
+    /// # Returns
+    ///
+    /// An error if no prefix list can be located, or `Ok(())` if at least one is.
+    async fn resolve_available_targets(
+        &mut self,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut failed = self.resolve_each_target().await?;
+        if !failed.is_empty() && failed.len() == self.targets.len() {
+            return Err(failed.swap_remove(0).1);
+        }
+
+        self.unresolved = Vec::with_capacity(failed.len());
+        for (index, e) in failed {
+            let e = errors::describe(&*e);
+            let target = &mut self.targets[index];
+            warn!("Cannot locate the prefix list of {}: {}", target.name, e);
+            // Pending until it resolves, and then checked against the CIDRs again
+            target.applied_cidrs = None;
+            self.unresolved.push((index, e));
+        }
+        if !self.unresolved.is_empty() {
+            // The update reports the unresolved targets and leaves the IP unrecorded
+            self.current_ips = None;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the address family of the resolved prefix lists, so only addresses they can hold
+    /// are detected.
+    ///
+    /// # Returns
+    ///
+    /// An error if the lists hold different families, or another family than the one
+    /// configured.
+    fn check_list_family(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut families = self
+            .targets
+            .iter()
+            .filter_map(|target| target.address_family.map(|family| (target, family)));
+        let Some((first, family)) = families.next() else {
+            return Ok(());
+        };
+        if let Some((other, other_family)) = families.find(|(_, other)| *other != family) {
+            return Err(format!(
+                "Prefix list {} holds {} addresses but {} holds {}; manage them with separate \
+                 monitors",
+                target_label(first),
+                family,
+                target_label(other),
+                other_family
+            )
+            .into());
+        }
+        if let Some(configured) = self
+            .address_family
+            .filter(|configured| *configured != family)
+        {
+            return Err(format!(
+                "Prefix list {} holds {} addresses, but the monitor is limited to {}",
+                target_label(first),
+                family,
+                configured
+            )
+            .into());
+        }
+
+        if self.list_family != Some(family) {
+            debug!(
+                "Prefix list {} holds {} addresses",
+                target_label(first),
+                family
+            );
+        }
+        self.list_family = Some(family);
+        Ok(())
+    }
+
+    /// Compares every target's managed entries against the entries the monitor would maintain,
+    /// reporting any drift without modifying the prefix lists.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if any target has drifted, or `Ok(false)` if all are in sync.
+    /// An error if the request fails.
+    async fn audit(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.resolve_targets().await?;
+
+        let external_ips = self.get_external_ips().await?;
+        let expected_cidrs = self.to_cidrs(&external_ips);
+        println!(
+            "Expected entries: {} (\"{}\")",
+            expected_cidrs.join(", "),
+            self.description
+        );
+
+        let mut drift = false;
+        for target in &self.targets {
+            let entries = target.get_current_entries(&self.description).await?;
+            let change = EntryChange::between(&entries, &expected_cidrs);
+
+            if change.is_empty() {
+                println!("{} ({}): in sync", target.prefix_list_id, target.name);
+                continue;
+            }
+
+            drift = true;
+            let mut problems = Vec::new();
+            if !change.added.is_empty() {
+                problems.push(format!("missing {}", change.added.join(", ")));
+            }
+            if !change.removed.is_empty() {
+                problems.push(format!("stale {}", change.removed.join(", ")));
+            }
+            println!(
+                "{} ({}): drift: {}",
+                target.prefix_list_id,
+                target.name,
+                problems.join("; ")
+            );
+        }
+
+        Ok(drift)
+    }
+
+    /// Starts building a monitor for embedding in another program.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct service clients; its region is the
+    ///   region of the prefix list.
+    ///
+    /// # Returns
+    ///
+    /// A builder with every option at the default of the command line.
+    pub fn builder(config: &SdkConfig) -> PrefixListMonitorBuilder {
+        PrefixListMonitorBuilder {
+            config: config.clone(),
+            prefix_list_id: None,
+            prefix_list_tag: None,
+            description: None,
+            cidr_suffix: None,
+            ipv6_prefix_length: None,
+            targets: Vec::new(),
+            ip_service: None,
+            ip_source: None,
+            time_source: None,
+        }
+    }
+
+    /// Checks the IP address and updates the prefix list accordingly.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the IP address has changed, or `Ok(false)` if it hasn't.
+    /// An error if the request fails.
+    pub async fn check_and_update(
+        &mut self,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        self.resolve_available_targets().await?;
+        for destination in &mut self.destinations {
+            if destination.resolve().await? {
+                // Re-run the update so new resources receive the CIDRs
+                self.current_ips = None;
+            }
+        }
+
+        // Get current external IPs
+        let external_ips = self.get_external_ips().await?;
+        self.update_to(external_ips).await
+    }
+
+    /// Updates the prefix lists to detected IP addresses, if they changed since the last
+    /// update.
+    ///
+    /// # Parameters
+    ///
+    /// * `external_ips`: The detected IP addresses of the monitored family.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the IP address has changed, or `Ok(false)` if it hasn't.
+    /// An error if the addresses fail verification or the update fails.
+    async fn update_to(
+        &mut self,
+        external_ips: Vec<String>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let new_cidrs = self.to_cidrs(&external_ips);
+
+        debug!("Detected external IP: {}", external_ips.join(", "));
+
+        // Check if IP has changed
+        if self.current_ips.as_ref() == Some(&external_ips) {
+            debug!("IP unchanged: {}", external_ips.join(", "));
+            return Ok(false);
+        }
+
+        info!(
+            ip = %external_ips.join(", "),
+            "IP change detected: {} -> {}",
+            self.current_ips
+                .as_ref()
+                .map(|ips| ips.join(", "))
+                .unwrap_or_else(|| "none".to_string()),
+            external_ips.join(", ")
+        );
+
+        status::record_history(
+            &self.name,
+            status::HistoryKind::IpChanged,
+            self.current_ips.as_deref(),
+            &external_ips,
+            self.time_source.now(),
+        );
+
+        // An address failing verification is left unrecorded so it is checked again
+        self.verifier.verify(&external_ips).await?;
+
+        self.apply(external_ips, new_cidrs).await
+    }
+
+    /// Applies CIDRs to every target, publishes them and records the IP addresses as current.
+    ///
+    /// # Parameters
+    ///
+    /// * `external_ips`: The IP addresses being applied.
+    /// * `new_cidrs`: The CIDRs the managed entries should consist of.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if any prefix list was modified, or `Ok(false)` if all were in sync.
+    /// An error if the request fails.
+    async fn apply(
+        &mut self,
+        external_ips: Vec<String>,
+        new_cidrs: Vec<String>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        // Held back like a declined update, so the first check after resuming applies it
+        if control::paused() {
+            warn!(
+                "Updates paused; not applying {} until resumed",
+                new_cidrs.join(", ")
+            );
+            return Ok(false);
+        }
+
+        // A declined update leaves the IP unrecorded, so the next check asks again
+        if self.confirm && !self.confirm_changes(&new_cidrs).await? {
+            return Err("Update declined; prefix lists not modified".into());
+        }
+
+        let multiple_targets = self.targets.len() > 1;
+        let mut updated = false;
+        // Targets modified during this check, with their changes
+        let mut modified: Vec<(usize, EntryChange)> = Vec::new();
+        // Targets whose prefix list cannot be located count as failed without being tried
+        let mut failed: Vec<(usize, String)> = self.unresolved.clone();
+
+        // A change that breaks the canary stops there, before reaching the other accounts
+        if let Some(change) = self.update_canary(&new_cidrs).await? {
+            updated = true;
+            modified.push(change);
+        }
+
+        // Targets that succeeded on an earlier attempt are not touched again
+        let pending = self
+            .targets
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| target.applied_cidrs.as_ref() != Some(&new_cidrs))
+            .filter(|(index, _)| !failed.iter().any(|(failed, _)| failed == index))
+            .map(|(index, target)| {
+                let span = target_span(target, multiple_targets);
+                Self::update_target(target, &new_cidrs, &self.description, self.update_order)
+                    .instrument(span)
+                    .map(move |result| (index, result))
+            })
+            .collect::<Vec<_>>();
+        let mut results: Vec<_> = stream::iter(pending)
+            .buffer_unordered(self.max_concurrency)
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+
+        for (index, result) in results {
+            match result {
+                Ok(change) => {
+                    self.targets[index].applied_cidrs = Some(new_cidrs.clone());
+                    if let Some(change) = change {
+                        updated = true;
+                        modified.push((index, change));
+                    }
+                }
+                Err(e) => failed.push((index, errors::describe(&*e))),
+            }
+        }
+        failed.sort_by_key(|(index, _)| *index);
+
+        let mut failures: Vec<(String, String)> = failed
+            .iter()
+            .map(|(index, e)| (target_label(&self.targets[*index]), e.clone()))
+            .collect();
+        if !failed.is_empty() {
+            self.report_partial_failure(&new_cidrs, &modified, &failed)
+                .await;
+        }
+
+        // Rolled back prefix lists leave the other destinations as they were
+        if failed.is_empty() || !self.rollback_on_partial_failure {
+            for destination in self
+                .destinations
+                .iter_mut()
+                .filter(|destination| destination.applied_cidrs.as_ref() != Some(&new_cidrs))
+            {
+                match destination.apply(&new_cidrs).await {
+                    Ok(changed) => {
+                        destination.applied_cidrs = Some(new_cidrs.clone());
+                        updated |= changed;
+                    }
+                    Err(e) => {
+                        let e = errors::describe(&*e);
+                        error!("Failed to update {}: {}", destination, e);
+                        failures.push((destination.to_string(), e));
+                    }
+                }
+            }
+        }
+        self.record_targets(&new_cidrs);
+
+        // Leave the IP unrecorded so failed targets are retried on the next check
+        let total = self.targets.len() + self.destinations.len();
+        if let [(_, e)] = failures.as_slice() {
+            if total == 1 {
+                return Err(e.clone().into());
+            }
+        }
+        if !failures.is_empty() {
+            return Err(format!(
+                "Partially applied: {} of {} targets failed to update ({})",
+                failures.len(),
+                total,
+                failures
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+            .into());
+        }
+
+        let prefix_list_id = self
+            .targets
+            .first()
+            .map(|t| t.prefix_list_id.as_str())
+            .unwrap_or_default();
+        self.publisher
+            .publish(
+                &external_ips,
+                &new_cidrs,
+                prefix_list_id,
+                self.time_source.now(),
+            )
+            .await?;
+        self.current_ips = Some(external_ips);
+
+        Ok(updated)
+    }
+
+    /// Records which prefix lists and destinations hold the CIDRs being applied, for the
+    /// status and metrics.
+    ///
+    /// # Parameters
+    ///
+    /// * `new_cidrs`: The CIDRs being applied.
+    fn record_targets(&self, new_cidrs: &[String]) {
+        let pending: Vec<String> = self
+            .targets
+            .iter()
+            .filter(|target| target.applied_cidrs.as_deref() != Some(new_cidrs))
+            .map(target_label)
+            .chain(
+                self.destinations
+                    .iter()
+                    .filter(|destination| destination.applied_cidrs.as_deref() != Some(new_cidrs))
+                    .map(Destination::to_string),
+            )
+            .collect();
+        let applied = self.targets.len() + self.destinations.len() - pending.len();
+
+        metrics::record_targets(&self.name, applied, pending.len());
+        status::record_targets(&self.name, pending);
+    }
+
+    /// Shows the entry changes an update would make and asks the operator to approve them.
+    ///
+    /// # Parameters
+    ///
+    /// * `new_cidrs`: The CIDRs the managed entries should consist of.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the operator approved or nothing would change, `Ok(false)` if they
+    /// declined, or an error if a prefix list cannot be read or stdin is not a terminal.
+    async fn confirm_changes(
+        &self,
+        new_cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        if !std::io::stdin().is_terminal() {
+            return Err(
+                "--confirm needs an interactive terminal; pass --yes to apply without asking"
+                    .into(),
+            );
+        }
+
+        let pending = self
+            .targets
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| target.applied_cidrs.as_deref() != Some(new_cidrs))
+            .filter(|(index, _)| {
+                !self
+                    .unresolved
+                    .iter()
+                    .any(|(unresolved, _)| unresolved == index)
+            })
+            .map(|(_, target)| async move {
+                let snapshot = target.snapshot().await?;
+                let change =
+                    EntryChange::between(&snapshot.managed_cidrs(&self.description), new_cidrs);
+                Ok::<_, Box<dyn std::error::Error + Send + Sync>>((target, change))
+            })
+            .collect::<Vec<_>>();
+        let plans: Vec<_> = stream::iter(pending)
+            .buffered(self.max_concurrency)
+            .try_collect()
+            .await?;
+
+        let mut planned = false;
+        for (target, change) in plans.iter().filter(|(_, change)| !change.is_empty()) {
+            planned = true;
+            if self.targets.len() > 1 {
+                println!("Prefix list {} ({}):", target.prefix_list_id, target.name);
+            } else {
+                println!("Prefix list {}:", target.prefix_list_id);
+            }
+            for cidr in &change.added {
+                println!("+ {:<20} {}", cidr, self.description);
+            }
+            for cidr in &change.removed {
+                println!("- {:<20} {}", cidr, self.description);
+            }
+        }
+        if !planned {
+            return Ok(true);
+        }
+
+        // Reading stdin blocks, which would stall a current-thread runtime's other tasks
+        self.prompting.send_replace(true);
+        let answer =
+            tokio::task::spawn_blocking(|| commands::confirm("Apply these changes?")).await;
+        self.prompting.send_replace(false);
+
+        Ok(answer??)
+    }
+
+    /// Applies an IP address or CIDR requested from outside instead of detecting it.
+    ///
+    /// # Parameters
+    ///
+    /// * `value`: The IP address, formatted like a detected one, or CIDR to apply.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if any prefix list was modified, `Ok(false)` if all were in sync or the
+    /// address belongs to another monitor's family, or an error if the value is invalid, fails
+    /// verification or a request fails.
+    async fn apply_requested_ip(
+        &mut self,
+        value: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let (ip, cidrs) = self.requested_cidrs(value)?;
+        if let Some(family) = self.family().filter(|family| !family.matches(&ip)) {
+            debug!("Ignoring requested IP {} outside {}", ip, family);
+            return Ok(false);
+        }
+        info!(ip = %value, "Applying requested IP {}", value);
+
+        self.verifier.verify(std::slice::from_ref(&ip)).await?;
+        self.apply(vec![ip], cidrs).await
+    }
+
+    /// Formats an IP address or CIDR requested from outside like a detected address.
+    ///
+    /// A bare address gets the same suffix or delegated prefix as a detected one, while a
+    /// CIDR is kept as given.
+    ///
+    /// # Parameters
+    ///
+    /// * `value`: The IP address or CIDR.
+    ///
+    /// # Returns
+    ///
+    /// The address and its CIDRs, or an error if the value is invalid.
+    fn requested_cidrs(
+        &self,
+        value: &str,
+    ) -> Result<(String, Vec<String>), Box<dyn std::error::Error + Send + Sync>> {
+        let cidr = commands::parse_cidr(value)?;
+        let ip = cidr.split('/').next().unwrap_or_default().to_string();
+        let cidrs = if value.contains('/') {
+            vec![cidr]
+        } else {
+            self.to_cidrs(std::slice::from_ref(&ip))
+        };
+        Ok((ip, cidrs))
+    }
+
+    /// Returns the address family the monitor writes, if limited to one by the configuration
+    /// or by its prefix lists.
+    fn family(&self) -> Option<AddressFamily> {
+        self.address_family.or(self.list_family)
+    }
+
+    /// Forgets which IP addresses were applied, so the next check rewrites and republishes
+    /// them even if the IP is unchanged.
+    fn forget_applied(&mut self) {
+        self.current_ips = None;
+        for target in &mut self.targets {
+            target.applied_cidrs = None;
+        }
+        for destination in &mut self.destinations {
+            destination.applied_cidrs = None;
+        }
+    }
+
+    /// Determines how long to wait before the next check.
+    ///
+    /// With an adaptive interval, a change drops the interval to the minimum, since changes
+    /// tend to come in bursts, and every stable check doubles it up to the maximum.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval`: The interval waited before the last check.
+    /// * `changed`: Whether the last check updated a prefix list.
+    ///
+    /// # Returns
+    ///
+    /// The interval to wait.
+    fn next_interval(&self, interval: Duration, changed: bool) -> Duration {
+        match self.adaptive_interval {
+            Some((min, _)) if changed => min,
+            Some((min, max)) => (interval * 2).clamp(min, max),
+            None => interval,
+        }
+    }
+
+    /// Runs the program in a loop until stopped.
+    ///
+    /// # Parameters
+    ///
+    /// * `interval`: The check interval in seconds, or the initial one when it adapts.
+    /// * `once`: Whether to run once and exit.
+    ///
+    /// # Returns
+    ///
+    /// An error if the request fails, or `Ok(())` on success.
+    async fn run(
+        &mut self,
+        interval: Duration,
+        once: bool,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        info!("Starting prefix list monitor");
+        info!("Prefix List: {}", self.prefix_list_ref);
+        if self.targets.len() > 1 {
+            info!(
+                "Accounts: {}",
+                self.targets
+                    .iter()
+                    .map(|t| t.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        info!("Description: {}", self.description);
+        match self.adaptive_interval {
+            Some((min, max)) => info!(
+                "Check interval: adaptive between {}s and {}s, starting at {}s",
+                min.as_secs(),
+                max.as_secs(),
+                interval.as_secs()
+            ),
+            None => info!("Check interval: {}s", interval.as_secs()),
+        }
+        info!("IP source: {}", self.ip_source);
+        eventlog::report(
+            eventlog::Event::Started,
+            &format!("Started monitoring {}", self.name),
+        );
+
+        let mut interval = interval;
+        let mut requests = control::subscribe();
+        // An IP requested from outside, applied instead of the next detection
+        let mut requested_ip: Option<String> = None;
+        let mut failures = 0;
+        // Quiet stretches log nothing, so a summary shows the daemon is alive
+        let mut summary_start = Instant::now();
+        let (mut summary_checks, mut summary_failures) = (0, 0);
+        let mut last_change: Option<Instant> = None;
+        let mut prompting = self.prompting.subscribe();
+        loop {
+            // Changes held back over a finished digest interval are summarized first
+            self.notifier.flush(self.current_ips.as_deref()).await;
+
+            // A failed check keeps the current interval
+            let mut next = interval;
+            let previous = self.current_ips.clone();
+            events::emit(
+                "check_started",
+                &self.name,
+                self.time_source.now(),
+                serde_json::json!({}),
+            );
+            // A hung DNS lookup or wedged connection would otherwise stop every later check;
+            // dropping the check cancels its requests, and the next one starts afresh
+            let stall_timeout = (interval * STALL_INTERVALS).max(MIN_STALL_TIMEOUT);
+            let finished = {
+                let check = async {
+                    match requested_ip.take() {
+                        Some(value) => self.apply_requested_ip(&value).await,
+                        None => self.check_and_update().await,
+                    }
+                };
+                tokio::pin!(check);
+                let mut deadline = Instant::now() + stall_timeout;
+                loop {
+                    tokio::select! {
+                        result = &mut check => break Some(result),
+                        // Time answering --confirm is not a stall, so the clock restarts after it
+                        Ok(()) = prompting.changed() => deadline = Instant::now() + stall_timeout,
+                        _ = time::sleep_until(deadline), if !*prompting.borrow() => break None,
+                    }
+                }
+            };
+            let result = match finished {
+                Some(result) => result,
+                None => {
+                    warn!(
+                        "Watchdog: check made no progress in {}, cancelling it",
+                        notify::format_duration(stall_timeout)
+                    );
+                    metrics::record_stall(&self.name);
+                    status::record_stall(&self.name);
+                    Err(format!(
+                        "Check stalled for {} and was cancelled by the watchdog",
+                        notify::format_duration(stall_timeout)
+                    )
+                    .into())
+                }
+            };
+            metrics::record_check(result.is_ok());
+            match result {
+                Ok(updated) => {
+                    if failures >= PERSISTENT_FAILURES {
+                        eventlog::report(
+                            eventlog::Event::Recovered,
+                            &format!("{}: checks succeed again", self.name),
+                        );
+                    }
+                    failures = 0;
+                    status::record_check(&self.name, self.current_ips.as_deref(), Ok(updated));
+                    if updated {
+                        metrics::record_update(&self.name);
+                        last_change = Some(Instant::now());
+                        info!("✓ Prefix list updated successfully");
+                        if let Some(current) = &self.current_ips {
+                            status::record_history(
+                                &self.name,
+                                status::HistoryKind::UpdateApplied,
+                                previous.as_deref(),
+                                current,
+                                self.time_source.now(),
+                            );
+                            eventlog::report(
+                                eventlog::Event::Changed,
+                                &format!(
+                                    "{}: IP changed from {} to {}",
+                                    self.name,
+                                    previous
+                                        .as_ref()
+                                        .map(|ips| ips.join(", "))
+                                        .unwrap_or_else(|| "unknown".to_string()),
+                                    current.join(", ")
+                                ),
+                            );
+                            events::emit(
+                                "update_applied",
+                                &self.name,
+                                self.time_source.now(),
+                                serde_json::json!({ "previous": previous, "current": current }),
+                            );
+                            self.notifier.changed(previous.as_deref(), current).await;
+                        }
+                    }
+                    next = self.next_interval(interval, updated);
+                    // Mirrored DNS records are checked again when they expire
+                    if let Some(ttl) = self.ip_source.refresh_after() {
+                        next = ttl.max(MIN_TTL_INTERVAL);
+                    }
+                }
+                Err(e) => {
+                    let error = errors::describe(&*e);
+                    error!("Error during check: {}", error);
+                    if errors::is_credentials_failure(&*e) {
+                        credentials::explain_once().await;
+                    }
+                    events::emit(
+                        "error",
+                        &self.name,
+                        self.time_source.now(),
+                        serde_json::json!({ "error": error }),
+                    );
+                    // A single failure is routine; only a run of them is worth an operator's look
+                    failures += 1;
+                    summary_failures += 1;
+                    status::record_check(&self.name, self.current_ips.as_deref(), Err(&error));
+                    if failures == PERSISTENT_FAILURES {
+                        eventlog::report(
+                            eventlog::Event::Failing,
+                            &format!(
+                                "{}: {} consecutive checks failed: {}",
+                                self.name, failures, error
+                            ),
+                        );
+                    }
+                    self.notifier.failed(&error).await;
+                }
+            }
+
+            summary_checks += 1;
+            if let Some(every) = self.summary_interval {
+                if summary_start.elapsed() >= every {
+                    info!(
+                        "Status: IP {}, last changed {}, {} checks and {} failures in the last {}",
+                        self.current_ips
+                            .as_ref()
+                            .map(|ips| ips.join(", "))
+                            .unwrap_or_else(|| "unknown".to_string()),
+                        last_change
+                            .map(|at| format!("{} ago", notify::format_duration(at.elapsed())))
+                            .unwrap_or_else(|| "never since startup".to_string()),
+                        summary_checks,
+                        summary_failures,
+                        notify::format_duration(summary_start.elapsed())
+                    );
+                    summary_start = Instant::now();
+                    (summary_checks, summary_failures) = (0, 0);
+                }
+            }
+
+            if once {
+                info!("Running in once mode, exiting");
+                break;
+            }
+
+            if next != interval {
+                debug!("Next check in {}s", next.as_secs());
+            }
+            interval = next;
+            tokio::select! {
+                _ = time::sleep(interval) => {}
+                Ok(request) = requests.recv() => match request {
+                    Request::Check => info!("Immediate check requested"),
+                    Request::Reconcile => {
+                        info!("Reconciling prefix lists");
+                        self.forget_applied();
+                    }
+                    Request::SetIp(value) => requested_ip = Some(value),
+                },
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a [`PrefixListMonitor`] from the options a program embedding it sets, leaving the
+/// others at the defaults of the command line.
+///
+/// Unlike the command line, the builder does not read the environment.
+pub struct PrefixListMonitorBuilder {
+    /// The AWS configuration used to construct service clients.
+    config: SdkConfig,
+    /// The prefix list ID to update, or `ssm:<parameter-name>`.
+    prefix_list_id: Option<String>,
+    /// The `Key=Value` tag locating the prefix list instead of its ID.
+    prefix_list_tag: Option<String>,
+    /// The description of the managed entries.
+    description: Option<String>,
+    /// The CIDR suffix used to format IPv4 addresses.
+    cidr_suffix: Option<u8>,
+    /// The length of the delegated IPv6 prefix written in place of IPv6 host addresses.
+    ipv6_prefix_length: Option<u8>,
+    /// The prefix lists in other regions, as `region:prefix-list-id`.
+    targets: Vec<String>,
+    /// The IP detection service URLs, comma-separated.
+    ip_service: Option<String>,
+    /// The source of the IP addresses replacing the built-in detection.
+    ip_source: Option<Box<dyn DetectIp>>,
+    /// The clock signing requests and stamping history and published addresses, if not the
+    /// configuration's.
+    time_source: Option<SharedTimeSource>,
+}
+
+impl PrefixListMonitorBuilder {
+    /// Sets the prefix list to update.
+    ///
+    /// # Parameters
+    ///
+    /// * `prefix_list_id`: The prefix list ID, or `ssm:<parameter-name>` to read it from SSM
+    ///   Parameter Store.
+    pub fn prefix_list_id(mut self, prefix_list_id: impl Into<String>) -> Self {
+        self.prefix_list_id = Some(prefix_list_id.into());
+        self
+    }
+
+    /// Locates the prefix list by a tag instead of its ID.
+    ///
+    /// # Parameters
+    ///
+    /// * `tag`: The tag as `Key=Value`, carried by exactly one prefix list.
+    pub fn prefix_list_tag(mut self, tag: impl Into<String>) -> Self {
+        self.prefix_list_tag = Some(tag.into());
+        self
+    }
+
+    /// Sets the description that identifies the managed entries.
+    ///
+    /// # Parameters
+    ///
+    /// * `description`: The description, optionally containing `{host}`.
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Sets the CIDR suffix IPv4 addresses are written with.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidr_suffix`: The suffix, e.g. 32 for a single host.
+    pub fn cidr_suffix(mut self, cidr_suffix: u8) -> Self {
+        self.cidr_suffix = Some(cidr_suffix);
+        self
+    }
+
+    /// Writes the delegated IPv6 network in place of detected IPv6 host addresses.
+    ///
+    /// # Parameters
+    ///
+    /// * `length`: The prefix length delegated to the site, from 1 to 128.
+    pub fn ipv6_prefix_length(mut self, length: u8) -> Self {
+        self.ipv6_prefix_length = Some(length);
+        self
+    }
+
+    /// Adds a prefix list in another region, updated along with the first.
+    ///
+    /// # Parameters
+    ///
+    /// * `region`: The region of the prefix list.
+    /// * `prefix_list_id`: The prefix list ID, or `ssm:<parameter-name>` read in that region.
+    pub fn target(mut self, region: &str, prefix_list_id: &str) -> Self {
+        self.targets.push(format!("{}:{}", region, prefix_list_id));
+        self
+    }
+
+    /// Sets the HTTP services that detect the IP address.
+    ///
+    /// # Parameters
+    ///
+    /// * `ip_service`: The service URLs, comma-separated, asked in order.
+    pub fn ip_service(mut self, ip_service: impl Into<String>) -> Self {
+        self.ip_service = Some(ip_service.into());
+        self
+    }
+
+    /// Replaces the built-in detection with a source of the program's own.
+    ///
+    /// # Parameters
+    ///
+    /// * `ip_source`: The source of the IP addresses.
+    pub fn ip_source(mut self, ip_source: impl DetectIp + 'static) -> Self {
+        self.ip_source = Some(Box::new(ip_source));
+        self
+    }
+
+    /// Replaces the wall clock, for instance with one following tokio's paused time in tests.
+    ///
+    /// The clock signs the monitor's AWS requests and stamps the history, events and
+    /// published addresses. Waits and intervals always follow tokio's clock.
+    ///
+    /// # Parameters
+    ///
+    /// * `time_source`: The clock.
+    pub fn time_source(mut self, time_source: impl TimeSource + 'static) -> Self {
+        self.time_source = Some(SharedTimeSource::new(time_source));
+        self
+    }
+
+    /// Builds the monitor.
+    ///
+    /// # Returns
+    ///
+    /// The monitor, or an error if no prefix list is set or an option is not valid.
+    pub async fn build(
+        self,
+    ) -> Result<PrefixListMonitor, Box<dyn std::error::Error + Send + Sync>> {
+        let mut args = Args::defaults()?;
+        args.prefix_list_id = self
+            .prefix_list_id
+            .as_deref()
+            .map(target::parse_prefix_list_id)
+            .transpose()?;
+        args.prefix_list_tag = self.prefix_list_tag;
+        if let Some(description) = self.description {
+            args.description = description;
+        }
+        if let Some(cidr_suffix) = self.cidr_suffix {
+            args.cidr_suffix = cidr_suffix;
+        }
+        if let Some(length) = self.ipv6_prefix_length {
+            if !(1..=128).contains(&length) {
+                return Err("IPv6 prefix length must be between 1 and 128".into());
+            }
+            args.ipv6_prefix_length = Some(length);
+        }
+        args.targets = self
+            .targets
+            .iter()
+            .map(|value| target::parse_region_target(value))
+            .collect::<Result<_, _>>()?;
+        if let Some(ip_service) = self.ip_service {
+            args.ip_service = ip_service;
+        }
+
+        let config = match self.time_source {
+            Some(time_source) => self.config.to_builder().time_source(time_source).build(),
+            None => self.config,
+        };
+        let mut monitor = build_monitor(&config, &args).await?;
+        if let Some(ip_source) = self.ip_source {
+            monitor.ip_source = IpSource::Custom(ip_source);
+        }
+
+        Ok(monitor)
+    }
+}
+
+/// Builds the HTTP client for AWS API calls on the platform's native TLS library.
+///
+/// # Returns
+///
+/// The HTTP client.
+#[cfg(feature = "native-tls")]
+fn native_tls_http_client() -> aws_sdk_ec2::config::SharedHttpClient {
+    aws_smithy_http_client::hyper_014::HyperClientBuilder::new()
+        .build(hyper_tls::HttpsConnector::new())
+}
+
+/// Loads the AWS SDK configuration, applying any explicitly configured credential source.
+///
+/// Every client built from the configuration, including those for other regions and assumed
+/// roles, shares its HTTP client and therefore its connection pools.
+///
+/// # Parameters
+///
+/// * `args`: The arguments passed to the program.
+/// * `http_client`: An HTTP client to share with configurations loaded earlier, if any.
+///
+/// # Returns
+///
+/// The `SdkConfig` used to construct AWS service clients.
+async fn load_aws_config(
+    args: &Args,
+    http_client: Option<aws_sdk_ec2::config::SharedHttpClient>,
+) -> SdkConfig {
+    // Pinned so SDK updates do not change retries and timeouts, although newer ones deprecate it
+    #[allow(deprecated)]
+    let mut loader = aws_config::defaults(BehaviorVersion::v2025_08_07());
+    if let Some(region) = &args.region {
+        loader = loader.region(aws_config::Region::new(region.clone()));
+    }
+    if let Some(profile) = &args.profile {
+        loader = loader.profile_name(profile);
+    }
+    if args.use_fips_endpoints {
+        info!("Using FIPS endpoints");
+        loader = loader.use_fips(true);
+    }
+    if args.use_dualstack_endpoints {
+        info!("Using dual-stack endpoints");
+        loader = loader.use_dual_stack(true);
+    }
+    if args.correct_clock_skew {
+        loader = loader.time_source(clock::correct(SharedTimeSource::default()));
+    }
+    if let Some(http_client) = http_client {
+        loader = loader.http_client(http_client);
+    } else {
+        #[cfg(feature = "native-tls")]
+        {
+            loader = loader.http_client(native_tls_http_client());
+        }
+    }
+    let config = loader.load().await;
+
+    match (&args.role_arn, &args.web_identity_token_file) {
+        (Some(role_arn), Some(token_file)) => {
+            info!(
+                "Credential source: web identity token {} for role {}",
+                token_file.display(),
+                role_arn
+            );
+            let mut provider_config =
+                ProviderConfig::default().with_region(config.region().cloned());
+            if let Some(http_client) = config.http_client() {
+                provider_config = provider_config.with_http_client(http_client);
+            }
+            let provider = WebIdentityTokenCredentialsProvider::builder()
+                .configure(&provider_config)
+                .static_configuration(StaticConfiguration {
+                    web_identity_token_file: token_file.clone(),
+                    role_arn: role_arn.clone(),
+                    session_name: args.role_session_name.clone(),
+                })
+                .build();
+            config
+                .into_builder()
+                .credentials_provider(SharedCredentialsProvider::new(provider))
+                .build()
+        }
+        (Some(role_arn), None) => {
+            info!(
+                "Credential source: assume role {} using the default provider chain",
+                role_arn
+            );
+            let provider = AssumeRoleProvider::builder(role_arn)
+                .session_name(&args.role_session_name)
+                .configure(&config)
+                .build()
+                .await;
+            config
+                .into_builder()
+                .credentials_provider(SharedCredentialsProvider::new(provider))
+                .build()
+        }
+        _ => {
+            match &args.profile {
+                Some(profile) => info!("Credential source: profile {}", profile),
+                None => info!("Credential source: default provider chain (environment, profile, container, instance metadata)"),
+            }
+            config
+        }
+    }
+}
+
+/// Masks an IPv6 address to the network of the given prefix length.
+///
+/// # Parameters
+///
+/// * `ip`: The IPv6 address.
+/// * `length`: The prefix length, from 1 to 128.
+///
+/// # Returns
+///
+/// The network address of the prefix containing `ip`.
+fn ipv6_network(ip: Ipv6Addr, length: u8) -> Ipv6Addr {
+    let mask = u128::MAX << (128 - u32::from(length));
+    Ipv6Addr::from(u128::from(ip) & mask)
+}
+
+/// Renders the entry description template and enforces the AWS length limit.
+///
+/// A literal description over the limit is rejected outright, while a templated one is
+/// truncated deterministically (with a warning) since its length depends on the host.
+///
+/// # Parameters
+///
+/// * `template`: The configured description, optionally containing `{host}`.
+///
+/// # Returns
+///
+/// The description to give managed entries, or an error if a literal description is too long.
+fn render_description(template: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    if !template.contains("{host}") {
+        if template.chars().count() > MAX_DESCRIPTION_LENGTH {
+            return Err(format!(
+                "Entry description is {} characters; AWS allows at most {}",
+                template.chars().count(),
+                MAX_DESCRIPTION_LENGTH
+            )
+            .into());
+        }
+        return Ok(template.to_string());
+    }
+
+    let host = gethostname::gethostname().to_string_lossy().into_owned();
+    let description = template.replace("{host}", &host);
+    if description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        let truncated: String = description.chars().take(MAX_DESCRIPTION_LENGTH).collect();
+        warn!(
+            "Entry description is {} characters; truncating to {}: {}",
+            description.chars().count(),
+            MAX_DESCRIPTION_LENGTH,
+            truncated
+        );
+        return Ok(truncated);
+    }
+
+    Ok(description)
+}
+
+/// Builds the prefix list targets, assuming a role into each member account when configured.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration providing the base credentials.
+/// * `args`: The arguments passed to the program.
+///
+/// # Returns
+///
+/// The targets to update, or an error if the member accounts cannot be listed.
+async fn build_targets(
+    config: &SdkConfig,
+    args: &Args,
+) -> Result<Vec<Target>, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(role_name) = &args.assume_role_name else {
+        if args.targets.is_empty() {
+            return Ok(vec![Target::new("local account", config)]);
+        }
+        return build_region_targets(config, args);
+    };
+
+    let mut account_ids = args.account_ids.clone();
+    if let Some(organizational_unit_id) = &args.organizational_unit_id {
+        let ou_accounts =
+            target::list_organizational_unit_accounts(config, organizational_unit_id).await?;
+        info!(
+            "Found {} active accounts in {}",
+            ou_accounts.len(),
+            organizational_unit_id
+        );
+        account_ids.extend(ou_accounts);
+    }
+    account_ids.sort();
+    account_ids.dedup();
+
+    if account_ids.is_empty() {
+        return Err(
+            "No member accounts to update; set --account-ids or --organizational-unit-id".into(),
+        );
+    }
+    if let Some(canary) = &args.canary_account_id {
+        if !account_ids.contains(canary) {
+            return Err(format!(
+                "Canary account {} is not one of the accounts to update",
+                canary
+            )
+            .into());
+        }
+    }
+
+    let mut targets = Vec::with_capacity(account_ids.len());
+    for account_id in &account_ids {
+        targets.push(
+            Target::assume_role(config, account_id, role_name, &args.role_session_name).await,
+        );
+    }
+
+    Ok(targets)
+}
+
+/// Builds a target for the configured prefix list, if any, and one for each prefix list in
+/// another region, each with clients of its own region.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration providing the credentials.
+/// * `args`: The arguments passed to the program.
+///
+/// # Returns
+///
+/// The targets to update, named after their regions, or an error if a target is given twice.
+fn build_region_targets(
+    config: &SdkConfig,
+    args: &Args,
+) -> Result<Vec<Target>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut targets = Vec::with_capacity(args.targets.len() + 1);
+    if args.prefix_list_id.is_some() || args.prefix_list_tag.is_some() {
+        let region = config
+            .region()
+            .map(|region| region.to_string())
+            .unwrap_or_else(|| "local account".to_string());
+        targets.push(Target::new(region, config));
+    }
+    for (index, region_target) in args.targets.iter().enumerate() {
+        let duplicate = args.targets[..index].iter().any(|other| {
+            other.region == region_target.region
+                && other.prefix_list_id == region_target.prefix_list_id
+        });
+        if duplicate {
+            return Err(format!(
+                "Target {}:{} is given more than once",
+                region_target.region, region_target.prefix_list_id
+            )
+            .into());
+        }
+        targets.push(Target::in_region(config, region_target)?);
+    }
+
+    Ok(targets)
+}
+
+/// Names a target in the status and in partial failure errors.
+///
+/// # Parameters
+///
+/// * `target`: The target to name.
+///
+/// # Returns
+///
+/// The prefix list ID followed by the name of the list.
+fn target_label(target: &Target) -> String {
+    // A prefix list that was never located has no ID yet
+    if target.prefix_list_id.is_empty() {
+        return target.name.clone();
+    }
+    format!("{} ({})", target.prefix_list_id, target.name)
+}
+
+/// Creates the tracing span that labels log lines with the target being worked on.
+///
+/// # Parameters
+///
+/// * `target`: The target being worked on.
+/// * `labelled`: Whether to label the lines, only useful when there are several targets.
+///
+/// # Returns
+///
+/// The span to instrument the target's work with.
+fn target_span(target: &Target, labelled: bool) -> Span {
+    if labelled {
+        info_span!("target", account = %target.name)
+    } else {
+        Span::none()
+    }
+}
+
+/// Builds the monitor for one prefix list from its arguments.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration used to construct service clients.
+/// * `args`: The arguments of the monitor.
+///
+/// # Returns
+///
+/// The monitor, or an error if its options are not valid.
+async fn build_monitor(
+    config: &SdkConfig,
+    args: &Args,
+) -> Result<PrefixListMonitor, Box<dyn std::error::Error + Send + Sync>> {
+    if args.adaptive_interval && args.min_interval > args.max_interval {
+        return Err(format!(
+            "Minimum interval {}s is longer than maximum interval {}s",
+            args.min_interval, args.max_interval
+        )
+        .into());
+    }
+
+    partition::check(args, config.region())?;
+    let description = render_description(&args.description)?;
+    // Without a prefix list of its own region, the monitor is named after the first target
+    let prefix_list_ref = PrefixListRef::from_args(
+        args.prefix_list_id.as_deref().or(args
+            .targets
+            .first()
+            .map(|target| target.prefix_list_id.as_str())),
+        args.prefix_list_tag.as_deref(),
+    )?;
+    let targets = build_targets(config, args).await?;
+    let ip_source = IpSource::from_args(config, args)?;
+    let verifier = Verifier::from_args(args)?;
+
+    Ok(PrefixListMonitor::new(
+        config,
+        targets,
+        prefix_list_ref,
+        description,
+        ip_source,
+        verifier,
+        args,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayClient;
+    use aws_smithy_types::date_time::{DateTime, Format};
+    use futures::future::BoxFuture;
+    use std::net::IpAddr;
+    use std::time::SystemTime;
+
+    /// A source that never answers, like a hung DNS lookup.
+    struct Hung;
+
+    impl DetectIp for Hung {
+        fn detect(
+            &self,
+        ) -> BoxFuture<'_, Result<Vec<IpAddr>, Box<dyn std::error::Error + Send + Sync>>> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    /// A source that always detects the same address.
+    struct Fixed;
+
+    impl DetectIp for Fixed {
+        fn detect(
+            &self,
+        ) -> BoxFuture<'_, Result<Vec<IpAddr>, Box<dyn std::error::Error + Send + Sync>>> {
+            Box::pin(async { Ok(vec!["203.0.113.7".parse()?]) })
+        }
+    }
+
+    /// A wall clock following tokio's, so pausing and advancing tokio's time moves it too.
+    #[derive(Debug)]
+    struct TokioClock {
+        /// The wall-clock time when the clock was made.
+        epoch: SystemTime,
+        /// tokio's time when the clock was made.
+        start: Instant,
+    }
+
+    impl TimeSource for TokioClock {
+        fn now(&self) -> SystemTime {
+            self.epoch + self.start.elapsed()
+        }
+    }
+
+    /// Builds a monitor without prefix lists, so its checks make no AWS requests.
+    async fn monitor(builder: PrefixListMonitorBuilder) -> PrefixListMonitor {
+        let mut monitor = builder
+            .prefix_list_id("pl-0123456789abcdef0")
+            .build()
+            .await
+            .expect("monitor builds");
+        monitor.targets.clear();
+        monitor
+    }
+
+    /// Returns an AWS configuration that is never used to send a request.
+    fn config() -> SdkConfig {
+        SdkConfig::builder()
+            .region(aws_config::Region::new("us-east-1"))
+            .behavior_version(BehaviorVersion::latest())
+            .build()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn watchdog_cancels_a_stalled_check() {
+        let mut monitor = monitor(PrefixListMonitor::builder(&config()).ip_source(Hung)).await;
+
+        let started = Instant::now();
+        monitor
+            .run(Duration::from_secs(10), true)
+            .await
+            .expect("a stalled check is reported, not returned");
+
+        // Three 10s intervals are below the floor of two minutes
+        assert_eq!(started.elapsed(), MIN_STALL_TIMEOUT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn history_is_stamped_by_the_builder_clock() {
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let clock = TokioClock {
+            epoch,
+            start: Instant::now(),
+        };
+        let mut monitor = monitor(
+            PrefixListMonitor::builder(&config())
+                .description("Clock test")
+                .ip_source(Fixed)
+                .time_source(clock),
+        )
+        .await;
+
+        time::advance(Duration::from_secs(90)).await;
+        monitor.check_and_update().await.expect("check succeeds");
+
+        let expected = DateTime::from(epoch + Duration::from_secs(90))
+            .fmt(Format::DateTime)
+            .expect("time formats");
+        let entry = status::history(usize::MAX)
+            .into_iter()
+            .find(|entry| entry.monitor == monitor.name)
+            .expect("the change is recorded");
+        assert_eq!(entry.time, expected);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn builder_clock_stays_with_its_monitor() {
+        let epoch = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let clock = TokioClock {
+            epoch,
+            start: Instant::now(),
+        };
+        let mut scripted = monitor(
+            PrefixListMonitor::builder(&config())
+                .description("Scripted clock")
+                .ip_source(Fixed)
+                .time_source(clock),
+        )
+        .await;
+        let mut system = monitor(
+            PrefixListMonitor::builder(&config())
+                .description("System clock")
+                .ip_source(Fixed),
+        )
+        .await;
+
+        scripted.check_and_update().await.expect("check succeeds");
+        system.check_and_update().await.expect("check succeeds");
+
+        let stamped = |name: &str| {
+            let entry = status::history(usize::MAX)
+                .into_iter()
+                .find(|entry| entry.monitor == name)
+                .expect("the change is recorded");
+            DateTime::from_str(&entry.time, Format::DateTime).expect("time parses")
+        };
+        assert_eq!(stamped(&scripted.name), DateTime::from(epoch));
+        let now = DateTime::from(SystemTime::now()).secs();
+        assert!((now - stamped(&system.name).secs()).abs() < 60);
+    }
+
+    #[tokio::test]
+    async fn requested_addresses_are_formatted_like_detected_ones() {
+        let monitor = monitor(PrefixListMonitor::builder(&config()).cidr_suffix(24)).await;
+        let cidrs = |value| monitor.requested_cidrs(value).unwrap().1;
+
+        assert_eq!(cidrs("203.0.113.7"), ["203.0.113.7/24"]);
+        assert_eq!(cidrs("2001:db8::1"), ["2001:db8::1/128"]);
+        assert_eq!(cidrs("203.0.113.7/32"), ["203.0.113.7/32"]);
+
+        let delegated =
+            self::monitor(PrefixListMonitor::builder(&config()).ipv6_prefix_length(56)).await;
+        assert_eq!(
+            delegated.requested_cidrs("2001:db8:0:1ff::1").unwrap().1,
+            ["2001:db8:0:100::/56"]
+        );
+    }
+
+    #[tokio::test]
+    async fn requested_address_must_match_the_list_family() {
+        let mut monitor = monitor(PrefixListMonitor::builder(&config())).await;
+        monitor.list_family = Some(AddressFamily::V4);
+
+        let e = commands::set_ip(&mut monitor, "2001:db8::1")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "2001:db8::1 is not an IPv4 address, which is all the prefix lists hold"
+        );
+        assert!(commands::set_ip(&mut monitor, "not an address")
+            .await
+            .is_err());
+        // Requests from the control server meant for an IPv6 monitor are left to it
+        assert!(!monitor.apply_requested_ip("2001:db8::/64").await.unwrap());
+    }
+
+    /// Builds a monitor whose first prefix list accepts the update and whose second denies it.
+    async fn partially_failing_monitor(
+        rollback: bool,
+    ) -> (PrefixListMonitor, ReplayClient, ReplayClient) {
+        let applied = ReplayClient::fixture("partial_failure_applied.jsonl");
+        let denied = ReplayClient::fixture("partial_failure_denied.jsonl");
+        let mut monitor = monitor(PrefixListMonitor::builder(&config()).description("Home")).await;
+        monitor.rollback_on_partial_failure = rollback;
+        for (name, replay, prefix_list_id) in [
+            ("123456789012", &applied, "pl-0123456789abcdef0"),
+            ("210987654321", &denied, "pl-0fedcba9876543210"),
+        ] {
+            let mut target = Target::new(name, &replay.sdk_config());
+            target.prefix_list_id = prefix_list_id.to_string();
+            monitor.targets.push(target);
+        }
+        (monitor, applied, denied)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn partial_failure_keeps_the_applied_prefix_lists() {
+        let (mut monitor, applied, denied) = partially_failing_monitor(false).await;
+        let cidrs = vec!["203.0.113.7/32".to_string()];
+
+        let e = monitor
+            .apply(vec!["203.0.113.7".to_string()], cidrs.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Partially applied: 1 of 2 targets failed to update (pl-0fedcba9876543210 (210987654321))"
+        );
+        assert_eq!(monitor.targets[0].applied_cidrs, Some(cidrs));
+        assert_eq!(monitor.targets[1].applied_cidrs, None);
+        assert_eq!(monitor.current_ips, None);
+        // The recorded rollback is never sent
+        assert_eq!(applied.remaining(), 3);
+        assert_eq!(denied.remaining(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn partial_failure_rolls_back_the_applied_prefix_lists() {
+        let (mut monitor, applied, denied) = partially_failing_monitor(true).await;
+
+        assert!(monitor
+            .apply(
+                vec!["203.0.113.7".to_string()],
+                vec!["203.0.113.7/32".to_string()]
+            )
+            .await
+            .is_err());
+        // The applied list was read again and its entry put back, so the next check retries both
+        assert_eq!(applied.remaining(), 0);
+        assert_eq!(denied.remaining(), 0);
+        assert_eq!(monitor.targets[0].applied_cidrs, None);
+        assert_eq!(monitor.targets[1].applied_cidrs, None);
+    }
+
+    #[test]
+    fn literal_description_over_the_limit_is_rejected() {
+        let limit = "a".repeat(MAX_DESCRIPTION_LENGTH);
+        assert_eq!(render_description(&limit).unwrap(), limit);
+        // Characters count, not bytes
+        let accented = "é".repeat(MAX_DESCRIPTION_LENGTH);
+        assert_eq!(render_description(&accented).unwrap(), accented);
+
+        let e = render_description(&format!("{}a", limit)).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "Entry description is 256 characters; AWS allows at most 255"
+        );
+    }
+
+    #[test]
+    fn templated_description_is_truncated() {
+        let host = gethostname::gethostname().to_string_lossy().into_owned();
+        assert_eq!(
+            render_description("Home {host}").unwrap(),
+            format!("Home {}", host)
+        );
+
+        let long = format!("{}{{host}}", "a".repeat(MAX_DESCRIPTION_LENGTH));
+        assert_eq!(
+            render_description(&long).unwrap(),
+            "a".repeat(MAX_DESCRIPTION_LENGTH)
+        );
+    }
+
+    #[test]
+    fn ipv6_addresses_are_masked_to_their_network() {
+        let network = |ip: &str, length| ipv6_network(ip.parse().unwrap(), length).to_string();
+        assert_eq!(network("2001:db8:0:1ff::1", 56), "2001:db8:0:100::");
+        assert_eq!(network("2001:db8:abcd:12ff::1", 48), "2001:db8:abcd::");
+        assert_eq!(network("2001:db8::1", 128), "2001:db8::1");
+        assert_eq!(network("ffff::1", 1), "8000::");
+    }
+
+    #[test]
+    fn monitor_futures_are_send() {
+        fn assert_send<T: Send>(_: T) {}
+        // Never called: only the futures' types matter, as tokio::spawn would see them
+        let _ = |mut monitor: PrefixListMonitor| {
+            assert_send(async move { monitor.check_and_update().await })
+        };
+        let _ = |mut monitor: PrefixListMonitor| {
+            assert_send(async move { monitor.run(Duration::from_secs(60), true).await })
+        };
+    }
+}
@@ -0,0 +1,440 @@
+use aws_sdk_ec2::config::{
+    interceptors::{BeforeSerializationInterceptorContextRef, FinalizerInterceptorContextRef},
+    ConfigBag, Intercept, RuntimeComponents,
+};
+use aws_smithy_runtime_api::{box_error::BoxError, client::orchestrator::Metadata};
+use aws_smithy_types::config_bag::{Storable, StoreReplace};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// The upper bounds of the AWS request duration buckets, in seconds.
+const DURATION_BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// The metrics collected since startup.
+static REGISTRY: LazyLock<Mutex<Registry>> = LazyLock::new(Default::default);
+
+/// The durations observed for one AWS operation.
+#[derive(Default)]
+struct Histogram {
+    /// The number of observations at or below each bucket bound.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    /// The sum of the observations, in seconds.
+    sum: f64,
+    /// The number of observations.
+    count: u64,
+}
+
+/// The metrics collected since startup.
+#[derive(Default)]
+struct Registry {
+    /// The durations of AWS requests, by operation.
+    durations: BTreeMap<String, Histogram>,
+    /// The failed AWS request attempts, by operation and kind of error.
+    errors: BTreeMap<(String, &'static str), u64>,
+    /// The IP detections, by result.
+    detections: BTreeMap<&'static str, u64>,
+    /// The checks, by result.
+    checks: BTreeMap<&'static str, u64>,
+    /// The targets holding and still missing the latest CIDRs, by monitor.
+    targets: BTreeMap<String, (usize, usize)>,
+    /// The updates applied, by monitor.
+    updates: BTreeMap<String, u64>,
+    /// The checks cancelled by the watchdog, by monitor.
+    stalls: BTreeMap<String, u64>,
+}
+
+/// Classifies an AWS error code for the error counters.
+///
+/// # Parameters
+///
+/// * `code`: The AWS error code.
+///
+/// # Returns
+///
+/// `throttle`, `auth`, `conflict` or `other`.
+fn error_kind(code: &str) -> &'static str {
+    match code {
+        "Throttling" | "ThrottlingException" | "RequestLimitExceeded" => "throttle",
+        "UnauthorizedOperation"
+        | "AccessDenied"
+        | "AccessDeniedException"
+        | "ExpiredToken"
+        | "ExpiredTokenException"
+        | "RequestExpired"
+        | "TokenRefreshRequired"
+        | "InvalidClientTokenId"
+        | "AuthFailure"
+        | "UnrecognizedClientException"
+        | "SignatureDoesNotMatch" => "auth",
+        "PrefixListVersionMismatch" | "IncorrectState" | "InvalidTokenException" => "conflict",
+        _ => "other",
+    }
+}
+
+/// Extracts the error code from an AWS query protocol error response.
+///
+/// # Parameters
+///
+/// * `body`: The XML response body.
+///
+/// # Returns
+///
+/// The contents of the first `Code` element, if any.
+fn error_code(body: &str) -> Option<&str> {
+    let start = body.find("<Code>")? + "<Code>".len();
+    let end = body[start..].find("</Code>")? + start;
+    Some(&body[start..end])
+}
+
+/// Records the outcome of an IP detection.
+///
+/// # Parameters
+///
+/// * `success`: Whether the detection returned addresses.
+pub fn record_detection(success: bool) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.detections.entry(result(success)).or_default() += 1;
+    }
+}
+
+/// Records the outcome of a check.
+///
+/// # Parameters
+///
+/// * `success`: Whether the check completed without error.
+pub fn record_check(success: bool) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.checks.entry(result(success)).or_default() += 1;
+    }
+}
+
+/// Records an update applied by a monitor.
+///
+/// # Parameters
+///
+/// * `monitor`: The name of the monitor.
+pub fn record_update(monitor: &str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.updates.entry(monitor.to_string()).or_default() += 1;
+    }
+}
+
+/// Records a check cancelled by the watchdog.
+///
+/// # Parameters
+///
+/// * `monitor`: The name of the monitor.
+pub fn record_stall(monitor: &str) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        *registry.stalls.entry(monitor.to_string()).or_default() += 1;
+    }
+}
+
+/// Records how many of a monitor's targets hold the CIDRs it last applied.
+///
+/// # Parameters
+///
+/// * `monitor`: The name of the monitor.
+/// * `applied`: The targets updated with the CIDRs.
+/// * `pending`: The targets still to be updated.
+pub fn record_targets(monitor: &str, applied: usize, pending: usize) {
+    if let Ok(mut registry) = REGISTRY.lock() {
+        registry
+            .targets
+            .insert(monitor.to_string(), (applied, pending));
+    }
+}
+
+/// Returns the label value for an outcome.
+fn result(success: bool) -> &'static str {
+    if success {
+        "success"
+    } else {
+        "failure"
+    }
+}
+
+/// Renders the metrics in the Prometheus text exposition format.
+///
+/// # Returns
+///
+/// The metrics, one sample per line.
+pub fn render() -> String {
+    let Ok(registry) = REGISTRY.lock() else {
+        return String::new();
+    };
+    let mut text = String::new();
+
+    text.push_str(
+        "# HELP aws_request_duration_seconds Duration of AWS API calls, including retries.\n",
+    );
+    text.push_str("# TYPE aws_request_duration_seconds histogram\n");
+    for (operation, histogram) in &registry.durations {
+        for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+            let _ = writeln!(
+                text,
+                "aws_request_duration_seconds_bucket{{operation=\"{}\",le=\"{}\"}} {}",
+                operation, bound, count
+            );
+        }
+        let _ = writeln!(
+            text,
+            "aws_request_duration_seconds_bucket{{operation=\"{}\",le=\"+Inf\"}} {}",
+            operation, histogram.count
+        );
+        let _ = writeln!(
+            text,
+            "aws_request_duration_seconds_sum{{operation=\"{}\"}} {}",
+            operation, histogram.sum
+        );
+        let _ = writeln!(
+            text,
+            "aws_request_duration_seconds_count{{operation=\"{}\"}} {}",
+            operation, histogram.count
+        );
+    }
+
+    text.push_str(
+        "# HELP aws_request_errors_total Failed AWS API call attempts by kind of error.\n",
+    );
+    text.push_str("# TYPE aws_request_errors_total counter\n");
+    for ((operation, kind), count) in &registry.errors {
+        let _ = writeln!(
+            text,
+            "aws_request_errors_total{{operation=\"{}\",kind=\"{}\"}} {}",
+            operation, kind, count
+        );
+    }
+
+    text.push_str("# HELP ip_detections_total IP detections by result.\n");
+    text.push_str("# TYPE ip_detections_total counter\n");
+    for (result, count) in &registry.detections {
+        let _ = writeln!(
+            text,
+            "ip_detections_total{{result=\"{}\"}} {}",
+            result, count
+        );
+    }
+
+    text.push_str("# HELP checks_total Checks by result.\n");
+    text.push_str("# TYPE checks_total counter\n");
+    for (result, count) in &registry.checks {
+        let _ = writeln!(text, "checks_total{{result=\"{}\"}} {}", result, count);
+    }
+
+    text.push_str("# HELP targets_applied Targets holding the latest CIDRs, by monitor.\n");
+    text.push_str("# TYPE targets_applied gauge\n");
+    for (monitor, (applied, _)) in &registry.targets {
+        let _ = writeln!(
+            text,
+            "targets_applied{{monitor=\"{}\"}} {}",
+            monitor, applied
+        );
+    }
+
+    text.push_str(
+        "# HELP targets_pending Targets still to be updated with the latest CIDRs, by monitor.\n",
+    );
+    text.push_str("# TYPE targets_pending gauge\n");
+    for (monitor, (_, pending)) in &registry.targets {
+        let _ = writeln!(
+            text,
+            "targets_pending{{monitor=\"{}\"}} {}",
+            monitor, pending
+        );
+    }
+
+    text.push_str("# HELP updates_total Updates applied, by monitor.\n");
+    text.push_str("# TYPE updates_total counter\n");
+    for (monitor, count) in &registry.updates {
+        let _ = writeln!(text, "updates_total{{monitor=\"{}\"}} {}", monitor, count);
+    }
+
+    text.push_str("# HELP check_stalls_total Checks cancelled by the watchdog, by monitor.\n");
+    text.push_str("# TYPE check_stalls_total counter\n");
+    for (monitor, count) in &registry.stalls {
+        let _ = writeln!(
+            text,
+            "check_stalls_total{{monitor=\"{}\"}} {}",
+            monitor, count
+        );
+    }
+
+    text
+}
+
+/// Pushes the metrics of a finished run to a Prometheus Pushgateway, for runs too short to be
+/// scraped.
+///
+/// The metrics replace those the previous run pushed, along with the outcome of this one.
+///
+/// # Parameters
+///
+/// * `url`: The base URL of the Pushgateway.
+/// * `duration`: How long the run took.
+/// * `completed`: Whether the run got to check at all, rather than failing to start.
+///
+/// # Returns
+///
+/// An error if the Pushgateway cannot be reached or rejects the metrics.
+pub async fn push(
+    url: &str,
+    duration: Duration,
+    completed: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (success, changed) = match REGISTRY.lock() {
+        Ok(registry) => (
+            completed && !registry.checks.contains_key(result(false)),
+            registry.updates.values().any(|count| *count > 0),
+        ),
+        Err(_) => (false, false),
+    };
+    let finished = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let mut text = render();
+    text.push_str("# HELP run_success Whether every check of the run succeeded.\n");
+    text.push_str("# TYPE run_success gauge\n");
+    let _ = writeln!(text, "run_success {}", u8::from(success));
+    text.push_str("# HELP run_changed Whether the run applied an update.\n");
+    text.push_str("# TYPE run_changed gauge\n");
+    let _ = writeln!(text, "run_changed {}", u8::from(changed));
+    text.push_str("# HELP run_duration_seconds How long the run took.\n");
+    text.push_str("# TYPE run_duration_seconds gauge\n");
+    let _ = writeln!(text, "run_duration_seconds {}", duration.as_secs_f64());
+    text.push_str("# HELP run_last_timestamp_seconds When the run finished, as a Unix time.\n");
+    text.push_str("# TYPE run_last_timestamp_seconds gauge\n");
+    let _ = writeln!(text, "run_last_timestamp_seconds {}", finished.as_secs());
+
+    // PUT replaces every metric of the group, so counters of an earlier run do not linger
+    let url = format!(
+        "{}/metrics/job/{}",
+        url.trim_end_matches('/'),
+        env!("CARGO_PKG_NAME")
+    );
+    reqwest::Client::new()
+        .put(&url)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(text)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()
+        .map_err(|e| format!("Pushgateway rejected the metrics: {}", e))?;
+    debug!("Pushed metrics to {}", url);
+
+    Ok(())
+}
+
+/// When an AWS call started, held until it finishes.
+#[derive(Debug)]
+struct CallStarted(Instant);
+
+impl Storable for CallStarted {
+    type Storer = StoreReplace<Self>;
+}
+
+/// An interceptor that records the duration of every AWS call and the errors of its attempts,
+/// and logs each call with its request ID.
+#[derive(Clone, Debug)]
+pub struct CallMetrics;
+
+impl CallMetrics {
+    /// Returns the name of the operation an interceptor is called for.
+    fn operation(cfg: &ConfigBag) -> String {
+        cfg.load::<Metadata>()
+            .map(|m| m.name().to_string())
+            .unwrap_or_else(|| "Unknown".to_string())
+    }
+}
+
+impl Intercept for CallMetrics {
+    fn name(&self) -> &'static str {
+        "CallMetrics"
+    }
+
+    fn read_before_execution(
+        &self,
+        _context: &BeforeSerializationInterceptorContextRef<'_>,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        cfg.interceptor_state()
+            .store_put(CallStarted(Instant::now()));
+
+        Ok(())
+    }
+
+    fn read_after_attempt(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        // Replayed responses carry the time they were recorded
+        if let Some(date) = context.response().and_then(|r| r.headers().get("date")) {
+            if !crate::replay::replaying() {
+                crate::clock::observe(date);
+            }
+        }
+
+        // Each attempt is counted, so throttling shows even when a retry succeeds
+        let kind = match context.response() {
+            Some(response) if response.status().is_success() => return Ok(()),
+            Some(response) => response
+                .body()
+                .bytes()
+                .map(String::from_utf8_lossy)
+                .and_then(|body| error_code(&body).map(error_kind))
+                .unwrap_or("other"),
+            None if context.output_or_error().is_some_and(|r| r.is_err()) => "network",
+            None => return Ok(()),
+        };
+
+        if let Ok(mut registry) = REGISTRY.lock() {
+            *registry
+                .errors
+                .entry((Self::operation(cfg), kind))
+                .or_default() += 1;
+        }
+
+        Ok(())
+    }
+
+    fn read_after_execution(
+        &self,
+        context: &FinalizerInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        let Some(CallStarted(started)) = cfg.load::<CallStarted>() else {
+            return Ok(());
+        };
+        let seconds = started.elapsed().as_secs_f64();
+        debug!(
+            "{} took {:.3}s (request ID {})",
+            Self::operation(cfg),
+            seconds,
+            context
+                .response()
+                .and_then(|r| r.headers().get("x-amzn-requestid"))
+                .unwrap_or("none")
+        );
+
+        if let Ok(mut registry) = REGISTRY.lock() {
+            let histogram = registry.durations.entry(Self::operation(cfg)).or_default();
+            for (bound, count) in DURATION_BUCKETS.iter().zip(histogram.buckets.iter_mut()) {
+                if seconds <= *bound {
+                    *count += 1;
+                }
+            }
+            histogram.sum += seconds;
+            histogram.count += 1;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use crate::control::{self, Request};
+use futures::{StreamExt, TryStreamExt};
+use rtnetlink::packet_core::NetlinkPayload;
+use rtnetlink::packet_route::address::AddressScope;
+use rtnetlink::packet_route::route::{RouteAttribute, RouteMessage, RouteType};
+use rtnetlink::packet_route::RouteNetlinkMessage;
+use rtnetlink::{new_multicast_connection, Handle, MulticastGroup, RouteMessageBuilder};
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::{debug, info, warn};
+
+/// How long address events must stop before the check runs, so a replaced address (removal,
+/// addition and IPv6 duplicate address detection) triggers one check with the final state.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Returns whether a route is a default route.
+///
+/// # Parameters
+///
+/// * `route`: The route.
+///
+/// # Returns
+///
+/// Whether the route is a unicast route to `0.0.0.0/0` or `::/0`.
+fn is_default_route(route: &RouteMessage) -> bool {
+    route.header.destination_prefix_length == 0 && route.header.kind == RouteType::Unicast
+}
+
+/// Looks up the interfaces that carry a default route.
+///
+/// # Parameters
+///
+/// * `handle`: The handle of the netlink connection.
+///
+/// # Returns
+///
+/// The indexes of the interfaces, or an error if the routes cannot be read.
+async fn default_interfaces(handle: &Handle) -> Result<HashSet<u32>, rtnetlink::Error> {
+    let mut interfaces = HashSet::new();
+    for query in [
+        RouteMessageBuilder::<Ipv4Addr>::new().build(),
+        RouteMessageBuilder::<Ipv6Addr>::new().build(),
+    ] {
+        let mut routes = handle.route().get(query).execute();
+        while let Some(route) = routes.try_next().await? {
+            if is_default_route(&route) {
+                interfaces.extend(route.attributes.iter().filter_map(|a| match a {
+                    RouteAttribute::Oif(index) => Some(*index),
+                    _ => None,
+                }));
+            }
+        }
+    }
+
+    Ok(interfaces)
+}
+
+/// Triggers a check whenever the global address of a default-route interface or the default
+/// route itself changes, in the background.
+///
+/// # Returns
+///
+/// An error if the netlink socket cannot be opened, or `Ok(())` on success.
+pub fn watch() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (connection, handle, mut messages) = new_multicast_connection(&[
+        MulticastGroup::Ipv4Ifaddr,
+        MulticastGroup::Ipv6Ifaddr,
+        MulticastGroup::Ipv4Route,
+        MulticastGroup::Ipv6Route,
+    ])
+    .map_err(|e| format!("Cannot watch network changes: {}", e))?;
+    tokio::spawn(connection);
+    info!("Watching for address changes on the default-route interface");
+
+    tokio::spawn(async move {
+        let mut settle_until: Option<Instant> = None;
+        loop {
+            let message = tokio::select! {
+                message = messages.next() => match message {
+                    Some((message, _)) => message,
+                    None => {
+                        warn!("Network change watch stopped; relying on the check interval");
+                        return;
+                    }
+                },
+                _ = time::sleep_until(settle_until.unwrap_or_else(Instant::now)),
+                    if settle_until.is_some() =>
+                {
+                    settle_until = None;
+                    info!("Network changed; checking now");
+                    control::send(Request::Check);
+                    continue;
+                }
+            };
+
+            let relevant = match message.payload {
+                NetlinkPayload::InnerMessage(
+                    RouteNetlinkMessage::NewAddress(address)
+                    | RouteNetlinkMessage::DelAddress(address),
+                ) if address.header.scope == AddressScope::Universe => {
+                    match default_interfaces(&handle).await {
+                        Ok(interfaces) => interfaces.contains(&address.header.index),
+                        Err(e) => {
+                            warn!("Cannot read the default routes: {}", e);
+                            true
+                        }
+                    }
+                }
+                NetlinkPayload::InnerMessage(
+                    RouteNetlinkMessage::NewRoute(route) | RouteNetlinkMessage::DelRoute(route),
+                ) => is_default_route(&route),
+                _ => false,
+            };
+            if relevant {
+                debug!(
+                    "Default-route interface changed; checking in {}s",
+                    SETTLE_TIME.as_secs()
+                );
+                settle_until = Some(Instant::now() + SETTLE_TIME);
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::target::MODIFY_ATTEMPTS;
+use aws_config::SdkConfig;
+use aws_sdk_networkfirewall::types::IpSet;
+use tracing::{info, warn};
+
+/// An IP set variable of a stateful Network Firewall rule group kept at the current CIDRs.
+///
+/// The variable belongs to the monitor: its whole definition is replaced, so rules should
+/// reference a variable that holds nothing but the monitored addresses.
+pub struct RuleGroupIpSet {
+    /// The client instance used to read and update the rule group.
+    client: aws_sdk_networkfirewall::Client,
+    /// The ARN of the rule group.
+    pub rule_group_arn: String,
+    /// The name of the IP set variable, without the leading `$`.
+    variable: String,
+}
+
+impl RuleGroupIpSet {
+    /// Builds the IP set variable from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct the Network Firewall client.
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// The IP set variable, or `None` when no rule group is configured.
+    pub fn from_args(config: &SdkConfig, args: &crate::Args) -> Option<Self> {
+        Some(Self {
+            client: aws_sdk_networkfirewall::Client::new(config),
+            rule_group_arn: args.network_firewall_rule_group.clone()?,
+            variable: args
+                .network_firewall_ip_set
+                .trim_start_matches('$')
+                .to_string(),
+        })
+    }
+
+    /// Sets the IP set variable to the CIDRs.
+    ///
+    /// The rule group is written back with the update token it was read with, so a change
+    /// made in between is detected and the update is planned again from the new state, like
+    /// a prefix list version mismatch.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidrs`: The CIDRs the IP set should consist of.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the rule group was modified, `Ok(false)` if it was already in sync, or an
+    /// error if the rule group has no such variable or a request fails.
+    pub async fn apply(
+        &self,
+        cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut attempt = 1;
+        loop {
+            let current = self
+                .client
+                .describe_rule_group()
+                .rule_group_arn(&self.rule_group_arn)
+                .send()
+                .await?;
+            let response = current
+                .rule_group_response()
+                .ok_or_else(|| format!("Rule group {} not found", self.rule_group_arn))?;
+            let mut rule_group = current
+                .rule_group()
+                .cloned()
+                .ok_or_else(|| format!("Rule group {} has no rules", self.rule_group_arn))?;
+
+            let ip_set = rule_group
+                .rule_variables
+                .as_mut()
+                .and_then(|variables| variables.ip_sets.as_mut())
+                .and_then(|ip_sets| ip_sets.get_mut(&self.variable))
+                .ok_or_else(|| {
+                    format!(
+                        "Rule group {} has no IP set variable {}",
+                        self.rule_group_arn, self.variable
+                    )
+                })?;
+            let mut previous = ip_set.definition().to_vec();
+            previous.sort();
+            let mut desired = cidrs.to_vec();
+            desired.sort();
+            if previous == desired {
+                return Ok(false);
+            }
+            *ip_set = IpSet::builder().set_definition(Some(desired)).build()?;
+
+            let result = self
+                .client
+                .update_rule_group()
+                .rule_group_arn(&self.rule_group_arn)
+                .update_token(current.update_token())
+                .rule_group(rule_group)
+                .set_description(response.description().map(str::to_string))
+                .set_encryption_configuration(response.encryption_configuration().cloned())
+                .send()
+                .await;
+            match result {
+                Ok(_) => {
+                    info!(
+                        "Updated Network Firewall rule group {} variable ${}: {} -> {}",
+                        response.rule_group_name(),
+                        self.variable,
+                        previous.join(", "),
+                        cidrs.join(", ")
+                    );
+                    return Ok(true);
+                }
+                // Another change landed between reading the token and writing; start over
+                Err(e)
+                    if attempt < MODIFY_ATTEMPTS
+                        && e.as_service_error()
+                            .is_some_and(|e| e.is_invalid_token_exception()) =>
+                {
+                    warn!(
+                        "Rule group {} changed concurrently (attempt {}/{}), retrying",
+                        response.rule_group_name(),
+                        attempt,
+                        MODIFY_ATTEMPTS
+                    );
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// How long a webhook may take to accept a notification.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A webhook that receives notifications, with the state used to rate limit it.
+struct Channel {
+    /// The URL notifications are posted to.
+    url: String,
+    /// The scheme and host of the URL, which is all that is logged: webhook URLs carry their
+    /// secret in the path.
+    label: String,
+    /// When the last change notification was sent.
+    last_change: Option<Instant>,
+    /// The changes held back since the last change notification.
+    held_changes: u32,
+    /// The failure last sent and when, so repeats within the digest interval are dropped.
+    last_failure: Option<(String, Instant)>,
+}
+
+/// Sends notifications about address changes and failed checks to webhooks.
+///
+/// With a digest interval, each channel sends at most one change notification per interval;
+/// later changes are summarized when it ends. Failures are sent right away, but the same
+/// failure is repeated at most once per interval.
+pub struct Notifier {
+    /// The client instance used to post notifications.
+    client: reqwest::Client,
+    /// The webhooks notified.
+    channels: Vec<Channel>,
+    /// The interval changes are summarized over, if they are.
+    digest_interval: Option<Duration>,
+    /// The name of the monitor the notifications are about.
+    subject: String,
+}
+
+impl Notifier {
+    /// Creates a new instance of `Notifier`.
+    ///
+    /// # Parameters
+    ///
+    /// * `args`: The arguments passed to the program.
+    /// * `subject`: The name of the monitor the notifications are about.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Notifier`.
+    pub fn new(args: &crate::Args, subject: String) -> Self {
+        Self {
+            client: if args.small_footprint {
+                reqwest::Client::builder()
+                    .http1_only()
+                    .pool_max_idle_per_host(1)
+                    .build()
+                    .unwrap_or_default()
+            } else {
+                reqwest::Client::new()
+            },
+            channels: args
+                .notify_webhooks
+                .iter()
+                .map(|url| Channel {
+                    url: url.clone(),
+                    label: reqwest::Url::parse(url)
+                        .ok()
+                        .and_then(|url| {
+                            url.host_str()
+                                .map(|host| format!("{}://{}", url.scheme(), host))
+                        })
+                        .unwrap_or_else(|| "webhook".to_string()),
+                    last_change: None,
+                    held_changes: 0,
+                    last_failure: None,
+                })
+                .collect(),
+            digest_interval: args.notify_digest_interval.map(Duration::from_secs),
+            subject,
+        }
+    }
+
+    /// Notifies the channels that the prefix lists were updated to new addresses.
+    ///
+    /// # Parameters
+    ///
+    /// * `previous`: The addresses applied before, if known.
+    /// * `current`: The addresses now applied.
+    pub async fn changed(&mut self, previous: Option<&[String]>, current: &[String]) {
+        let text = format!(
+            "{}: IP changed from {} to {}",
+            self.subject,
+            previous
+                .map(|ips| ips.join(", "))
+                .unwrap_or_else(|| "unknown".to_string()),
+            current.join(", ")
+        );
+        let now = Instant::now();
+        for index in 0..self.channels.len() {
+            let channel = &mut self.channels[index];
+            let held = match (self.digest_interval, channel.last_change) {
+                (Some(interval), Some(sent)) => now.duration_since(sent) < interval,
+                _ => false,
+            };
+            if held {
+                channel.held_changes += 1;
+                debug!("Holding change notification for {}", channel.label);
+                continue;
+            }
+            channel.last_change = Some(now);
+            self.send(index, &text).await;
+        }
+    }
+
+    /// Notifies the channels that a check failed.
+    ///
+    /// # Parameters
+    ///
+    /// * `error`: The description of the failure.
+    pub async fn failed(&mut self, error: &str) {
+        let text = format!("{}: check failed: {}", self.subject, error);
+        let now = Instant::now();
+        for index in 0..self.channels.len() {
+            let channel = &mut self.channels[index];
+            let repeated = match (self.digest_interval, &channel.last_failure) {
+                (Some(interval), Some((last, sent))) => {
+                    last == error && now.duration_since(*sent) < interval
+                }
+                _ => false,
+            };
+            if repeated {
+                continue;
+            }
+            channel.last_failure = Some((error.to_string(), now));
+            self.send(index, &text).await;
+        }
+    }
+
+    /// Sends the summary of held changes to each channel whose digest interval has ended.
+    ///
+    /// # Parameters
+    ///
+    /// * `current`: The addresses currently applied.
+    pub async fn flush(&mut self, current: Option<&[String]>) {
+        let Some(interval) = self.digest_interval else {
+            return;
+        };
+        let now = Instant::now();
+        for index in 0..self.channels.len() {
+            let channel = &mut self.channels[index];
+            let due = channel
+                .last_change
+                .is_some_and(|sent| now.duration_since(sent) >= interval);
+            if channel.held_changes == 0 || !due {
+                continue;
+            }
+            // The first change of the interval was sent on its own
+            let text = format!(
+                "{}: IP changed {} times in the last {}, currently {}",
+                self.subject,
+                channel.held_changes + 1,
+                format_duration(now.duration_since(channel.last_change.unwrap_or(now))),
+                current
+                    .map(|ips| ips.join(", "))
+                    .unwrap_or_else(|| "unknown".to_string())
+            );
+            channel.held_changes = 0;
+            channel.last_change = Some(now);
+            self.send(index, &text).await;
+        }
+    }
+
+    /// Posts a notification to a channel, logging rather than returning delivery failures.
+    ///
+    /// # Parameters
+    ///
+    /// * `index`: The index of the channel.
+    /// * `text`: The notification text.
+    async fn send(&self, index: usize, text: &str) {
+        let channel = &self.channels[index];
+        let result = self
+            .client
+            .post(&channel.url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::json!({ "text": text }).to_string())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => debug!("Sent notification to {}", channel.label),
+            Err(e) => warn!(
+                "Failed to send notification to {}: {}",
+                channel.label,
+                e.without_url()
+            ),
+        }
+    }
+}
+
+/// Formats a duration in whole hours, minutes or seconds for notification and log text.
+///
+/// # Parameters
+///
+/// * `duration`: The duration.
+///
+/// # Returns
+///
+/// The duration in its largest whole unit, e.g. `2h` or `15m`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}h", seconds / 3600)
+    } else if seconds >= 60 {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
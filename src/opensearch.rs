@@ -0,0 +1,86 @@
+use aws_config::SdkConfig;
+use tracing::info;
+
+/// The `aws:SourceIp` condition of an OpenSearch Service domain access policy.
+pub struct DomainAccessPolicy {
+    /// The client instance used to read and update the domain configuration.
+    client: aws_sdk_opensearch::Client,
+    /// The name of the domain.
+    pub domain_name: String,
+    /// The `Sid` of the statement holding the condition, if the policy names its statements.
+    sid: Option<String>,
+}
+
+impl DomainAccessPolicy {
+    /// Builds the domain access policy from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct the OpenSearch client.
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// The domain access policy, or `None` when no domain is configured.
+    pub fn from_args(config: &SdkConfig, args: &crate::Args) -> Option<Self> {
+        Some(Self {
+            client: aws_sdk_opensearch::Client::new(config),
+            domain_name: args.opensearch_domain.clone()?,
+            sid: args.opensearch_statement.clone(),
+        })
+    }
+
+    /// Sets the access policy's `aws:SourceIp` condition to the CIDRs.
+    ///
+    /// # Parameters
+    ///
+    /// * `cidrs`: The CIDRs the condition should allow.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the policy was modified, `Ok(false)` if it was already in sync, or an
+    /// error if the condition cannot be changed safely or a request fails.
+    pub async fn apply(
+        &self,
+        cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self
+            .client
+            .describe_domain_config()
+            .domain_name(&self.domain_name)
+            .send()
+            .await?;
+        let document = response
+            .domain_config()
+            .and_then(|config| config.access_policies())
+            .map(|policies| policies.options())
+            .filter(|document| !document.is_empty())
+            .ok_or_else(|| {
+                format!(
+                    "OpenSearch domain {} has no access policy",
+                    self.domain_name
+                )
+            })?;
+
+        let Some(change) =
+            crate::policy::replace_condition(document, self.sid.as_deref(), "aws:SourceIp", cidrs)?
+        else {
+            return Ok(false);
+        };
+
+        self.client
+            .update_domain_config()
+            .domain_name(&self.domain_name)
+            .access_policies(change.document)
+            .send()
+            .await?;
+        info!(
+            "Updated OpenSearch domain {} access policy: aws:SourceIp {} -> {}",
+            self.domain_name,
+            change.previous.join(", "),
+            cidrs.join(", ")
+        );
+
+        Ok(true)
+    }
+}
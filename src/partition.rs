@@ -0,0 +1,203 @@
+use aws_config::Region;
+
+/// An AWS partition, a separate set of regions with its own accounts, credentials and ARNs.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Partition {
+    /// The commercial regions
+    #[default]
+    Aws,
+    /// AWS GovCloud (US): us-gov-west-1 and us-gov-east-1
+    AwsUsGov,
+    /// AWS China: cn-north-1 and cn-northwest-1
+    AwsCn,
+}
+
+impl Partition {
+    /// Determines the partition a region belongs to.
+    ///
+    /// # Parameters
+    ///
+    /// * `region`: The region name.
+    ///
+    /// # Returns
+    ///
+    /// The partition, or an error if the name is not a region or belongs to a partition this
+    /// monitor does not support.
+    pub fn of_region(region: &str) -> Result<Self, String> {
+        // Region names are a country or area code, a location and a number, e.g. eu-west-1
+        let parts: Vec<&str> = region.split('-').collect();
+        let valid = parts.len() >= 3
+            && parts[0].len() == 2
+            && parts.iter().all(|part| {
+                !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+            })
+            && parts[parts.len() - 1].chars().all(|c| c.is_ascii_digit());
+        if !valid {
+            return Err(format!(
+                "{} is not an AWS region name, such as us-east-1",
+                region
+            ));
+        }
+
+        match (parts[0], parts[1]) {
+            ("us", "gov") => Ok(Self::AwsUsGov),
+            ("cn", _) => Ok(Self::AwsCn),
+            ("us", "iso" | "isob" | "isof") | ("eu", "isoe") => Err(format!(
+                "Region {} is in an isolated partition, which is not supported",
+                region
+            )),
+            _ => Ok(Self::Aws),
+        }
+    }
+
+    /// Returns the partition of the configured region, assuming the commercial one without.
+    ///
+    /// # Parameters
+    ///
+    /// * `region`: The region of the AWS configuration, if any.
+    ///
+    /// # Returns
+    ///
+    /// The partition.
+    pub fn of_config(region: Option<&Region>) -> Self {
+        region
+            .and_then(|region| Self::of_region(region.as_ref()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Returns the partition's name as written in ARNs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Aws => "aws",
+            Self::AwsUsGov => "aws-us-gov",
+            Self::AwsCn => "aws-cn",
+        }
+    }
+
+    /// Builds the ARN of an IAM role in this partition.
+    ///
+    /// # Parameters
+    ///
+    /// * `account_id`: The account the role is in.
+    /// * `role_name`: The name of the role.
+    ///
+    /// # Returns
+    ///
+    /// The ARN of the role.
+    pub fn role_arn(&self, account_id: &str, role_name: &str) -> String {
+        format!("arn:{}:iam::{}:role/{}", self.name(), account_id, role_name)
+    }
+}
+
+impl std::fmt::Display for Partition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Checks that an ARN belongs to the partition the monitor runs in.
+///
+/// # Parameters
+///
+/// * `option`: The option the ARN was given in, for the error.
+/// * `arn`: The ARN.
+/// * `partition`: The partition of the configured region.
+///
+/// # Returns
+///
+/// An error if the ARN names another partition, or `Ok(())` if it matches or is not an ARN.
+fn check_arn(option: &str, arn: &str, partition: Partition) -> Result<(), String> {
+    let Some(rest) = arn.strip_prefix("arn:") else {
+        return Ok(());
+    };
+    let arn_partition = rest.split(':').next().unwrap_or_default();
+    if arn_partition == partition.name() {
+        return Ok(());
+    }
+
+    Err(format!(
+        "{} {} is in partition {}, but the region is in {}; use arn:{}:... for this region",
+        option,
+        arn,
+        arn_partition,
+        partition,
+        partition.name()
+    ))
+}
+
+/// Checks that the configured regions and ARNs all belong to one partition.
+///
+/// # Parameters
+///
+/// * `args`: The arguments passed to the program.
+/// * `region`: The region of the AWS configuration, if any.
+///
+/// # Returns
+///
+/// The partition, or an error naming the first setting in another partition.
+pub fn check(
+    args: &crate::Args,
+    region: Option<&Region>,
+) -> Result<Partition, Box<dyn std::error::Error + Send + Sync>> {
+    let partition = match (region, args.partition) {
+        (Some(region), expected) => {
+            let partition = Partition::of_region(region.as_ref())?;
+            if let Some(expected) = expected.filter(|expected| *expected != partition) {
+                return Err(format!(
+                    "Region {} is in partition {}, not {}; set --region to a region of {}",
+                    region, partition, expected, expected
+                )
+                .into());
+            }
+            partition
+        }
+        // Without a region the SDK cannot pick endpoints, so another partition needs one
+        (None, Some(expected)) if expected != Partition::Aws => {
+            return Err(format!(
+                "Partition {} needs a region; set --region or AWS_REGION",
+                expected
+            )
+            .into());
+        }
+        (None, _) => Partition::Aws,
+    };
+
+    if let Some(region) = &args.ip_source_region {
+        let source_partition = Partition::of_region(region)?;
+        if source_partition != partition {
+            return Err(format!(
+                "IP source region {} is in partition {}, but the region is in {}",
+                region, source_partition, partition
+            )
+            .into());
+        }
+    }
+    for target in &args.targets {
+        let target_partition = Partition::of_region(&target.region)?;
+        if target_partition != partition {
+            return Err(format!(
+                "Target region {} is in partition {}, but the region is in {}",
+                target.region, target_partition, partition
+            )
+            .into());
+        }
+    }
+    let arns = [
+        ("--role-arn", &args.role_arn),
+        ("--iam-policy-arn", &args.iam_policy_arn),
+        (
+            "--network-firewall-rule-group",
+            &args.network_firewall_rule_group,
+        ),
+    ];
+    for (option, arn) in arns {
+        if let Some(arn) = arn {
+            check_arn(option, arn, partition)?;
+        }
+    }
+
+    Ok(partition)
+}
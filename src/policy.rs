@@ -0,0 +1,160 @@
+use serde_json::Value;
+
+/// The result of rewriting the address condition of a policy statement.
+pub struct ConditionChange {
+    /// The addresses the condition allowed before.
+    pub previous: Vec<String>,
+    /// The rewritten policy document.
+    pub document: String,
+}
+
+/// Returns the statements of a policy document, whether given as one object or a list.
+///
+/// # Parameters
+///
+/// * `policy`: The parsed policy document.
+///
+/// # Returns
+///
+/// The statements, or an error if the document has none.
+fn statements(
+    policy: &mut Value,
+) -> Result<Vec<&mut Value>, Box<dyn std::error::Error + Send + Sync>> {
+    match policy.get_mut("Statement") {
+        Some(Value::Array(statements)) => Ok(statements.iter_mut().collect()),
+        Some(statement @ Value::Object(_)) => Ok(vec![statement]),
+        _ => Err("Policy document has no Statement".into()),
+    }
+}
+
+/// Removes one statement, leaving everything the monitor must not touch.
+///
+/// # Parameters
+///
+/// * `policy`: The parsed policy document.
+/// * `index`: The position of the statement.
+///
+/// # Returns
+///
+/// The document without that statement.
+fn without_statement(policy: &Value, index: usize) -> Value {
+    let mut policy = policy.clone();
+    match policy.get_mut("Statement") {
+        Some(Value::Array(statements)) => {
+            statements.remove(index);
+        }
+        Some(statement) => *statement = Value::Null,
+        None => {}
+    }
+    policy
+}
+
+/// Returns the address condition value of a statement.
+///
+/// # Parameters
+///
+/// * `statement`: The statement.
+/// * `key`: The condition key, e.g. `aws:SourceIp`.
+///
+/// # Returns
+///
+/// The value, under whichever operator holds the key, e.g. `IpAddress` or `NotIpAddress`.
+fn condition_value<'a>(statement: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    statement
+        .get_mut("Condition")
+        .and_then(Value::as_object_mut)?
+        .values_mut()
+        .filter_map(Value::as_object_mut)
+        .find_map(|keys| keys.get_mut(key))
+}
+
+/// Replaces the addresses of an address condition in one statement of a policy.
+///
+/// The statement is the one with the given `Sid`, or without one, the only statement with
+/// the condition. Only its condition key changes; the rest of the document is compared
+/// before and after and the change is refused if anything else differs.
+///
+/// # Parameters
+///
+/// * `document`: The policy document as JSON.
+/// * `sid`: The ID of the statement holding the condition, if statements are named.
+/// * `key`: The condition key, e.g. `aws:SourceIp`.
+/// * `cidrs`: The CIDRs the condition should allow.
+///
+/// # Returns
+///
+/// The previous addresses and the new document, `None` if the condition already allows
+/// exactly these CIDRs, or an error if the statement is missing or ambiguous or has no such
+/// condition.
+pub fn replace_condition(
+    document: &str,
+    sid: Option<&str>,
+    key: &str,
+    cidrs: &[String],
+) -> Result<Option<ConditionChange>, Box<dyn std::error::Error + Send + Sync>> {
+    let original: Value = serde_json::from_str(document)
+        .map_err(|e| format!("Policy document is not valid JSON: {}", e))?;
+    let mut policy = original.clone();
+    let described = match sid {
+        Some(sid) => format!("with Sid {}", sid),
+        None => format!("with a {} condition", key),
+    };
+
+    let mut matching: Vec<(usize, &mut Value)> = statements(&mut policy)?
+        .into_iter()
+        .enumerate()
+        .filter(|(_, statement)| match sid {
+            Some(sid) => statement.get("Sid") == Some(&Value::from(sid)),
+            None => statement
+                .get("Condition")
+                .and_then(Value::as_object)
+                .is_some_and(|operators| {
+                    operators
+                        .values()
+                        .filter_map(Value::as_object)
+                        .any(|keys| keys.contains_key(key))
+                }),
+        })
+        .collect();
+    let (index, statement) = match matching.as_mut_slice() {
+        [(index, statement)] => (*index, statement),
+        [] => return Err(format!("Policy has no statement {}", described).into()),
+        _ => return Err(format!("Policy has several statements {}", described).into()),
+    };
+
+    let value = condition_value(statement, key)
+        .ok_or_else(|| format!("Statement {} has no {} condition", described, key))?;
+    let previous: Vec<String> = match value {
+        Value::String(address) => vec![address.clone()],
+        Value::Array(addresses) => addresses
+            .iter()
+            .filter_map(|address| address.as_str().map(str::to_string))
+            .collect(),
+        _ => {
+            return Err(
+                format!("Condition {} of statement {} is not a list", key, described).into(),
+            )
+        }
+    };
+    let mut sorted_previous = previous.clone();
+    sorted_previous.sort();
+    let mut sorted_cidrs = cidrs.to_vec();
+    sorted_cidrs.sort();
+    if sorted_previous == sorted_cidrs {
+        return Ok(None);
+    }
+    *value = Value::from(cidrs.to_vec());
+
+    if without_statement(&policy, index) != without_statement(&original, index) {
+        return Err(format!(
+            "Refusing to modify the policy: the change reaches beyond the statement {}",
+            described
+        )
+        .into());
+    }
+
+    Ok(Some(ConditionChange {
+        previous,
+        document: serde_json::to_string(&policy)?,
+    }))
+}
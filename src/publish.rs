@@ -0,0 +1,141 @@
+use aws_config::SdkConfig;
+use aws_sdk_s3::{
+    primitives::{ByteStream, DateTime, DateTimeFormat},
+    types::ServerSideEncryption,
+};
+use aws_sdk_ssm::types::ParameterType;
+use std::time::SystemTime;
+use tracing::info;
+
+/// An SSM parameter that receives the current CIDR.
+struct SsmDestination {
+    /// The client instance used to write SSM parameters.
+    client: aws_sdk_ssm::Client,
+    /// The name of the parameter.
+    name: String,
+}
+
+/// An S3 object that receives a JSON document describing the current IP.
+struct S3Destination {
+    /// The client instance used to write S3 objects.
+    client: aws_sdk_s3::Client,
+    /// The bucket the document is written to.
+    bucket: String,
+    /// The object key template; `{host}` and `{prefix_list_id}` are substituted.
+    key_template: String,
+    /// The KMS key used for SSE-KMS encryption, if configured.
+    kms_key_id: Option<String>,
+}
+
+/// Publishes the current IP address to destinations consumed by other automation.
+pub struct Publisher {
+    /// The SSM parameter that receives the current CIDR, if configured.
+    ssm: Option<SsmDestination>,
+    /// The S3 object that receives the current IP document, if configured.
+    s3: Option<S3Destination>,
+    /// The hostname of the machine running the monitor.
+    host: String,
+}
+
+impl Publisher {
+    /// Creates a new instance of `Publisher`.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct service clients.
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Publisher`. Clients are only built for configured destinations.
+    pub fn new(config: &SdkConfig, args: &crate::Args) -> Self {
+        let ssm = args
+            .publish_ssm_parameter
+            .as_ref()
+            .map(|name| SsmDestination {
+                client: aws_sdk_ssm::Client::new(config),
+                name: name.clone(),
+            });
+        let s3 = args.publish_s3_bucket.as_ref().map(|bucket| S3Destination {
+            client: aws_sdk_s3::Client::new(config),
+            bucket: bucket.clone(),
+            key_template: args.publish_s3_key.clone(),
+            kms_key_id: args.publish_s3_kms_key_id.clone(),
+        });
+
+        Self {
+            ssm,
+            s3,
+            host: gethostname::gethostname().to_string_lossy().into_owned(),
+        }
+    }
+
+    /// Publishes the current IP to every configured destination.
+    ///
+    /// When several addresses are tracked, the SSM parameter holds them comma-separated and the
+    /// S3 document's `ip` and `cidr` fields hold the first, with all of them in `ips` and `cidrs`.
+    ///
+    /// # Parameters
+    ///
+    /// * `ips`: The current external IP addresses.
+    /// * `cidrs`: The CIDR format of the current IP addresses.
+    /// * `prefix_list_id`: The ID of the prefix list being monitored.
+    /// * `time`: When the addresses were applied, by the monitor's clock.
+    ///
+    /// # Returns
+    ///
+    /// An error if any destination fails, or `Ok(())` on success.
+    pub async fn publish(
+        &self,
+        ips: &[String],
+        cidrs: &[String],
+        prefix_list_id: &str,
+        time: SystemTime,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let cidr = cidrs.join(",");
+
+        if let Some(ssm) = &self.ssm {
+            ssm.client
+                .put_parameter()
+                .name(&ssm.name)
+                .value(&cidr)
+                .r#type(ParameterType::String)
+                .overwrite(true)
+                .send()
+                .await?;
+            info!("Published {} to SSM parameter {}", cidr, ssm.name);
+        }
+
+        if let Some(s3) = &self.s3 {
+            let key = s3
+                .key_template
+                .replace("{host}", &self.host)
+                .replace("{prefix_list_id}", prefix_list_id);
+            let document = serde_json::json!({
+                "ip": ips.first(),
+                "cidr": cidrs.first(),
+                "ips": ips,
+                "cidrs": cidrs,
+                "timestamp": DateTime::from(time).fmt(DateTimeFormat::DateTime)?,
+                "host": self.host,
+            });
+
+            let mut request = s3
+                .client
+                .put_object()
+                .bucket(&s3.bucket)
+                .key(&key)
+                .content_type("application/json")
+                .body(ByteStream::from(serde_json::to_vec(&document)?));
+            if let Some(kms_key_id) = &s3.kms_key_id {
+                request = request
+                    .server_side_encryption(ServerSideEncryption::AwsKms)
+                    .ssekms_key_id(kms_key_id);
+            }
+            request.send().await?;
+            info!("Published {} to s3://{}/{}", cidr, s3.bucket, key);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use crate::control::{self, Request};
+use aws_config::SdkConfig;
+use std::time::Duration;
+use tokio::time;
+use tracing::{debug, info, warn};
+
+/// How long a receive waits for a message before returning empty.
+const WAIT_TIME_SECONDS: i32 = 20;
+
+/// How long to wait after a failed receive before trying again.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Parses the body of a queue message into a request.
+///
+/// # Parameters
+///
+/// * `body`: The message body.
+///
+/// # Returns
+///
+/// `SetIp` for an IP address or CIDR, `Reconcile` for `reconcile`, and `Check` for anything
+/// else.
+fn parse_request(body: &str) -> Request {
+    let body = body.trim();
+    let address = body.split('/').next().unwrap_or_default();
+    if address.parse::<std::net::IpAddr>().is_ok() {
+        Request::SetIp(body.to_string())
+    } else if body.eq_ignore_ascii_case("reconcile") {
+        Request::Reconcile
+    } else {
+        Request::Check
+    }
+}
+
+/// Long-polls an SQS queue in the background, turning each message into a request to the
+/// monitors and deleting it.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration used to construct the SQS client.
+/// * `queue_url`: The URL of the queue.
+pub fn poll(config: &SdkConfig, queue_url: String) {
+    let client = aws_sdk_sqs::Client::new(config);
+    info!("Polling SQS queue {} for check requests", queue_url);
+
+    tokio::spawn(async move {
+        loop {
+            let output = match client
+                .receive_message()
+                .queue_url(&queue_url)
+                .wait_time_seconds(WAIT_TIME_SECONDS)
+                .max_number_of_messages(10)
+                .send()
+                .await
+            {
+                Ok(output) => output,
+                Err(e) => {
+                    warn!(
+                        "Failed to receive from SQS queue {}: {}",
+                        queue_url,
+                        crate::errors::describe(&e)
+                    );
+                    time::sleep(RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            for message in output.messages() {
+                let request = parse_request(message.body().unwrap_or_default());
+                info!("Received {:?} from SQS", request);
+                control::send(request);
+
+                // A message that cannot be deleted is delivered again after its visibility timeout
+                if let Some(receipt_handle) = message.receipt_handle() {
+                    if let Err(e) = client
+                        .delete_message()
+                        .queue_url(&queue_url)
+                        .receipt_handle(receipt_handle)
+                        .send()
+                        .await
+                    {
+                        warn!(
+                            "Failed to delete SQS message: {}",
+                            crate::errors::describe(&e)
+                        );
+                    } else {
+                        debug!(
+                            "Deleted SQS message {}",
+                            message.message_id().unwrap_or("?")
+                        );
+                    }
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,152 @@
+use std::io::{self, BufRead};
+
+/// The prefix of a value that names a secret in the OS keyring instead of holding it.
+const KEYRING_PREFIX: &str = "keyring:";
+
+/// The keyring service the secrets are stored under.
+#[cfg(feature = "keyring")]
+const SERVICE: &str = env!("CARGO_PKG_NAME");
+
+/// What the `secret` command does with a secret.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum SecretAction {
+    /// Store the secret read from stdin, replacing any stored before
+    Set,
+    /// Remove the secret from the keyring
+    Delete,
+}
+
+/// Opens the keyring entry of a secret.
+///
+/// # Parameters
+///
+/// * `name`: The name of the secret.
+///
+/// # Returns
+///
+/// The entry, or an error if the keyring cannot be used.
+#[cfg(feature = "keyring")]
+fn entry(name: &str) -> Result<keyring::Entry, Box<dyn std::error::Error + Send + Sync>> {
+    keyring::Entry::new(SERVICE, name)
+        .map_err(|e| format!("Cannot open keyring entry {}: {}", name, e).into())
+}
+
+/// Looks up a value naming a keyring secret.
+///
+/// # Parameters
+///
+/// * `value`: The value, `keyring:<name>` for a secret or anything else to keep it as is.
+///
+/// # Returns
+///
+/// The secret or the value as given, or an error if the value names a secret that cannot be
+/// read.
+fn resolve(value: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let Some(name) = value.strip_prefix(KEYRING_PREFIX) else {
+        return Ok(value.to_string());
+    };
+
+    #[cfg(feature = "keyring")]
+    {
+        entry(name)?
+            .get_password()
+            .map_err(|e| format!("Cannot read secret {} from the keyring: {}", name, e).into())
+    }
+    #[cfg(not(feature = "keyring"))]
+    Err(format!(
+        "Secret {} is in the OS keyring; rebuild with `--features keyring` to read it",
+        name
+    )
+    .into())
+}
+
+/// Replaces the secrets given as `keyring:<name>` with the secrets stored in the keyring.
+///
+/// Only the webhook URLs, the control token, the Cloudflare API token and the dynamic DNS
+/// providers are looked up, as they carry credentials.
+///
+/// # Parameters
+///
+/// * `args`: The arguments passed to the program.
+///
+/// # Returns
+///
+/// An error if a named secret cannot be read.
+pub fn resolve_args(
+    args: &mut crate::Args,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    for webhook in &mut args.notify_webhooks {
+        *webhook = resolve(webhook)?;
+    }
+    if let Some(token) = &mut args.control_token {
+        *token = resolve(token)?;
+    }
+    if let Some(token) = &mut args.cloudflare_api_token {
+        *token = resolve(token)?;
+    }
+    for provider in &mut args.dyndns {
+        *provider = crate::dyndns::check(&resolve(provider)?)?;
+    }
+
+    Ok(())
+}
+
+/// Stores or removes a secret in the OS keyring.
+///
+/// # Parameters
+///
+/// * `action`: Whether to store or remove the secret.
+/// * `name`: The name the secret is referred to by, as in `keyring:<name>`.
+///
+/// # Returns
+///
+/// An error if stdin cannot be read or the keyring cannot be used.
+pub fn run(
+    action: SecretAction,
+    name: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let secret = match action {
+        SecretAction::Set => {
+            let mut secret = String::new();
+            io::stdin().lock().read_line(&mut secret)?;
+            let secret = secret.trim_end_matches(['\r', '\n']).to_string();
+            if secret.is_empty() {
+                return Err("No secret on stdin".into());
+            }
+            Some(secret)
+        }
+        SecretAction::Delete => None,
+    };
+
+    #[cfg(feature = "keyring")]
+    {
+        let entry = entry(name)?;
+        match secret {
+            Some(secret) => {
+                entry
+                    .set_password(&secret)
+                    .map_err(|e| format!("Cannot store secret {}: {}", name, e))?;
+                println!(
+                    "Stored secret {}; refer to it as {}{}",
+                    name, KEYRING_PREFIX, name
+                );
+            }
+            None => {
+                entry
+                    .delete_credential()
+                    .map_err(|e| format!("Cannot delete secret {}: {}", name, e))?;
+                println!("Deleted secret {}", name);
+            }
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "keyring"))]
+    {
+        let _ = secret;
+        Err(format!(
+            "Cannot use the OS keyring for secret {}; rebuild with `--features keyring`",
+            name
+        )
+        .into())
+    }
+}
@@ -0,0 +1,534 @@
+use crate::ip_source::AddressFamily;
+use crate::target::UpdateOrder;
+use aws_config::SdkConfig;
+use aws_sdk_ec2::types::{Filter, IpPermission, IpRange, Ipv6Range, SecurityGroup};
+use std::fmt;
+use tracing::{debug, info};
+
+/// A protocol and port range opened by a managed ingress rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rule {
+    /// The IP protocol as AWS names it: `tcp`, `udp`, `icmp` or `-1` for all traffic.
+    protocol: String,
+    /// The first port, or -1 when the protocol has no ports.
+    from_port: i32,
+    /// The last port, or -1 when the protocol has no ports.
+    to_port: i32,
+}
+
+impl Rule {
+    /// Reads the protocol and ports of an existing permission.
+    ///
+    /// # Parameters
+    ///
+    /// * `permission`: The permission.
+    ///
+    /// # Returns
+    ///
+    /// The rule the permission implements.
+    fn from_permission(permission: &IpPermission) -> Self {
+        Self {
+            protocol: permission.ip_protocol().unwrap_or("-1").to_string(),
+            from_port: permission.from_port().unwrap_or(-1),
+            to_port: permission.to_port().unwrap_or(-1),
+        }
+    }
+
+    /// Starts a permission for the rule, without any ranges.
+    fn to_builder(&self) -> aws_sdk_ec2::types::builders::IpPermissionBuilder {
+        let builder = IpPermission::builder().ip_protocol(&self.protocol);
+        // All traffic has no ports
+        if self.protocol == "-1" {
+            return builder;
+        }
+        builder.from_port(self.from_port).to_port(self.to_port)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.protocol.as_str() {
+            "-1" => write!(f, "all"),
+            "icmp" => write!(f, "icmp"),
+            protocol if self.from_port == self.to_port => {
+                write!(f, "{}/{}", protocol, self.from_port)
+            }
+            protocol => write!(f, "{}/{}-{}", protocol, self.from_port, self.to_port),
+        }
+    }
+}
+
+/// Parses a security group rule given on the command line or in a configuration file.
+///
+/// # Parameters
+///
+/// * `value`: `tcp/PORT`, `udp/PORT` (a port or a `FIRST-LAST` range), `icmp` or `all`.
+///
+/// # Returns
+///
+/// The rule, or an error if the protocol or ports are not valid.
+pub fn parse_rule(value: &str) -> Result<Rule, String> {
+    let (protocol, ports) = match value.split_once('/') {
+        Some((protocol, ports)) => (protocol.to_ascii_lowercase(), Some(ports)),
+        None => (value.to_ascii_lowercase(), None),
+    };
+    let (protocol, from_port, to_port) = match (protocol.as_str(), ports) {
+        ("all", None) => ("-1", -1, -1),
+        ("icmp", None) => ("icmp", -1, -1),
+        ("tcp" | "udp", Some(ports)) => {
+            let (first, last) = ports.split_once('-').unwrap_or((ports, ports));
+            let parse = |port: &str| {
+                port.parse::<u16>()
+                    .map(i32::from)
+                    .map_err(|_| format!("Invalid port {} in rule {}", port, value))
+            };
+            let (first, last) = (parse(first)?, parse(last)?);
+            if first > last {
+                return Err(format!(
+                    "Port range {} is reversed in rule {}",
+                    ports, value
+                ));
+            }
+            (protocol.as_str(), first, last)
+        }
+        ("tcp" | "udp", None) => {
+            return Err(format!(
+                "Rule {} needs a port, e.g. {}/443",
+                value, protocol
+            ))
+        }
+        _ => {
+            return Err(format!(
+                "Invalid rule {}; expected tcp/PORT, udp/PORT, icmp or all",
+                value
+            ))
+        }
+    };
+
+    Ok(Rule {
+        protocol: protocol.to_string(),
+        from_port,
+        to_port,
+    })
+}
+
+/// Parses a security group tag given on the command line.
+///
+/// # Parameters
+///
+/// * `value`: The tag as `Key=Value`.
+///
+/// # Returns
+///
+/// The key and value, or an error if the value has no `=`.
+pub fn parse_tag(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("Security group tag must be Key=Value, got {}", value))
+}
+
+/// Security groups that receive ingress rules for the current CIDRs.
+///
+/// The groups are listed by ID or discovered by tag on every update, so groups created
+/// for new environments are picked up without a restart. The managed rules are recognized
+/// by their description, like the managed prefix list entries.
+pub struct SecurityGroups {
+    /// The client instance used to read and modify security groups.
+    client: aws_sdk_ec2::Client,
+    /// The security groups listed explicitly.
+    ids: Vec<String>,
+    /// The `Key=Value` tag carried by additional security groups, if any.
+    tag: Option<(String, String)>,
+    /// The protocols and ports opened for every CIDR.
+    rules: Vec<Rule>,
+    /// The description identifying the managed rules.
+    description: String,
+    /// The address family managed, if limited to one.
+    address_family: Option<AddressFamily>,
+    /// Whether stale rules are revoked before new ones are authorized.
+    remove_first: bool,
+    /// The IDs of the security groups found on the last lookup, sorted.
+    known_ids: Vec<String>,
+}
+
+impl SecurityGroups {
+    /// Builds the security groups from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration used to construct the EC2 client.
+    /// * `args`: The arguments passed to the program.
+    /// * `description`: The description identifying the managed rules.
+    ///
+    /// # Returns
+    ///
+    /// The security groups, or `None` when neither IDs nor a tag are configured.
+    pub fn from_args(config: &SdkConfig, args: &crate::Args, description: &str) -> Option<Self> {
+        if args.security_group_ids.is_empty() && args.security_group_tag.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            client: crate::replay::ec2_client(config.into()),
+            ids: args.security_group_ids.clone(),
+            tag: args.security_group_tag.clone(),
+            rules: args.security_group_rules.clone(),
+            description: description.to_string(),
+            address_family: args.address_family,
+            remove_first: args.update_order == UpdateOrder::RemoveFirst,
+            known_ids: Vec::new(),
+        })
+    }
+
+    /// Looks up the security groups listed by ID and those carrying the tag.
+    ///
+    /// # Returns
+    ///
+    /// The security groups, each once, or an error if a request fails.
+    async fn discover(
+        &self,
+    ) -> Result<Vec<SecurityGroup>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut groups: Vec<SecurityGroup> = Vec::new();
+
+        if !self.ids.is_empty() {
+            let response = self
+                .client
+                .describe_security_groups()
+                .set_group_ids(Some(self.ids.clone()))
+                .send()
+                .await?;
+            groups.extend(response.security_groups().iter().cloned());
+        }
+
+        if let Some((key, value)) = &self.tag {
+            let mut pages = self
+                .client
+                .describe_security_groups()
+                .filters(
+                    Filter::builder()
+                        .name(format!("tag:{}", key))
+                        .values(value)
+                        .build(),
+                )
+                .into_paginator()
+                .send();
+            while let Some(page) = pages.next().await {
+                for group in page?.security_groups() {
+                    if !groups.iter().any(|g| g.group_id() == group.group_id()) {
+                        groups.push(group.clone());
+                    }
+                }
+            }
+            if groups.is_empty() {
+                info!("No security groups carry the tag {}={}", key, value);
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Looks up the tagged security groups again, noting any that appeared or disappeared.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the set of security groups changed since the last lookup, or an error if
+    /// the request fails. Security groups listed only by ID never change.
+    pub async fn resolve(&mut self) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        if self.tag.is_none() {
+            return Ok(false);
+        }
+
+        let mut ids: Vec<String> = self
+            .discover()
+            .await?
+            .iter()
+            .filter_map(|group| group.group_id().map(str::to_string))
+            .collect();
+        ids.sort();
+        if ids == self.known_ids {
+            return Ok(false);
+        }
+
+        for id in ids.iter().filter(|id| !self.known_ids.contains(id)) {
+            info!("Discovered security group {}", id);
+        }
+        for id in self.known_ids.iter().filter(|id| !ids.contains(id)) {
+            info!("Security group {} no longer carries the tag", id);
+        }
+        self.known_ids = ids;
+
+        Ok(true)
+    }
+
+    /// Returns whether a CIDR belongs to the managed address family.
+    fn manages(&self, cidr: &str) -> bool {
+        let ip = cidr.split('/').next().unwrap_or_default();
+        self.address_family.is_none_or(|family| family.matches(ip))
+    }
+
+    /// Returns the managed rules of a security group.
+    ///
+    /// # Parameters
+    ///
+    /// * `group`: The security group.
+    ///
+    /// # Returns
+    ///
+    /// Each ingress rule and CIDR that carries the managed description, whatever its port.
+    fn managed_entries(&self, group: &SecurityGroup) -> Vec<(Rule, String)> {
+        let mut entries = Vec::new();
+        for permission in group.ip_permissions() {
+            let rule = Rule::from_permission(permission);
+            let v4 = permission
+                .ip_ranges()
+                .iter()
+                .filter(|range| range.description() == Some(self.description.as_str()))
+                .filter_map(|range| range.cidr_ip());
+            let v6 = permission
+                .ipv6_ranges()
+                .iter()
+                .filter(|range| range.description() == Some(self.description.as_str()))
+                .filter_map(|range| range.cidr_ipv6());
+            entries.extend(
+                v4.chain(v6)
+                    .filter(|cidr| self.manages(cidr))
+                    .map(|cidr| (rule.clone(), cidr.to_string())),
+            );
+        }
+        entries
+    }
+
+    /// Builds the ingress permissions for a set of rules and CIDRs.
+    ///
+    /// # Parameters
+    ///
+    /// * `entries`: The rules, each with a CIDR it allows.
+    ///
+    /// # Returns
+    ///
+    /// One permission per rule, with the managed description on each range.
+    fn permissions(&self, entries: &[(Rule, String)]) -> Vec<IpPermission> {
+        let mut rules: Vec<&Rule> = Vec::new();
+        for (rule, _) in entries {
+            if !rules.contains(&rule) {
+                rules.push(rule);
+            }
+        }
+
+        rules
+            .into_iter()
+            .map(|rule| {
+                let mut permission = rule.to_builder();
+                for (_, cidr) in entries.iter().filter(|(r, _)| r == rule) {
+                    permission = if cidr.contains(':') {
+                        permission.ipv6_ranges(
+                            Ipv6Range::builder()
+                                .cidr_ipv6(cidr)
+                                .description(&self.description)
+                                .build(),
+                        )
+                    } else {
+                        permission.ip_ranges(
+                            IpRange::builder()
+                                .cidr_ip(cidr)
+                                .description(&self.description)
+                                .build(),
+                        )
+                    };
+                }
+                permission.build()
+            })
+            .collect()
+    }
+
+    /// Allows rules in a security group.
+    ///
+    /// # Parameters
+    ///
+    /// * `group_id`: The ID of the security group.
+    /// * `entries`: The rules, each with a CIDR it allows; nothing is sent if empty.
+    ///
+    /// # Returns
+    ///
+    /// An error if the request fails, or `Ok(())` on success.
+    async fn authorize(
+        &self,
+        group_id: &str,
+        entries: &[(Rule, String)],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !entries.is_empty() {
+            self.client
+                .authorize_security_group_ingress()
+                .group_id(group_id)
+                .set_ip_permissions(Some(self.permissions(entries)))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Revokes rules from a security group.
+    ///
+    /// # Parameters
+    ///
+    /// * `group_id`: The ID of the security group.
+    /// * `entries`: The rules, each with a CIDR it allows; nothing is sent if empty.
+    ///
+    /// # Returns
+    ///
+    /// An error if the request fails, or `Ok(())` on success.
+    async fn revoke(
+        &self,
+        group_id: &str,
+        entries: &[(Rule, String)],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !entries.is_empty() {
+            self.client
+                .revoke_security_group_ingress()
+                .group_id(group_id)
+                .set_ip_permissions(Some(self.permissions(entries)))
+                .send()
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Brings the managed rules of every security group in line with the new CIDRs.
+    ///
+    /// Every configured rule is kept for every CIDR, so the rules change as a unit. New rules
+    /// are authorized before stale ones are revoked, so access is never cut off, unless the
+    /// update order asks for removing first.
+    ///
+    /// # Parameters
+    ///
+    /// * `new_cidrs`: The CIDRs the managed rules should allow.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if any security group was modified, or an error if a request fails.
+    pub async fn apply(
+        &self,
+        new_cidrs: &[String],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let desired: Vec<(Rule, String)> = self
+            .rules
+            .iter()
+            .flat_map(|rule| {
+                new_cidrs
+                    .iter()
+                    .filter(|cidr| self.manages(cidr))
+                    .map(move |cidr| (rule.clone(), cidr.clone()))
+            })
+            .collect();
+        let mut updated = false;
+
+        for group in self.discover().await? {
+            let group_id = group.group_id().unwrap_or_default();
+            let current = self.managed_entries(&group);
+            let added: Vec<_> = desired
+                .iter()
+                .filter(|entry| !current.contains(entry))
+                .cloned()
+                .collect();
+            let removed: Vec<_> = current
+                .iter()
+                .filter(|entry| !desired.contains(entry))
+                .cloned()
+                .collect();
+            if added.is_empty() && removed.is_empty() {
+                debug!("Security group {} is up to date", group_id);
+                continue;
+            }
+
+            if self.remove_first {
+                self.revoke(group_id, &removed).await?;
+                self.authorize(group_id, &added).await?;
+            } else {
+                self.authorize(group_id, &added).await?;
+                self.revoke(group_id, &removed).await?;
+            }
+            info!(
+                "Updated security group {} ({}): {} from {}",
+                group_id,
+                group.group_name().unwrap_or_default(),
+                self.rules
+                    .iter()
+                    .map(Rule::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                new_cidrs
+                    .iter()
+                    .filter(|cidr| self.manages(cidr))
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            updated = true;
+        }
+
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_are_parsed() {
+        let rule = |protocol: &str, from_port, to_port| Rule {
+            protocol: protocol.to_string(),
+            from_port,
+            to_port,
+        };
+        assert_eq!(parse_rule("tcp/443"), Ok(rule("tcp", 443, 443)));
+        assert_eq!(parse_rule("UDP/5000-5010"), Ok(rule("udp", 5000, 5010)));
+        assert_eq!(parse_rule("icmp"), Ok(rule("icmp", -1, -1)));
+        assert_eq!(parse_rule("all"), Ok(rule("-1", -1, -1)));
+    }
+
+    #[test]
+    fn rules_are_displayed_as_parsed() {
+        for value in ["tcp/443", "udp/5000-5010", "icmp", "all"] {
+            assert_eq!(parse_rule(value).unwrap().to_string(), value);
+        }
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for value in [
+            "tcp",
+            "tcp/",
+            "tcp/https",
+            "tcp/65536",
+            "udp/10-",
+            "sctp/443",
+            "icmp/8",
+            "all/0",
+        ] {
+            assert!(parse_rule(value).is_err(), "{} was accepted", value);
+        }
+        assert_eq!(
+            parse_rule("tcp/443-80"),
+            Err("Port range 443-80 is reversed in rule tcp/443-80".to_string())
+        );
+    }
+
+    #[test]
+    fn tags_are_split_at_the_first_equals_sign() {
+        assert_eq!(
+            parse_tag("Environment=prod"),
+            Ok(("Environment".to_string(), "prod".to_string()))
+        );
+        assert_eq!(
+            parse_tag("Filter=a=b"),
+            Ok(("Filter".to_string(), "a=b".to_string()))
+        );
+        assert_eq!(
+            parse_tag("Empty="),
+            Ok(("Empty".to_string(), String::new()))
+        );
+        assert!(parse_tag("Environment").is_err());
+    }
+}
@@ -0,0 +1,370 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The name the service is installed under.
+const SERVICE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// The launchd label of the service.
+#[cfg(target_os = "macos")]
+const LAUNCHD_LABEL: &str = concat!("com.github.kariudo.", env!("CARGO_PKG_NAME"));
+
+/// Quotes an argument of a systemd `ExecStart` line when it contains spaces or quotes.
+///
+/// # Parameters
+///
+/// * `argument`: The argument.
+///
+/// # Returns
+///
+/// The argument, in double quotes with escapes if needed.
+fn quote_systemd(argument: &str) -> String {
+    if !argument.contains([' ', '\t', '"', '\\', '\'']) {
+        return argument.to_string();
+    }
+
+    format!(
+        "\"{}\"",
+        argument.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Builds a systemd unit that runs the monitor with the given arguments.
+///
+/// # Parameters
+///
+/// * `arguments`: The arguments the monitor is started with, naming its settings.
+///
+/// # Returns
+///
+/// The contents of the unit file, or an error if the path of this binary is unknown.
+pub fn systemd_unit(
+    arguments: &[String],
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let executable = std::env::current_exe()?;
+    let command_line = std::iter::once(executable.display().to_string())
+        .chain(arguments.iter().cloned())
+        .chain(["--log-target".to_string(), "journald".to_string()])
+        .map(|argument| quote_systemd(&argument))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Ok(format!(
+        "[Unit]\n\
+         Description=AWS VPC prefix list monitor\n\
+         Wants=network-online.target\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         RestartSec=30\n\
+         \n\
+         [Install]\n\
+         WantedBy=multi-user.target\n",
+        command_line
+    ))
+}
+
+/// Builds a launchd property list that keeps the monitor running with the given arguments.
+///
+/// # Parameters
+///
+/// * `arguments`: The arguments the monitor is started with, naming its settings.
+///
+/// # Returns
+///
+/// The contents of the property list, or an error if the path of this binary is unknown.
+#[cfg(target_os = "macos")]
+fn launchd_plist(arguments: &[String]) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let escape = |value: &str| {
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let executable = std::env::current_exe()?;
+    let program_arguments = std::iter::once(executable.display().to_string())
+        .chain(arguments.iter().cloned())
+        .map(|argument| format!("    <string>{}</string>\n", escape(&argument)))
+        .collect::<String>();
+    let log = format!("/var/log/{}.log", SERVICE_NAME);
+
+    Ok(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+         \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n\
+         <dict>\n\
+         \x20 <key>Label</key>\n\
+         \x20 <string>{}</string>\n\
+         \x20 <key>ProgramArguments</key>\n\
+         \x20 <array>\n\
+         {}\
+         \x20 </array>\n\
+         \x20 <key>RunAtLoad</key>\n\
+         \x20 <true/>\n\
+         \x20 <key>KeepAlive</key>\n\
+         \x20 <true/>\n\
+         \x20 <key>StandardOutPath</key>\n\
+         \x20 <string>{}</string>\n\
+         \x20 <key>StandardErrorPath</key>\n\
+         \x20 <string>{}</string>\n\
+         </dict>\n\
+         </plist>\n",
+        LAUNCHD_LABEL, program_arguments, log, log
+    ))
+}
+
+/// Runs a service manager command, failing with its output if it does not succeed.
+///
+/// # Parameters
+///
+/// * `program`: The command.
+/// * `arguments`: Its arguments.
+///
+/// # Returns
+///
+/// An error if the command cannot be started or fails.
+fn run_command(
+    program: &str,
+    arguments: &[&str],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let output = Command::new(program)
+        .args(arguments)
+        .output()
+        .map_err(|e| format!("Cannot run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} {} failed: {}",
+            program,
+            arguments.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Writes a service definition, explaining that installing needs an administrator.
+///
+/// # Parameters
+///
+/// * `path`: The file to write.
+/// * `contents`: The definition.
+///
+/// # Returns
+///
+/// An error if the file cannot be written.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn write_definition(
+    path: &Path,
+    contents: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    std::fs::write(path, contents).map_err(|e| {
+        let hint = if e.kind() == std::io::ErrorKind::PermissionDenied {
+            "; run install-service with sudo"
+        } else {
+            ""
+        };
+        format!("Cannot write {}: {}{}", path.display(), e, hint)
+    })?;
+    println!("Wrote {}", path.display());
+
+    Ok(())
+}
+
+/// Removes a service definition, if present.
+///
+/// # Parameters
+///
+/// * `path`: The file to remove.
+///
+/// # Returns
+///
+/// An error if the file exists but cannot be removed.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn remove_definition(path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {
+            println!("Removed {}", path.display());
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Cannot remove {}: {}", path.display(), e).into()),
+    }
+}
+
+/// Installs or removes the systemd unit.
+#[cfg(target_os = "linux")]
+fn apply(
+    uninstall: bool,
+    arguments: &[String],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let unit_name = format!("{}.service", SERVICE_NAME);
+    let path = Path::new("/etc/systemd/system").join(&unit_name);
+    if uninstall {
+        // A unit that is not loaded cannot be stopped, which is fine when removing it
+        let _ = run_command("systemctl", &["disable", "--now", &unit_name]);
+        remove_definition(&path)?;
+        run_command("systemctl", &["daemon-reload"])?;
+        println!("Uninstalled {}", unit_name);
+        return Ok(());
+    }
+
+    write_definition(&path, &systemd_unit(arguments)?)?;
+    run_command("systemctl", &["daemon-reload"])?;
+    run_command("systemctl", &["enable", "--now", &unit_name])?;
+    println!(
+        "Installed and started {}; follow it with `journalctl -u {} -f`",
+        unit_name, unit_name
+    );
+
+    Ok(())
+}
+
+/// Installs or removes the launchd daemon.
+#[cfg(target_os = "macos")]
+fn apply(
+    uninstall: bool,
+    arguments: &[String],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = Path::new("/Library/LaunchDaemons").join(format!("{}.plist", LAUNCHD_LABEL));
+    let target = format!("system/{}", LAUNCHD_LABEL);
+    if uninstall {
+        let _ = run_command("launchctl", &["bootout", &target]);
+        remove_definition(&path)?;
+        println!("Uninstalled {}", LAUNCHD_LABEL);
+        return Ok(());
+    }
+
+    // A daemon installed before must be unloaded to pick up the new definition
+    let _ = run_command("launchctl", &["bootout", &target]);
+    write_definition(&path, &launchd_plist(arguments)?)?;
+    run_command(
+        "launchctl",
+        &["bootstrap", "system", &path.display().to_string()],
+    )?;
+    println!(
+        "Installed and started {}; its log is /var/log/{}.log",
+        LAUNCHD_LABEL, SERVICE_NAME
+    );
+
+    Ok(())
+}
+
+/// Installs or removes the scheduled task that starts the monitor at boot.
+///
+/// The binary does not speak the service control protocol, so it is registered as a task
+/// running as SYSTEM at startup rather than with the service control manager.
+#[cfg(windows)]
+fn apply(
+    uninstall: bool,
+    arguments: &[String],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if uninstall {
+        let _ = run_command("schtasks", &["/End", "/TN", SERVICE_NAME]);
+        run_command("schtasks", &["/Delete", "/TN", SERVICE_NAME, "/F"])?;
+        println!("Uninstalled {}", SERVICE_NAME);
+        return Ok(());
+    }
+
+    let executable = std::env::current_exe()?;
+    let command_line = std::iter::once(executable.display().to_string())
+        .chain(arguments.iter().cloned())
+        .chain(["--event-log".to_string()])
+        .map(|argument| {
+            if argument.contains(' ') {
+                format!("\"{}\"", argument)
+            } else {
+                argument
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    run_command(
+        "schtasks",
+        &[
+            "/Create",
+            "/TN",
+            SERVICE_NAME,
+            "/TR",
+            &command_line,
+            "/SC",
+            "ONSTART",
+            "/RU",
+            "SYSTEM",
+            "/RL",
+            "HIGHEST",
+            "/F",
+        ],
+    )?;
+    run_command("schtasks", &["/Run", "/TN", SERVICE_NAME])?;
+    println!(
+        "Installed and started the {} task; it reports to the Windows Event Log",
+        SERVICE_NAME
+    );
+
+    Ok(())
+}
+
+/// Reports that this platform has no supported service manager.
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn apply(
+    _uninstall: bool,
+    _arguments: &[String],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    Err(
+        "install-service supports systemd, launchd and Windows; write a service definition \
+         for this platform by hand"
+            .into(),
+    )
+}
+
+/// Installs the monitor as a service started at boot with the current settings, or removes it.
+///
+/// The service runs this binary from where it is now, with the environment file and jobs
+/// file in effect, so it runs exactly as the monitor does when started by hand.
+///
+/// # Parameters
+///
+/// * `uninstall`: Whether to stop and remove the service instead.
+/// * `env_file`: The environment file loaded at startup, if any.
+/// * `config`: The jobs file, if any.
+///
+/// # Returns
+///
+/// An error if there are no settings to run with or the service manager fails.
+pub fn run(
+    uninstall: bool,
+    env_file: Option<&Path>,
+    config: Option<&Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if uninstall {
+        return apply(true, &[]);
+    }
+
+    // Services start elsewhere than the working directory, so the paths must be absolute
+    let absolute = |path: &Path| -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(std::path::absolute(path)?)
+    };
+    let mut arguments = Vec::new();
+    if let Some(env_file) = env_file {
+        arguments.push("--env-file".to_string());
+        arguments.push(absolute(env_file)?.display().to_string());
+    }
+    if let Some(config) = config {
+        arguments.push("--config".to_string());
+        arguments.push(absolute(config)?.display().to_string());
+    }
+    if arguments.is_empty() {
+        return Err(
+            "No settings for the service to run with; write them with `init`, or name them \
+             with --env-file or --config"
+                .into(),
+        );
+    }
+
+    apply(false, &arguments)
+}
@@ -0,0 +1,246 @@
+use crate::{EntryChange, PrefixListMonitor};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// One check of a simulation timeline.
+struct Step {
+    /// The line of the timeline the step is on.
+    line: usize,
+    /// The addresses detected at this check, or `None` if detection fails.
+    ips: Option<Vec<String>>,
+    /// The outcome the step must have, if asserted.
+    expected: Option<String>,
+}
+
+/// Reads a simulation timeline.
+///
+/// Each line is one check: the detected addresses, comma-separated, or `fail` for a failed
+/// detection, optionally followed by `=>` and the expected outcome. Blank lines and text
+/// after `#` are ignored.
+///
+/// # Parameters
+///
+/// * `contents`: The contents of the timeline file.
+///
+/// # Returns
+///
+/// The steps, or an error naming the first line that is not valid.
+fn parse_timeline(contents: &str) -> Result<Vec<Step>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut steps = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let (detected, expected) = match line.split_once("=>") {
+            Some((detected, expected)) => (detected.trim(), Some(normalize(expected))),
+            None => (line, None),
+        };
+        let ips = if detected.eq_ignore_ascii_case("fail") {
+            None
+        } else {
+            let ips = detected
+                .split(',')
+                .map(|ip| {
+                    ip.trim()
+                        .parse::<IpAddr>()
+                        .map(|ip| ip.to_string())
+                        .map_err(|_| format!("line {}: not an IP address: {}", number + 1, ip))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(ips)
+        };
+
+        steps.push(Step {
+            line: number + 1,
+            ips,
+            expected,
+        });
+    }
+
+    if steps.is_empty() {
+        return Err("The timeline has no steps".into());
+    }
+
+    Ok(steps)
+}
+
+/// Collapses the whitespace of an outcome, so expectations can be spaced freely.
+fn normalize(outcome: &str) -> String {
+    outcome.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Describes the change made at a step.
+///
+/// # Parameters
+///
+/// * `change`: The entries added and removed.
+///
+/// # Returns
+///
+/// `applied` followed by each added (`+`) and removed (`-`) CIDR, or `unchanged` if nothing
+/// changed.
+fn describe(change: &EntryChange) -> String {
+    if change.is_empty() {
+        return "unchanged".to_string();
+    }
+
+    let mut outcome = vec!["applied".to_string()];
+    outcome.extend(change.added.iter().map(|cidr| format!("+{}", cidr)));
+    outcome.extend(change.removed.iter().map(|cidr| format!("-{}", cidr)));
+    outcome.join(" ")
+}
+
+/// Compares the outcome of a step with the expected one.
+///
+/// # Parameters
+///
+/// * `outcome`: The outcome of the step.
+/// * `expected`: The expected outcome; `failed` alone accepts any failure, and `failed: text`
+///   any failure mentioning the text.
+///
+/// # Returns
+///
+/// Whether the outcome is the expected one.
+fn matches(outcome: &str, expected: &str) -> bool {
+    if let Some(text) = expected.strip_prefix("failed") {
+        let text = text.trim_start_matches(':').trim();
+        return outcome.starts_with("failed") && outcome.contains(text);
+    }
+
+    // The CIDRs of a change may be listed in any order
+    let mut outcome: Vec<_> = outcome.split(' ').collect();
+    let mut expected: Vec<_> = expected.split(' ').collect();
+    outcome.sort_unstable();
+    expected.sort_unstable();
+    outcome == expected
+}
+
+/// Runs a step through the monitor without touching AWS, against simulated entries.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor whose change detection and verification are exercised.
+/// * `ips`: The detected addresses.
+/// * `entries`: The managed entries left by the previous steps, updated by this one.
+///
+/// # Returns
+///
+/// The change made, or an error if the addresses are rejected.
+async fn dry_run(
+    monitor: &mut PrefixListMonitor,
+    ips: Vec<String>,
+    entries: &mut Vec<String>,
+) -> Result<EntryChange, Box<dyn std::error::Error + Send + Sync>> {
+    let ips = monitor.select_family(ips)?;
+    if monitor.current_ips.as_ref() == Some(&ips) {
+        return Ok(EntryChange::default());
+    }
+    monitor.verifier.verify(&ips).await?;
+
+    let cidrs = monitor.to_cidrs(&ips);
+    let change = EntryChange::between(entries, &cidrs);
+    *entries = cidrs;
+    monitor.current_ips = Some(ips);
+
+    Ok(change)
+}
+
+/// Runs a step through the monitor's full update, modifying the configured prefix lists.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor updating the prefix lists.
+/// * `ips`: The detected addresses.
+/// * `entries`: The managed entries left by the previous steps, updated by this one.
+///
+/// # Returns
+///
+/// The change made, or an error if the addresses are rejected or the update fails.
+async fn apply(
+    monitor: &mut PrefixListMonitor,
+    ips: Vec<String>,
+    entries: &mut Vec<String>,
+) -> Result<EntryChange, Box<dyn std::error::Error + Send + Sync>> {
+    let ips = monitor.select_family(ips)?;
+    let cidrs = monitor.to_cidrs(&ips);
+    if !monitor.update_to(ips).await? {
+        return Ok(EntryChange::default());
+    }
+
+    let change = EntryChange::between(entries, &cidrs);
+    *entries = cidrs;
+    Ok(change)
+}
+
+/// Feeds a scripted timeline of detected addresses through the monitor, printing the outcome
+/// of each step and checking it against the expected one.
+///
+/// # Parameters
+///
+/// * `monitor`: The monitor the timeline runs through.
+/// * `path`: The timeline file.
+/// * `apply_changes`: Whether to modify the configured prefix lists, such as LocalStack ones,
+///   rather than simulated entries starting empty.
+///
+/// # Returns
+///
+/// `Ok(true)` if any step had an outcome other than the expected one, or an error if the
+/// timeline cannot be read or the prefix lists cannot be located.
+pub async fn run(
+    monitor: &mut PrefixListMonitor,
+    path: &Path,
+    apply_changes: bool,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read timeline {}: {}", path.display(), e))?;
+    let steps = parse_timeline(&contents)
+        .map_err(|e| format!("Invalid timeline {}: {}", path.display(), e))?;
+
+    let mut entries = Vec::new();
+    if apply_changes {
+        monitor.resolve_targets().await?;
+        for destination in &mut monitor.destinations {
+            destination.resolve().await?;
+        }
+        if let Some(target) = monitor.targets.first() {
+            entries = target.get_current_entries(&monitor.description).await?;
+        }
+    }
+
+    let total = steps.len();
+    let mut mismatches = 0;
+    for step in steps {
+        let detected = step
+            .ips
+            .as_ref()
+            .map_or_else(|| "fail".to_string(), |ips| ips.join(","));
+        let result = match step.ips {
+            None => Err("IP detection failed (scripted)".into()),
+            Some(ips) if apply_changes => apply(monitor, ips, &mut entries).await,
+            Some(ips) => dry_run(monitor, ips, &mut entries).await,
+        };
+        let outcome = match result {
+            Ok(change) => describe(&change),
+            Err(e) => normalize(&format!("failed: {}", e)),
+        };
+
+        println!("line {}: {} => {}", step.line, detected, outcome);
+        if let Some(expected) = step.expected {
+            if !matches(&outcome, &expected) {
+                println!("  expected {}", expected);
+                mismatches += 1;
+            }
+        }
+    }
+
+    if mismatches > 0 {
+        println!(
+            "{} of {} steps did not have the expected outcome",
+            mismatches, total
+        );
+    }
+
+    Ok(mismatches > 0)
+}
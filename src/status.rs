@@ -0,0 +1,436 @@
+use aws_smithy_types::date_time::{DateTime, Format};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::SystemTime;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// The version and build details printed by `--version`.
+pub const LONG_VERSION: &str = concat!(
+    env!("CARGO_PKG_VERSION"),
+    "\ncommit:   ",
+    env!("BUILD_GIT_SHA"),
+    "\nbuilt:    ",
+    env!("BUILD_TIMESTAMP"),
+    "\ntarget:   ",
+    env!("BUILD_TARGET"),
+    "\nfeatures: ",
+    env!("BUILD_FEATURES"),
+);
+
+/// When the process started, for the uptime.
+static STARTED: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// The status of every running monitor, by name.
+static MONITORS: LazyLock<Mutex<BTreeMap<String, MonitorStatus>>> = LazyLock::new(Default::default);
+
+/// The number of history entries kept; the oldest are dropped first.
+const HISTORY_CAPACITY: usize = 1000;
+
+/// The IP changes and updates of every monitor since startup, oldest first.
+static HISTORY: LazyLock<Mutex<VecDeque<HistoryEntry>>> = LazyLock::new(Default::default);
+
+/// What a monitor has done since startup.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MonitorStatus {
+    /// The IP addresses currently applied, if known.
+    pub current_ips: Option<Vec<String>>,
+    /// The checks performed.
+    pub checks: u64,
+    /// The checks that updated a prefix list.
+    pub updates: u64,
+    /// The checks that failed since the last successful one.
+    pub consecutive_failures: u64,
+    /// The description of the last failure, if any.
+    pub last_error: Option<String>,
+    /// Whether every target holds the CIDRs last applied.
+    #[serde(default)]
+    pub apply_state: ApplyState,
+    /// The targets still to be updated with the CIDRs last applied.
+    #[serde(default)]
+    pub pending_targets: Vec<String>,
+    /// How each HTTP detection service has answered.
+    #[serde(default)]
+    pub services: Vec<ServiceHealth>,
+    /// The checks cancelled by the watchdog because they stopped making progress.
+    #[serde(default)]
+    pub stalls: u64,
+}
+
+/// How an HTTP detection service has answered since startup.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ServiceHealth {
+    /// The URL of the service.
+    pub url: String,
+    /// The requests answered with an address.
+    pub successes: u64,
+    /// The requests that failed or were answered with something other than an address.
+    pub failures: u64,
+    /// The answers contradicted by another service.
+    pub outliers: u64,
+    /// The average time taken by the successful requests, if any.
+    pub average_latency_ms: Option<u64>,
+    /// Whether the service is skipped because it kept failing.
+    pub quarantined: bool,
+}
+
+/// How far the CIDRs last applied have reached a monitor's targets.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ApplyState {
+    /// Every target holds the CIDRs.
+    #[default]
+    AllApplied,
+    /// Some targets failed to update and are retried on the next check.
+    PartiallyApplied,
+}
+
+/// What happened in a history entry.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryKind {
+    /// A check detected addresses other than those applied.
+    IpChanged,
+    /// The detected addresses were applied to the targets.
+    UpdateApplied,
+}
+
+/// An IP change or update, as served on `GET /history`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// When it happened, in RFC 3339.
+    pub time: String,
+    /// The name of the monitor.
+    pub monitor: String,
+    /// Whether the addresses were detected or applied.
+    pub kind: HistoryKind,
+    /// The addresses before, if known.
+    pub previous: Option<Vec<String>>,
+    /// The addresses after.
+    pub current: Vec<String>,
+}
+
+/// The status of the daemon, as served on `GET /status`.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Status {
+    /// Seconds since the daemon started.
+    pub uptime_seconds: u64,
+    /// Whether updates are paused.
+    #[serde(default)]
+    pub paused: bool,
+    /// The status of each monitor, by name.
+    pub monitors: BTreeMap<String, MonitorStatus>,
+}
+
+/// The build the daemon is running, as served on `GET /status/version`.
+#[derive(Debug, Serialize)]
+pub struct Version {
+    /// The package version.
+    pub version: &'static str,
+    /// The abbreviated git commit the binary was built from.
+    pub commit: &'static str,
+    /// When the binary was built, in RFC 3339.
+    pub built: &'static str,
+    /// The target triple the binary was built for.
+    pub target: &'static str,
+    /// The Cargo features enabled in the build.
+    pub features: Vec<&'static str>,
+}
+
+/// Returns the details of the running build.
+pub fn version() -> Version {
+    Version {
+        version: env!("CARGO_PKG_VERSION"),
+        commit: env!("BUILD_GIT_SHA"),
+        built: env!("BUILD_TIMESTAMP"),
+        target: env!("BUILD_TARGET"),
+        features: env!("BUILD_FEATURES")
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .collect(),
+    }
+}
+
+/// Starts the uptime clock.
+pub fn init() {
+    LazyLock::force(&STARTED);
+}
+
+/// Records the outcome of a monitor's check and reports the new status to systemd.
+///
+/// # Parameters
+///
+/// * `name`: The name of the monitor.
+/// * `current_ips`: The IP addresses applied after the check, if known.
+/// * `outcome`: Whether the check updated a prefix list, or the description of its failure.
+pub fn record_check(name: &str, current_ips: Option<&[String]>, outcome: Result<bool, &str>) {
+    let Ok(mut monitors) = MONITORS.lock() else {
+        return;
+    };
+    let status = monitors.entry(name.to_string()).or_default();
+    status.checks += 1;
+    status.current_ips = current_ips.map(<[String]>::to_vec);
+    match outcome {
+        Ok(updated) => {
+            status.updates += u64::from(updated);
+            status.consecutive_failures = 0;
+        }
+        Err(error) => {
+            status.consecutive_failures += 1;
+            status.last_error = Some(error.to_string());
+        }
+    }
+
+    let line = monitors
+        .iter()
+        .map(|(name, status)| format!("{}: {}", name, describe(status)))
+        .collect::<Vec<_>>()
+        .join("; ");
+    drop(monitors);
+    notify_systemd(&format!("READY=1\nSTATUS={}", line));
+}
+
+/// Records which of a monitor's targets hold the CIDRs being applied.
+///
+/// # Parameters
+///
+/// * `name`: The name of the monitor.
+/// * `pending`: The targets still to be updated.
+pub fn record_targets(name: &str, pending: Vec<String>) {
+    let Ok(mut monitors) = MONITORS.lock() else {
+        return;
+    };
+    let status = monitors.entry(name.to_string()).or_default();
+    status.apply_state = if pending.is_empty() {
+        ApplyState::AllApplied
+    } else {
+        ApplyState::PartiallyApplied
+    };
+    status.pending_targets = pending;
+}
+
+/// Records how a monitor's detection services have answered.
+///
+/// # Parameters
+///
+/// * `name`: The name of the monitor.
+/// * `services`: The health of each service.
+pub fn record_services(name: &str, services: Vec<ServiceHealth>) {
+    let Ok(mut monitors) = MONITORS.lock() else {
+        return;
+    };
+    monitors.entry(name.to_string()).or_default().services = services;
+}
+
+/// Records a check cancelled by the watchdog.
+///
+/// # Parameters
+///
+/// * `name`: The name of the monitor.
+pub fn record_stall(name: &str) {
+    let Ok(mut monitors) = MONITORS.lock() else {
+        return;
+    };
+    monitors.entry(name.to_string()).or_default().stalls += 1;
+}
+
+/// Records an IP change or update in the history.
+///
+/// # Parameters
+///
+/// * `name`: The name of the monitor.
+/// * `kind`: Whether the addresses were detected or applied.
+/// * `previous`: The addresses before, if known.
+/// * `current`: The addresses after.
+/// * `time`: When the change happened, by the monitor's clock.
+pub fn record_history(
+    name: &str,
+    kind: HistoryKind,
+    previous: Option<&[String]>,
+    current: &[String],
+    time: SystemTime,
+) {
+    let Ok(mut history) = HISTORY.lock() else {
+        return;
+    };
+    // A change that keeps failing is detected again on every check, but happened once
+    let last = history.iter().rev().find(|entry| entry.monitor == name);
+    if last.is_some_and(|last| last.kind == kind && last.current == current) {
+        return;
+    }
+    if history.len() == HISTORY_CAPACITY {
+        history.pop_front();
+    }
+    history.push_back(HistoryEntry {
+        time: DateTime::from(time)
+            .fmt(Format::DateTime)
+            .unwrap_or_default(),
+        monitor: name.to_string(),
+        kind,
+        previous: previous.map(<[String]>::to_vec),
+        current: current.to_vec(),
+    });
+}
+
+/// Returns the most recent history entries.
+///
+/// # Parameters
+///
+/// * `limit`: The most entries returned.
+///
+/// # Returns
+///
+/// The entries, newest first.
+pub fn history(limit: usize) -> Vec<HistoryEntry> {
+    HISTORY
+        .lock()
+        .map(|history| history.iter().rev().take(limit).cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Returns the status of the daemon.
+pub fn current() -> Status {
+    Status {
+        uptime_seconds: STARTED.elapsed().as_secs(),
+        paused: crate::control::paused(),
+        monitors: MONITORS
+            .lock()
+            .map(|monitors| monitors.clone())
+            .unwrap_or_default(),
+    }
+}
+
+/// Summarizes a monitor's status on one line.
+///
+/// # Parameters
+///
+/// * `status`: The status of the monitor.
+///
+/// # Returns
+///
+/// The current IP, the counters and the targets still pending, if any.
+fn describe(status: &MonitorStatus) -> String {
+    let mut line = format!(
+        "IP {}, {} checks, {} updates, {} consecutive failures",
+        status
+            .current_ips
+            .as_ref()
+            .map(|ips| ips.join(", "))
+            .unwrap_or_else(|| "unknown".to_string()),
+        status.checks,
+        status.updates,
+        status.consecutive_failures
+    );
+    if status.apply_state == ApplyState::PartiallyApplied {
+        line.push_str(&format!(
+            ", partially applied (pending: {})",
+            status.pending_targets.join(", ")
+        ));
+    }
+    if status.stalls > 0 {
+        line.push_str(&format!(", {} stalled checks cancelled", status.stalls));
+    }
+
+    line
+}
+
+/// Sends a state update to systemd when it started the process with `NOTIFY_SOCKET` set.
+///
+/// # Parameters
+///
+/// * `state`: The newline-separated assignments, e.g. `STATUS=...`.
+fn notify_systemd(state: &str) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::net::UnixDatagram;
+
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return;
+        };
+        let result = UnixDatagram::unbound().and_then(|socket| {
+            let path = path.to_string_lossy();
+            // A leading @ names a socket in the abstract namespace
+            #[cfg(target_os = "linux")]
+            if let Some(name) = path.strip_prefix('@') {
+                use std::os::linux::net::SocketAddrExt;
+
+                let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                return socket.send_to_addr(state.as_bytes(), &address);
+            }
+            socket.send_to(state.as_bytes(), path.as_ref())
+        });
+        if let Err(e) = result {
+            debug!("Failed to notify systemd: {}", e);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// Prints the status of the running daemon, read from its control server.
+///
+/// # Parameters
+///
+/// * `address`: The address of the control server.
+/// * `token`: The bearer token that authenticates control requests.
+///
+/// # Returns
+///
+/// An error if the daemon cannot be reached or answers with an error, or `Ok(())` on success.
+pub async fn show(
+    address: SocketAddr,
+    token: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let response = reqwest::Client::new()
+        .get(format!("http://{}/status", address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| {
+            format!(
+                "Cannot reach the daemon's control server at {}: {}",
+                address, e
+            )
+        })?
+        .error_for_status()?;
+    let status: Status = serde_json::from_str(&response.text().await?)?;
+
+    println!(
+        "Uptime: {}",
+        crate::notify::format_duration(std::time::Duration::from_secs(status.uptime_seconds))
+    );
+    if status.paused {
+        println!("Updates: paused; changes are detected but not written");
+    }
+    for (name, monitor) in &status.monitors {
+        println!("{}: {}", name, describe(monitor));
+        if let Some(error) = &monitor.last_error {
+            println!("  last error: {}", error);
+        }
+        // A single service has nothing to be compared with
+        if monitor.services.len() > 1 {
+            for service in &monitor.services {
+                println!(
+                    "  {}: {} ok, {} failed, {} outliers{}{}",
+                    service.url,
+                    service.successes,
+                    service.failures,
+                    service.outliers,
+                    service
+                        .average_latency_ms
+                        .map(|latency| format!(", {}ms average", latency))
+                        .unwrap_or_default(),
+                    if service.quarantined {
+                        ", quarantined"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
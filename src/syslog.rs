@@ -0,0 +1,210 @@
+use std::io::{self, Write};
+use std::net::{TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::{Arc, Mutex};
+use tracing::{Level, Metadata};
+use tracing_subscriber::fmt::{
+    format::Writer,
+    time::{FormatTime, SystemTime},
+    MakeWriter,
+};
+
+/// The facility messages are logged under (`daemon`).
+const FACILITY: u8 = 3;
+
+/// The name messages are logged under.
+const APP_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// The address logged to when none is given: the local syslog socket.
+pub const DEFAULT_ADDRESS: &str = "unix:///dev/log";
+
+/// How messages reach the syslog server.
+enum Transport {
+    /// One datagram per message.
+    Udp(UdpSocket),
+    /// A stream of octet-counted messages (RFC 6587), reconnected when it breaks.
+    Tcp {
+        /// The address of the server.
+        address: String,
+        /// The connection, if open.
+        stream: Option<TcpStream>,
+    },
+    /// One datagram per message over a local socket.
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+}
+
+impl Transport {
+    /// Sends a formatted message.
+    ///
+    /// # Parameters
+    ///
+    /// * `message`: The RFC 5424 message.
+    ///
+    /// # Returns
+    ///
+    /// An error if the message cannot be sent, or `Ok(())` on success.
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Transport::Udp(socket) => socket.send(message).map(|_| ()),
+            Transport::Tcp { address, stream } => {
+                let mut framed = format!("{} ", message.len()).into_bytes();
+                framed.extend_from_slice(message);
+                // A broken connection is reopened once per message
+                for _ in 0..2 {
+                    if stream.is_none() {
+                        *stream = Some(TcpStream::connect(address.as_str())?);
+                    }
+                    if let Some(open) = stream.as_mut() {
+                        if open.write_all(&framed).is_ok() {
+                            return Ok(());
+                        }
+                    }
+                    *stream = None;
+                }
+                Err(io::Error::other("syslog connection lost"))
+            }
+            #[cfg(unix)]
+            Transport::Unix(socket) => socket.send(message).map(|_| ()),
+        }
+    }
+}
+
+/// Sends log lines to a syslog server as RFC 5424 messages.
+#[derive(Clone)]
+pub struct Syslog {
+    /// The connection to the server, shared by every writer.
+    transport: Arc<Mutex<Transport>>,
+    /// The host name reported in each message.
+    hostname: String,
+}
+
+impl Syslog {
+    /// Connects to a syslog server.
+    ///
+    /// # Parameters
+    ///
+    /// * `address`: `udp://HOST:PORT`, `tcp://HOST:PORT` or `unix://PATH`.
+    ///
+    /// # Returns
+    ///
+    /// The syslog sink, or an error if the address is malformed or cannot be reached.
+    pub fn connect(address: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let transport = if let Some(server) = address.strip_prefix("udp://") {
+            let bind = if server.starts_with('[') {
+                "[::]:0"
+            } else {
+                "0.0.0.0:0"
+            };
+            let socket = UdpSocket::bind(bind)?;
+            socket.connect(server)?;
+            Transport::Udp(socket)
+        } else if let Some(server) = address.strip_prefix("tcp://") {
+            Transport::Tcp {
+                address: server.to_string(),
+                stream: Some(TcpStream::connect(server)?),
+            }
+        } else if let Some(path) = address.strip_prefix("unix://") {
+            #[cfg(unix)]
+            {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                Transport::Unix(socket)
+            }
+            #[cfg(not(unix))]
+            return Err(format!("Unix sockets are not supported here: {}", path).into());
+        } else {
+            return Err(format!(
+                "Invalid syslog address '{}': expected udp://HOST:PORT, tcp://HOST:PORT or \
+                 unix://PATH",
+                address
+            )
+            .into());
+        };
+
+        Ok(Self {
+            transport: Arc::new(Mutex::new(transport)),
+            hostname: gethostname::gethostname().to_string_lossy().into_owned(),
+        })
+    }
+}
+
+/// A log line being written, sent as one message when dropped.
+pub struct Message {
+    /// The sink the message is sent to.
+    syslog: Syslog,
+    /// The syslog severity of the message.
+    severity: u8,
+    /// The formatted log line.
+    buffer: Vec<u8>,
+}
+
+impl Write for Message {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Message {
+    fn drop(&mut self) {
+        let text = String::from_utf8_lossy(&self.buffer);
+        let text = text.trim_end();
+        if text.is_empty() {
+            return;
+        }
+
+        let mut timestamp = String::new();
+        if SystemTime
+            .format_time(&mut Writer::new(&mut timestamp))
+            .is_err()
+        {
+            timestamp = "-".to_string();
+        }
+        let message = format!(
+            "<{}>1 {} {} {} {} - - {}",
+            FACILITY * 8 + self.severity,
+            timestamp,
+            self.syslog.hostname,
+            APP_NAME,
+            std::process::id(),
+            text
+        );
+
+        // Logging has nowhere to report its own failures
+        if let Ok(mut transport) = self.syslog.transport.lock() {
+            let _ = transport.send(message.as_bytes());
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for Syslog {
+    type Writer = Message;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Message {
+            syslog: self.clone(),
+            severity: 6,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let severity = match *meta.level() {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            Level::DEBUG | Level::TRACE => 7,
+        };
+        Message {
+            syslog: self.clone(),
+            severity,
+            buffer: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,1118 @@
+use crate::ip_source::AddressFamily;
+use aws_config::{sts::AssumeRoleProvider, SdkConfig};
+use aws_sdk_ec2::{
+    config::SharedCredentialsProvider,
+    operation::RequestId,
+    types::{
+        AddPrefixListEntry, Filter, ManagedPrefixList, PrefixListEntry, PrefixListState,
+        RemovePrefixListEntry,
+    },
+    Client,
+};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+/// The number of times a modification is attempted when it conflicts with another change.
+pub const MODIFY_ATTEMPTS: u32 = 3;
+
+/// The order in which an update adds the new entries and removes the old ones.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpdateOrder {
+    /// Add and remove in one modification, so the list changes at once
+    #[default]
+    Together,
+    /// Add the new entries first, so there is never a moment without access
+    AddFirst,
+    /// Remove the old entries first, so the list never holds both
+    RemoveFirst,
+}
+
+/// How often the prefix list state is polled while another change is in progress.
+const STATE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long to wait for another change to the prefix list to finish.
+const STATE_WAIT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How the prefix list to update is located in each target account.
+#[derive(Clone, Debug)]
+pub enum PrefixListRef {
+    /// A literal prefix list ID.
+    Id(String),
+    /// An SSM parameter holding the prefix list ID.
+    Ssm(String),
+    /// A tag carried by exactly one prefix list in the account.
+    Tag { key: String, value: String },
+}
+
+impl PrefixListRef {
+    /// Builds a prefix list reference from the command line arguments.
+    ///
+    /// # Parameters
+    ///
+    /// * `prefix_list_id`: The configured prefix list ID or `ssm:<parameter-name>`, if any.
+    /// * `prefix_list_tag`: The configured `Key=Value` tag, if any.
+    ///
+    /// # Returns
+    ///
+    /// The prefix list reference, or an error if neither option is usable.
+    pub fn from_args(
+        prefix_list_id: Option<&str>,
+        prefix_list_tag: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if let Some(tag) = prefix_list_tag {
+            let (key, value) = tag
+                .split_once('=')
+                .ok_or_else(|| format!("Prefix list tag must be Key=Value, got {}", tag))?;
+            return Ok(Self::Tag {
+                key: key.to_string(),
+                value: value.to_string(),
+            });
+        }
+
+        match prefix_list_id {
+            Some(id) => match id.strip_prefix("ssm:") {
+                Some(parameter_name) => Ok(Self::Ssm(parameter_name.to_string())),
+                None => Ok(Self::Id(id.to_string())),
+            },
+            None => Err("Either a prefix list ID or a prefix list tag is required".into()),
+        }
+    }
+}
+
+/// Validates a prefix list ID given on the command line, explaining common mistakes.
+///
+/// # Parameters
+///
+/// * `value`: A prefix list ID (`pl-` followed by hex digits) or `ssm:<parameter-name>`.
+///
+/// # Returns
+///
+/// The value, or an error describing what is wrong with it.
+pub fn parse_prefix_list_id(value: &str) -> Result<String, String> {
+    if value.starts_with("ssm:") {
+        return Ok(value.to_string());
+    }
+    if value.trim() != value {
+        return Err(format!(
+            "{:?} has leading or trailing whitespace, probably from copy/paste; use {:?}",
+            value,
+            value.trim()
+        ));
+    }
+
+    if let Some(hex) = value.strip_prefix("pl-") {
+        if !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            if hex.chars().any(|c| c.is_ascii_uppercase()) {
+                return Err(format!(
+                    "{} has uppercase letters; prefix list IDs are lowercase, use {}",
+                    value,
+                    value.to_ascii_lowercase()
+                ));
+            }
+            return Ok(value.to_string());
+        }
+        return Err(format!(
+            "{} is not a valid prefix list ID; expected pl- followed by hex digits, e.g. pl-0123456789abcdef0",
+            value
+        ));
+    }
+
+    // Other resource IDs share the `<kind>-<hex>` shape
+    let kind = match value.split_once('-').map(|(prefix, _)| prefix) {
+        Some("sg") => Some("a security group"),
+        Some("vpc") => Some("a VPC"),
+        Some("subnet") => Some("a subnet"),
+        Some("rtb") => Some("a route table"),
+        Some("eni") => Some("a network interface"),
+        Some("i") => Some("an instance"),
+        Some("nat") => Some("a NAT gateway"),
+        Some("eipalloc") => Some("an Elastic IP allocation"),
+        _ => None,
+    };
+    match kind {
+        Some(kind) => Err(format!(
+            "{} is the ID of {}, not a prefix list; pass the pl-... ID of the managed prefix list \
+             (aws ec2 describe-managed-prefix-lists lists them)",
+            value, kind
+        )),
+        None => Err(format!(
+            "{} looks like a prefix list name rather than an ID; find its pl-... ID with \
+             aws ec2 describe-managed-prefix-lists --filters Name=prefix-list-name,Values={}, \
+             or locate it by tag with --prefix-list-tag",
+            value, value
+        )),
+    }
+}
+
+/// A prefix list in another region than the configured one, updated along with it.
+#[derive(Clone, Debug)]
+pub struct RegionTarget {
+    /// The region of the prefix list.
+    pub region: String,
+    /// The prefix list ID, or `ssm:<parameter-name>` read in that region.
+    pub prefix_list_id: String,
+}
+
+/// Parses a prefix list in another region given on the command line.
+///
+/// # Parameters
+///
+/// * `value`: The prefix list as `region:prefix-list-id`.
+///
+/// # Returns
+///
+/// The target, or an error if the region or prefix list ID is not valid.
+pub fn parse_region_target(value: &str) -> Result<RegionTarget, String> {
+    let (region, prefix_list_id) = value
+        .split_once(':')
+        .filter(|(region, prefix_list_id)| !region.is_empty() && !prefix_list_id.is_empty())
+        .ok_or_else(|| {
+            format!(
+                "Invalid target {}; expected region:prefix-list-id, e.g. \
+                 eu-west-1:pl-0123456789abcdef0",
+                value
+            )
+        })?;
+    crate::partition::Partition::of_region(region)?;
+
+    Ok(RegionTarget {
+        region: region.to_string(),
+        prefix_list_id: parse_prefix_list_id(prefix_list_id)?,
+    })
+}
+
+impl std::fmt::Display for PrefixListRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{}", id),
+            Self::Ssm(parameter_name) => write!(f, "ssm:{}", parameter_name),
+            Self::Tag { key, value } => write!(f, "tag {}={}", key, value),
+        }
+    }
+}
+
+/// The entries added to and removed from a prefix list to reach a desired set of CIDRs.
+#[derive(Clone, Debug, Default)]
+pub struct EntryChange {
+    /// The CIDRs to add.
+    pub added: Vec<String>,
+    /// The CIDRs to remove.
+    pub removed: Vec<String>,
+}
+
+impl EntryChange {
+    /// Computes the change that turns the current entries into the desired ones.
+    ///
+    /// # Parameters
+    ///
+    /// * `current`: The CIDRs currently in the prefix list.
+    /// * `desired`: The CIDRs the prefix list should contain.
+    ///
+    /// # Returns
+    ///
+    /// The entries to add and remove.
+    pub fn between(current: &[String], desired: &[String]) -> Self {
+        Self {
+            added: desired
+                .iter()
+                .filter(|cidr| !current.contains(cidr))
+                .cloned()
+                .collect(),
+            removed: current
+                .iter()
+                .filter(|cidr| !desired.contains(cidr))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Returns whether the change leaves the prefix list untouched.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A prefix list's state read once and reused to plan and apply an update.
+pub struct Snapshot {
+    /// The version the entries were read at.
+    pub version: i64,
+    /// The maximum number of entries the prefix list allows.
+    max_entries: Option<i32>,
+    /// The entries at that version.
+    pub entries: Vec<PrefixListEntry>,
+}
+
+impl Snapshot {
+    /// Returns the CIDRs of the entries carrying a description.
+    ///
+    /// # Parameters
+    ///
+    /// * `description`: The description identifying the managed entries.
+    ///
+    /// # Returns
+    ///
+    /// The CIDRs of the managed entries.
+    pub fn managed_cidrs(&self, description: &str) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter(|e| e.description() == Some(description))
+            .filter_map(|e| e.cidr().map(str::to_string))
+            .collect()
+    }
+}
+
+/// A prefix list in a single AWS account that receives the managed entry.
+pub struct Target {
+    /// The name used to identify the target in logs (the account ID for assumed roles).
+    pub name: String,
+    /// The AWS configuration of the target account, used to assume roles from it.
+    config: SdkConfig,
+    /// The client instance used to interact with the AWS EC2 service, built on first use.
+    client: OnceLock<Client>,
+    /// The client instance used to read SSM parameters, built on first use.
+    ssm_client: OnceLock<aws_sdk_ssm::Client>,
+    /// The EC2 client in the owning account when the prefix list is shared to this one via RAM.
+    owner_client: Option<Client>,
+    /// The ID of the account the credentials belong to, looked up on first use.
+    account_id: Option<String>,
+    /// The resolved ID of the prefix list, empty until first resolved.
+    pub prefix_list_id: String,
+    /// The CIDRs last confirmed to be the managed entries of the prefix list.
+    pub applied_cidrs: Option<Vec<String>>,
+    /// The address family of the prefix list, if it could be read.
+    pub address_family: Option<AddressFamily>,
+    /// How this target's prefix list is located when it is not the monitor's, as for a list
+    /// in another region.
+    prefix_list_ref: Option<PrefixListRef>,
+}
+
+impl Target {
+    /// Creates a new instance of `Target`.
+    ///
+    /// # Parameters
+    ///
+    /// * `name`: The name used to identify the target in logs.
+    /// * `config`: The AWS configuration used to construct service clients.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Target`.
+    pub fn new(name: impl Into<String>, config: &SdkConfig) -> Self {
+        Self {
+            name: name.into(),
+            config: config.clone(),
+            client: OnceLock::new(),
+            ssm_client: OnceLock::new(),
+            owner_client: None,
+            account_id: None,
+            prefix_list_id: String::new(),
+            applied_cidrs: None,
+            address_family: None,
+            prefix_list_ref: None,
+        }
+    }
+
+    /// Creates a target for a prefix list in another region, with clients of that region.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration providing the credentials.
+    /// * `region_target`: The region and prefix list.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Target` named after its region, or an error if the prefix list
+    /// reference is not valid.
+    pub fn in_region(
+        config: &SdkConfig,
+        region_target: &RegionTarget,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let config = config
+            .to_builder()
+            .region(aws_config::Region::new(region_target.region.clone()))
+            .build();
+        let mut target = Self::new(&region_target.region, &config);
+        target.prefix_list_ref = Some(PrefixListRef::from_args(
+            Some(&region_target.prefix_list_id),
+            None,
+        )?);
+
+        Ok(target)
+    }
+
+    /// Creates a target in another account by assuming a role there.
+    ///
+    /// # Parameters
+    ///
+    /// * `config`: The AWS configuration providing the base credentials.
+    /// * `account_id`: The account to assume the role in.
+    /// * `role_name`: The name of the role to assume.
+    /// * `session_name`: The session name used when assuming the role.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Target` using the assumed role credentials.
+    pub async fn assume_role(
+        config: &SdkConfig,
+        account_id: &str,
+        role_name: &str,
+        session_name: &str,
+    ) -> Self {
+        let config = assume_role_config(config, account_id, role_name, session_name).await;
+        let mut target = Self::new(account_id, &config);
+        target.account_id = Some(account_id.to_string());
+        target
+    }
+
+    /// Returns the EC2 client of this account, building it on first use.
+    fn client(&self) -> &Client {
+        self.client
+            .get_or_init(|| crate::replay::ec2_client((&self.config).into()))
+    }
+
+    /// Returns the SSM client of this account, building it on first use.
+    fn ssm_client(&self) -> &aws_sdk_ssm::Client {
+        self.ssm_client
+            .get_or_init(|| aws_sdk_ssm::Client::new(&self.config))
+    }
+
+    /// Returns the EC2 client that reads and modifies the prefix list, which is the owning
+    /// account's client when the list is shared to this account.
+    fn list_client(&self) -> &Client {
+        self.owner_client.as_ref().unwrap_or_else(|| self.client())
+    }
+
+    /// Reads the address family of a prefix list.
+    ///
+    /// # Parameters
+    ///
+    /// * `prefix_list_id`: The ID of the prefix list.
+    ///
+    /// # Returns
+    ///
+    /// The family, or `None` if the list cannot be described, in which case detected addresses
+    /// of both families are written.
+    async fn read_address_family(&self, prefix_list_id: &str) -> Option<AddressFamily> {
+        match self
+            .list_client()
+            .describe_managed_prefix_lists()
+            .prefix_list_ids(prefix_list_id)
+            .send()
+            .await
+        {
+            Ok(response) => response
+                .prefix_lists()
+                .first()
+                .and_then(|list| list.address_family())
+                .and_then(AddressFamily::of_prefix_list),
+            Err(e) => {
+                let e: Box<dyn std::error::Error + Send + Sync> = e.into();
+                debug!(
+                    "Cannot read the address family of {}: {}",
+                    prefix_list_id,
+                    crate::errors::describe(&*e)
+                );
+                None
+            }
+        }
+    }
+
+    /// Resolves the prefix list ID for this target.
+    ///
+    /// SSM parameters and tags are re-resolved on every call so a rotated or replaced list is
+    /// picked up without restarting.
+    ///
+    /// # Parameters
+    ///
+    /// * `prefix_list_ref`: How the prefix list is located, unless the target has its own.
+    /// * `owner_role`: The role name and session name to assume in the owning account when the
+    ///   list is shared to this account via RAM.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(true)` if the resolved ID changed, `Ok(false)` if it didn't, or an error if the
+    /// lookup fails or the list cannot be modified from this account.
+    pub async fn resolve(
+        &mut self,
+        prefix_list_ref: &PrefixListRef,
+        owner_role: Option<(&str, &str)>,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let own_ref = self.prefix_list_ref.clone();
+        let prefix_list_ref = own_ref.as_ref().unwrap_or(prefix_list_ref);
+        let prefix_list_id = match prefix_list_ref {
+            PrefixListRef::Id(id) => id.clone(),
+            PrefixListRef::Ssm(parameter_name) => {
+                let response = self
+                    .ssm_client()
+                    .get_parameter()
+                    .name(parameter_name)
+                    .send()
+                    .await?;
+
+                response
+                    .parameter()
+                    .and_then(|p| p.value())
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(|| format!("SSM parameter {} has no value", parameter_name))?
+            }
+            PrefixListRef::Tag { key, value } => {
+                let response = self
+                    .client()
+                    .describe_managed_prefix_lists()
+                    .filters(
+                        Filter::builder()
+                            .name(format!("tag:{}", key))
+                            .values(value)
+                            .build(),
+                    )
+                    .send()
+                    .await?;
+
+                match response.prefix_lists() {
+                    [prefix_list] => prefix_list
+                        .prefix_list_id()
+                        .ok_or("Prefix list has no ID")?
+                        .to_string(),
+                    [] => return Err(format!("No prefix list tagged {}={}", key, value).into()),
+                    lists => {
+                        return Err(format!(
+                            "{} prefix lists tagged {}={}, expected exactly one",
+                            lists.len(),
+                            key,
+                            value
+                        )
+                        .into())
+                    }
+                }
+            }
+        };
+
+        if prefix_list_id == self.prefix_list_id {
+            return Ok(false);
+        }
+
+        if !matches!(prefix_list_ref, PrefixListRef::Id(_)) {
+            info!(
+                "Resolved prefix list ID {} from {}",
+                prefix_list_id, prefix_list_ref
+            );
+        }
+        self.owner_client = self.owner_client(&prefix_list_id, owner_role).await?;
+        self.address_family = self.read_address_family(&prefix_list_id).await;
+        self.prefix_list_id = prefix_list_id;
+        // A new list needs to be populated even if the IP is unchanged
+        self.applied_cidrs = None;
+
+        Ok(true)
+    }
+
+    /// Determines which account owns the prefix list and, when it is shared to this account via
+    /// RAM, assumes the configured role in the owning account so it can be modified.
+    ///
+    /// # Parameters
+    ///
+    /// * `prefix_list_id`: The ID of the prefix list.
+    /// * `owner_role`: The role name and session name to assume in the owning account, if any.
+    ///
+    /// # Returns
+    ///
+    /// The EC2 client for the owning account if the list is shared, `None` if this account owns
+    /// it, or an error if the list is shared and no owner role is configured.
+    async fn owner_client(
+        &mut self,
+        prefix_list_id: &str,
+        owner_role: Option<(&str, &str)>,
+    ) -> Result<Option<Client>, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self
+            .client()
+            .describe_managed_prefix_lists()
+            .prefix_list_ids(prefix_list_id)
+            .send()
+            .await?;
+        let owner_id = response
+            .prefix_lists()
+            .first()
+            .ok_or("Prefix list not found")?
+            .owner_id()
+            .ok_or("Prefix list has no owner")?
+            .to_string();
+
+        let account_id = match &self.account_id {
+            Some(account_id) => account_id.clone(),
+            None => {
+                let identity = crate::replay::sts_client((&self.config).into())
+                    .get_caller_identity()
+                    .send()
+                    .await?;
+                let account_id = identity
+                    .account()
+                    .ok_or("Caller identity has no account")?
+                    .to_string();
+                self.account_id = Some(account_id.clone());
+                account_id
+            }
+        };
+
+        if owner_id == account_id {
+            return Ok(None);
+        }
+
+        match owner_role {
+            Some((role_name, session_name)) => {
+                info!(
+                    "Prefix list {} is shared from {}, assuming {} there",
+                    prefix_list_id, owner_id, role_name
+                );
+                let config =
+                    assume_role_config(&self.config, &owner_id, role_name, session_name).await;
+                Ok(Some(crate::replay::ec2_client((&config).into())))
+            }
+            None => Err(format!(
+                "Prefix list {} is owned by {} and shared to {}; run with --role-arn into that account or set --owner-role-name",
+                prefix_list_id, owner_id, account_id
+            )
+            .into()),
+        }
+    }
+
+    /// Retrieves the prefix list's metadata (version, size limit, owner).
+    ///
+    /// # Returns
+    ///
+    /// The prefix list, or an error if the request fails or the list doesn't exist.
+    async fn describe_prefix_list(
+        &self,
+    ) -> Result<ManagedPrefixList, Box<dyn std::error::Error + Send + Sync>> {
+        let response = self
+            .list_client()
+            .describe_managed_prefix_lists()
+            .prefix_list_ids(&self.prefix_list_id)
+            .send()
+            .await?;
+
+        let prefix_list = response
+            .prefix_lists()
+            .first()
+            .ok_or("Prefix list not found")?;
+
+        Ok(prefix_list.clone())
+    }
+
+    /// Waits until no change to the prefix list is in progress.
+    ///
+    /// # Returns
+    ///
+    /// The settled prefix list, or an error if it stays busy past the timeout or the request
+    /// fails.
+    async fn wait_until_settled(
+        &self,
+    ) -> Result<ManagedPrefixList, Box<dyn std::error::Error + Send + Sync>> {
+        let started = Instant::now();
+        loop {
+            let prefix_list = self.describe_prefix_list().await?;
+            let state = prefix_list.state();
+            if !matches!(
+                state,
+                Some(
+                    PrefixListState::CreateInProgress
+                        | PrefixListState::ModifyInProgress
+                        | PrefixListState::RestoreInProgress
+                )
+            ) {
+                return Ok(prefix_list);
+            }
+
+            if started.elapsed() >= STATE_WAIT_TIMEOUT {
+                return Err(format!(
+                    "Prefix list {} is still {} after {} seconds",
+                    self.prefix_list_id,
+                    state.map(|s| s.as_str()).unwrap_or("busy"),
+                    STATE_WAIT_TIMEOUT.as_secs()
+                )
+                .into());
+            }
+            debug!(
+                "Prefix list {} is {}, waiting",
+                self.prefix_list_id,
+                state.map(|s| s.as_str()).unwrap_or("busy")
+            );
+            tokio::time::sleep(STATE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Retrieves the version of the prefix list.
+    ///
+    /// # Returns
+    ///
+    /// The version of the prefix list as an `i64`, or an error if the request fails.
+    pub async fn get_prefix_list_version(
+        &self,
+    ) -> Result<i64, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.describe_prefix_list().await?.version().unwrap_or(0))
+    }
+
+    /// Verifies the prefix list has room for the planned entries before modifying it, so a full
+    /// list produces an actionable message instead of an opaque API error.
+    ///
+    /// # Parameters
+    ///
+    /// * `snapshot`: The prefix list's state the update is based on.
+    /// * `adding`: The number of entries to be added.
+    /// * `removing`: The number of entries to be removed.
+    ///
+    /// # Returns
+    ///
+    /// An error if the entries would exceed the list's maximum, or `Ok(())` otherwise.
+    fn check_capacity(
+        &self,
+        snapshot: &Snapshot,
+        adding: usize,
+        removing: usize,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let Some(max_entries) = snapshot.max_entries else {
+            return Ok(());
+        };
+        if adding <= removing {
+            return Ok(());
+        }
+
+        let current = snapshot.entries.len();
+        let planned = current + adding - removing;
+        if planned > max_entries as usize {
+            return Err(format!(
+                "Prefix list {} is at {}/{} entries and this update needs {}; raise its max entries \
+                 (aws ec2 modify-managed-prefix-list --max-entries, which counts against the \
+                 rules quota of security groups referencing it) or remove unused entries",
+                self.prefix_list_id, current, max_entries, planned
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Reads the prefix list's version, size limit and entries once, after any change in
+    /// progress has finished, so an update can be planned and applied without reading them
+    /// again.
+    ///
+    /// # Returns
+    ///
+    /// The snapshot, or an error if the request fails.
+    pub async fn snapshot(&self) -> Result<Snapshot, Box<dyn std::error::Error + Send + Sync>> {
+        let prefix_list = self.wait_until_settled().await?;
+        let version = prefix_list.version().unwrap_or(0);
+
+        Ok(Snapshot {
+            version,
+            max_entries: prefix_list.max_entries(),
+            entries: self.get_entries(Some(version)).await?,
+        })
+    }
+
+    /// Retrieves the current entries from the prefix list.
+    ///
+    /// Every page of entries is read, so lists larger than one page are seen whole.
+    ///
+    /// # Parameters
+    ///
+    /// * `description`: The description identifying the managed entries.
+    ///
+    /// # Returns
+    ///
+    /// A vector of `String` representing the current entries in the prefix list, or an error if the request fails.
+    pub async fn get_current_entries(
+        &self,
+        description: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let entries: Vec<String> = self
+            .get_entries(None)
+            .await?
+            .iter()
+            .filter_map(|e| {
+                if e.description() == Some(description) {
+                    e.cidr().map(|s| s.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Retrieves all entries of the prefix list, regardless of description.
+    ///
+    /// # Parameters
+    ///
+    /// * `version`: The version to read, or `None` for the current version.
+    ///
+    /// # Returns
+    ///
+    /// The entries of the prefix list at that version, or an error if the request fails.
+    pub async fn get_entries(
+        &self,
+        version: Option<i64>,
+    ) -> Result<Vec<PrefixListEntry>, Box<dyn std::error::Error + Send + Sync>> {
+        let entries = self
+            .list_client()
+            .get_managed_prefix_list_entries()
+            .prefix_list_id(&self.prefix_list_id)
+            .set_target_version(version)
+            .into_paginator()
+            .items()
+            .send()
+            .try_collect()
+            .await?;
+
+        Ok(entries)
+    }
+
+    /// Restores the prefix list to the entries of a previous version.
+    ///
+    /// # Parameters
+    ///
+    /// * `previous_version`: The version whose entries are restored.
+    /// * `current_version`: The version the restore is based on.
+    ///
+    /// # Returns
+    ///
+    /// An error if the request fails, or `Ok(())` on success.
+    pub async fn restore_version(
+        &self,
+        previous_version: i64,
+        current_version: i64,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let response = self
+            .list_client()
+            .restore_managed_prefix_list_version()
+            .prefix_list_id(&self.prefix_list_id)
+            .previous_version(previous_version)
+            .current_version(current_version)
+            .send()
+            .await?;
+        info!(
+            prefix_list_id = %self.prefix_list_id,
+            version = response.prefix_list().and_then(|list| list.version()),
+            "Restored prefix list {} to version {} (request ID {})",
+            self.prefix_list_id,
+            previous_version,
+            response.request_id().unwrap_or("unknown")
+        );
+
+        Ok(())
+    }
+
+    /// Updates the prefix list by adding or replacing entries.
+    ///
+    /// # Parameters
+    ///
+    /// * `snapshot`: The state the update was planned from, or `None` to read it first.
+    /// * `new_cidrs`: The CIDRs to be added to the prefix list.
+    /// * `old_cidrs`: The CIDRs to be removed from the prefix list.
+    /// * `description`: The description given to the new entries.
+    /// * `order`: Whether to add and remove in one modification or one after the other.
+    ///
+    /// # Returns
+    ///
+    /// An error if the request fails, or `Ok(())` on success.
+    pub async fn update_prefix_list(
+        &self,
+        snapshot: Option<Snapshot>,
+        new_cidrs: &[String],
+        old_cidrs: &[String],
+        description: &str,
+        order: UpdateOrder,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let new_entries: Vec<(String, String)> = new_cidrs
+            .iter()
+            .map(|cidr| (cidr.clone(), description.to_string()))
+            .collect();
+
+        // A one-sided change needs a single modification whatever the order
+        if order == UpdateOrder::Together || new_entries.is_empty() || old_cidrs.is_empty() {
+            return self.modify_entries(snapshot, &new_entries, old_cidrs).await;
+        }
+
+        // The second modification reads the version the first one produced
+        if order == UpdateOrder::AddFirst {
+            self.modify_entries(snapshot, &new_entries, &[]).await?;
+            self.modify_entries(None, &[], old_cidrs).await
+        } else {
+            self.modify_entries(snapshot, &[], old_cidrs).await?;
+            self.modify_entries(None, &new_entries, &[]).await
+        }
+    }
+
+    /// Adds entries with their own descriptions to the prefix list and removes others.
+    ///
+    /// Adding a CIDR that is already present replaces its description. The snapshot's version
+    /// is used for the first attempt; the state is only read again after a conflict.
+    ///
+    /// # Parameters
+    ///
+    /// * `snapshot`: The state the update was planned from, or `None` to read it first.
+    /// * `new_entries`: The CIDRs and descriptions to be added to the prefix list.
+    /// * `old_cidrs`: The CIDRs to be removed from the prefix list.
+    ///
+    /// # Returns
+    ///
+    /// An error if the request fails, or `Ok(())` on success.
+    pub async fn modify_entries(
+        &self,
+        mut snapshot: Option<Snapshot>,
+        new_entries: &[(String, String)],
+        old_cidrs: &[String],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut attempt = 1;
+        loop {
+            let current = match snapshot.take() {
+                Some(snapshot) => snapshot,
+                None => self.snapshot().await?,
+            };
+            self.check_capacity(&current, new_entries.len(), old_cidrs.len())?;
+            let version = current.version;
+
+            let mut modify_request = self
+                .list_client()
+                .modify_managed_prefix_list()
+                .prefix_list_id(&self.prefix_list_id)
+                .current_version(version);
+
+            // Remove old entries with matching description
+            for old_cidr in old_cidrs {
+                debug!("Removing old entry: {}", old_cidr);
+                let entry = RemovePrefixListEntry::builder().cidr(old_cidr).build();
+                modify_request = modify_request.remove_entries(entry);
+            }
+
+            // Add new entries
+            for (new_cidr, description) in new_entries {
+                debug!("Adding new entry: {}", new_cidr);
+                let entry = AddPrefixListEntry::builder()
+                    .cidr(new_cidr)
+                    .description(description)
+                    .build();
+                modify_request = modify_request.add_entries(entry);
+            }
+
+            match modify_request.send().await {
+                Ok(response) => {
+                    if let Some(updated_list) = response.prefix_list() {
+                        info!(
+                            prefix_list_id = %self.prefix_list_id,
+                            version = updated_list.version().unwrap_or(0),
+                            "Successfully updated prefix list to version {} (request ID {})",
+                            updated_list.version().unwrap_or(0),
+                            response.request_id().unwrap_or("unknown")
+                        );
+                    }
+                    return Ok(());
+                }
+                // Another change landed between reading the version and writing; start over
+                Err(e)
+                    if attempt < MODIFY_ATTEMPTS
+                        && matches!(
+                            e.as_service_error().and_then(|e| e.meta().code()),
+                            Some("PrefixListVersionMismatch" | "IncorrectState")
+                        ) =>
+                {
+                    warn!(
+                        "Prefix list {} changed concurrently (attempt {}/{}, request ID {}), \
+                         retrying",
+                        self.prefix_list_id,
+                        attempt,
+                        MODIFY_ATTEMPTS,
+                        e.request_id().unwrap_or("unknown")
+                    );
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+/// Builds an AWS configuration using a role assumed in another account.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration providing the base credentials.
+/// * `account_id`: The account to assume the role in.
+/// * `role_name`: The name of the role to assume.
+/// * `session_name`: The session name used when assuming the role.
+///
+/// # Returns
+///
+/// The AWS configuration using the assumed role credentials.
+async fn assume_role_config(
+    config: &SdkConfig,
+    account_id: &str,
+    role_name: &str,
+    session_name: &str,
+) -> SdkConfig {
+    let role_arn =
+        crate::partition::Partition::of_config(config.region()).role_arn(account_id, role_name);
+    let provider = AssumeRoleProvider::builder(role_arn)
+        .session_name(session_name)
+        .configure(config)
+        .build()
+        .await;
+
+    config
+        .clone()
+        .into_builder()
+        .credentials_provider(SharedCredentialsProvider::new(provider))
+        .build()
+}
+
+/// Lists the active member accounts directly under an organizational unit.
+///
+/// # Parameters
+///
+/// * `config`: The AWS configuration used to construct the Organizations client.
+/// * `organizational_unit_id`: The OU (or root) whose accounts are listed.
+///
+/// # Returns
+///
+/// The account IDs, or an error if the request fails.
+pub async fn list_organizational_unit_accounts(
+    config: &SdkConfig,
+    organizational_unit_id: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let client = aws_sdk_organizations::Client::new(config);
+    let mut pages = client
+        .list_accounts_for_parent()
+        .parent_id(organizational_unit_id)
+        .into_paginator()
+        .send();
+
+    let mut account_ids = Vec::new();
+    while let Some(page) = pages.try_next().await? {
+        account_ids.extend(
+            page.accounts()
+                .iter()
+                .filter(|a| a.state() == Some(&aws_sdk_organizations::types::AccountState::Active))
+                .filter_map(|a| a.id().map(|id| id.to_string())),
+        );
+    }
+
+    Ok(account_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::replay::ReplayClient;
+
+    /// Builds a target whose prefix list is already resolved, answered by a recording.
+    fn target(replay: &ReplayClient) -> Target {
+        let mut target = Target::new("us-east-1", &replay.sdk_config());
+        target.prefix_list_id = "pl-0123456789abcdef0".to_string();
+        target
+    }
+
+    /// Replaces the recorded managed entry with a new address.
+    async fn replace_entry(
+        target: &Target,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        target
+            .modify_entries(
+                None,
+                &[("203.0.113.7/32".to_string(), "Home".to_string())],
+                &["198.51.100.4/32".to_string()],
+            )
+            .await
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn throttled_requests_are_retried() {
+        let replay = ReplayClient::fixture("throttling.jsonl");
+
+        replace_entry(&target(&replay)).await.unwrap();
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn version_conflict_is_retried_on_the_new_version() {
+        let replay = ReplayClient::fixture("version_conflict.jsonl");
+
+        replace_entry(&target(&replay)).await.unwrap();
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn persistent_version_conflict_gives_up() {
+        let replay = ReplayClient::fixture("version_conflict_persistent.jsonl");
+
+        let e = replace_entry(&target(&replay)).await.unwrap_err();
+        assert!(crate::errors::describe(&*e).starts_with("PrefixListVersionMismatch"));
+        // Every attempt read the list again and tried once more, and no more
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn modification_in_progress_is_waited_out() {
+        let replay = ReplayClient::fixture("modify_in_progress.jsonl");
+
+        let started = Instant::now();
+        replace_entry(&target(&replay)).await.unwrap();
+        assert_eq!(started.elapsed(), STATE_POLL_INTERVAL * 2);
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[test]
+    fn prefix_list_ids_are_accepted() {
+        for value in [
+            "pl-0123456789abcdef0",
+            "pl-63a5400a",
+            "ssm:/network/prefix-list-id",
+        ] {
+            assert_eq!(parse_prefix_list_id(value).as_deref(), Ok(value));
+        }
+    }
+
+    #[test]
+    fn prefix_list_id_mistakes_are_explained() {
+        let error = |value| parse_prefix_list_id(value).unwrap_err();
+        assert!(error(" pl-0123456789abcdef0\n").contains("use \"pl-0123456789abcdef0\""));
+        assert!(error("pl-0123456789ABCDEF0").contains("use pl-0123456789abcdef0"));
+        assert!(error("pl-").contains("expected pl- followed by hex digits"));
+        assert!(error("pl-home").contains("expected pl- followed by hex digits"));
+        assert!(error("sg-0123456789abcdef0").contains("the ID of a security group"));
+        assert!(error("vpc-0123456789abcdef0").contains("the ID of a VPC"));
+        assert!(error("home-office").contains("looks like a prefix list name"));
+    }
+
+    #[test]
+    fn region_targets_are_parsed() {
+        let target = parse_region_target("eu-west-1:pl-0123456789abcdef0").unwrap();
+        assert_eq!(target.region, "eu-west-1");
+        assert_eq!(target.prefix_list_id, "pl-0123456789abcdef0");
+
+        // Only the first colon separates the region
+        let target = parse_region_target("us-gov-west-1:ssm:/network/prefix-list-id").unwrap();
+        assert_eq!(target.region, "us-gov-west-1");
+        assert_eq!(target.prefix_list_id, "ssm:/network/prefix-list-id");
+    }
+
+    #[test]
+    fn invalid_region_targets_are_rejected() {
+        for value in [
+            "pl-0123456789abcdef0",
+            ":pl-0123456789abcdef0",
+            "eu-west-1:",
+            "eu-west:pl-0123456789abcdef0",
+            "us-iso-east-1:pl-0123456789abcdef0",
+            "eu-west-1:sg-0123456789abcdef0",
+        ] {
+            assert!(
+                parse_region_target(value).is_err(),
+                "{} was accepted",
+                value
+            );
+        }
+    }
+}
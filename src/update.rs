@@ -0,0 +1,223 @@
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// The GitHub API URL of the latest release.
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/kariudo/aws-vpc-prefix-list-updater/releases/latest";
+
+/// The name of the release asset listing the SHA-256 checksums of the binaries.
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+/// A GitHub release.
+#[derive(Deserialize)]
+struct Release {
+    /// The tag, e.g. `v0.2.0`.
+    tag_name: String,
+    /// The files attached to the release.
+    assets: Vec<Asset>,
+}
+
+/// A file attached to a GitHub release.
+#[derive(Deserialize)]
+struct Asset {
+    /// The file name.
+    name: String,
+    /// The URL the file is downloaded from.
+    browser_download_url: String,
+}
+
+impl Release {
+    /// Returns the download URL of an asset.
+    ///
+    /// # Parameters
+    ///
+    /// * `name`: The file name of the asset.
+    ///
+    /// # Returns
+    ///
+    /// The URL, or an error if the release has no such asset.
+    fn asset_url(&self, name: &str) -> Result<&str, Box<dyn std::error::Error + Send + Sync>> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.as_str())
+            .ok_or_else(|| format!("Release {} has no {} asset", self.tag_name, name).into())
+    }
+}
+
+/// Parses a version such as `v1.2.3` into its numeric parts.
+///
+/// # Parameters
+///
+/// * `version`: The version, with or without a leading `v`.
+///
+/// # Returns
+///
+/// The major, minor and patch numbers, or `None` if the version is not numeric.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = version
+        .trim_start_matches('v')
+        .split(['.', '-', '+'])
+        .map(|part| part.parse::<u64>());
+    Some((
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+        parts.next()?.ok()?,
+    ))
+}
+
+/// Finds the checksum of a file in a `sha256sum` listing.
+///
+/// # Parameters
+///
+/// * `listing`: The contents of the checksum file.
+/// * `name`: The file name.
+///
+/// # Returns
+///
+/// The lowercase hex checksum, if the file is listed.
+fn find_checksum(listing: &str, name: &str) -> Option<String> {
+    listing.lines().find_map(|line| {
+        let (checksum, file) = line.split_once(char::is_whitespace)?;
+        // sha256sum marks binary mode with a leading asterisk
+        (file.trim().trim_start_matches('*') == name).then(|| checksum.to_ascii_lowercase())
+    })
+}
+
+/// Replaces the running executable with a new binary.
+///
+/// # Parameters
+///
+/// * `current`: The path of the running executable.
+/// * `binary`: The contents of the new binary.
+///
+/// # Returns
+///
+/// An error if the file cannot be written or moved into place, or `Ok(())` on success.
+fn replace_executable(
+    current: &Path,
+    binary: &[u8],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Written next to the executable, so the rename stays on one file system
+    let staged = PathBuf::from(format!("{}.new", current.display()));
+    std::fs::write(&staged, binary)
+        .map_err(|e| format!("Cannot write {}: {}", staged.display(), e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(current)?.permissions().mode();
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(mode))?;
+    }
+    // Windows cannot replace a running executable, but it can rename it out of the way
+    #[cfg(windows)]
+    {
+        let old = PathBuf::from(format!("{}.old", current.display()));
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(current, &old)?;
+    }
+
+    std::fs::rename(&staged, current)
+        .map_err(|e| format!("Cannot replace {}: {}", current.display(), e))?;
+
+    Ok(())
+}
+
+/// Checks GitHub for a newer release and, unless only checking, installs it over the
+/// running executable after verifying its checksum.
+///
+/// # Parameters
+///
+/// * `check_only`: Whether to only report whether an update is available.
+/// * `yes`: Whether to install without asking for confirmation.
+///
+/// # Returns
+///
+/// `Ok(true)` if a newer release is available and was not installed, `Ok(false)` otherwise,
+/// or an error if a request, the verification or the replacement fails.
+pub async fn self_update(
+    check_only: bool,
+    yes: bool,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .build()?;
+    let release: Release = serde_json::from_str(
+        &client
+            .get(LATEST_RELEASE_URL)
+            .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?,
+    )?;
+
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = parse_version(&release.tag_name)
+        .ok_or_else(|| format!("Unrecognized release tag {}", release.tag_name))?;
+    if Some(latest) <= parse_version(current) {
+        println!("Already up to date ({})", current);
+        return Ok(false);
+    }
+    println!("Update available: {} -> {}", current, release.tag_name);
+    if check_only {
+        return Ok(true);
+    }
+
+    let asset = format!("{}-{}", env!("CARGO_PKG_NAME"), env!("BUILD_TARGET"));
+    #[cfg(windows)]
+    let asset = format!("{}.exe", asset);
+    let binary_url = release.asset_url(&asset)?;
+    let checksums_url = release.asset_url(CHECKSUMS_ASSET)?;
+
+    if !yes && !crate::commands::confirm(&format!("Install {}?", release.tag_name))? {
+        println!("Update declined");
+        return Ok(true);
+    }
+
+    let checksums = client
+        .get(checksums_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let expected = find_checksum(&checksums, &asset)
+        .ok_or_else(|| format!("{} does not list {}", CHECKSUMS_ASSET, asset))?;
+    info!("Downloading {}", binary_url);
+    let binary = client
+        .get(binary_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let actual: String = Sha256::digest(&binary)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if actual != expected {
+        return Err(format!(
+            "Checksum mismatch for {}: expected {}, got {}; the binary was not replaced",
+            asset, expected, actual
+        )
+        .into());
+    }
+
+    let executable = std::env::current_exe()?;
+    replace_executable(&executable, &binary)?;
+    println!(
+        "Updated {} to {}; restart it to run the new version",
+        executable.display(),
+        release.tag_name
+    );
+
+    Ok(false)
+}
@@ -0,0 +1,418 @@
+use hickory_resolver::TokioResolver;
+use maxminddb::Reader;
+use serde::Deserialize;
+use std::fmt;
+use std::net::IpAddr;
+use tracing::{debug, warn};
+
+/// The fields of a GeoIP record used to verify addresses; country and ASN databases each
+/// provide some of them.
+#[derive(Deserialize, Debug)]
+struct GeoRecord<'a> {
+    /// The country the address is registered in.
+    #[serde(borrow)]
+    country: Option<GeoCountry<'a>>,
+    /// The number of the autonomous system announcing the address.
+    autonomous_system_number: Option<u32>,
+    /// The organization operating the autonomous system.
+    autonomous_system_organization: Option<&'a str>,
+}
+
+/// The country of a GeoIP record.
+#[derive(Deserialize, Debug)]
+struct GeoCountry<'a> {
+    /// The ISO 3166-1 alpha-2 code of the country.
+    iso_code: Option<&'a str>,
+}
+
+/// Parses an autonomous system number given on the command line.
+///
+/// # Parameters
+///
+/// * `value`: The number, optionally prefixed with `AS`.
+///
+/// # Returns
+///
+/// The number, or an error if it is not valid.
+pub fn parse_asn(value: &str) -> Result<u32, String> {
+    let number = value
+        .strip_prefix("AS")
+        .or_else(|| value.strip_prefix("as"))
+        .unwrap_or(value);
+    number
+        .parse()
+        .map_err(|_| format!("Invalid AS number {}; expected e.g. 7922 or AS7922", value))
+}
+
+/// A CIDR range detected addresses are checked against.
+#[derive(Clone, Copy, Debug)]
+pub struct IpRange {
+    /// The network address of the range.
+    network: IpAddr,
+    /// The prefix length of the range.
+    length: u8,
+}
+
+impl IpRange {
+    /// Returns whether the range contains an address.
+    ///
+    /// # Parameters
+    ///
+    /// * `ip`: The IP address.
+    ///
+    /// # Returns
+    ///
+    /// Whether `ip` is in the range; addresses of the other family never are.
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.length))
+                    .unwrap_or(0);
+                u32::from(ip) & mask == u32::from(network) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.length))
+                    .unwrap_or(0);
+                u128::from(ip) & mask == u128::from(network) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.length)
+    }
+}
+
+/// Parses a CIDR range given on the command line.
+///
+/// # Parameters
+///
+/// * `value`: A CIDR, or an IP address standing for a single host.
+///
+/// # Returns
+///
+/// The range, or an error if it is not a valid address or CIDR.
+pub fn parse_range(value: &str) -> Result<IpRange, String> {
+    let (address, length) = match value.split_once('/') {
+        Some((address, length)) => (address, Some(length)),
+        None => (value, None),
+    };
+    let network: IpAddr = address
+        .parse()
+        .map_err(|_| format!("Invalid IP address or CIDR {}", value))?;
+    let max_length = if network.is_ipv4() { 32 } else { 128 };
+    let length = match length {
+        Some(length) => length
+            .parse::<u8>()
+            .ok()
+            .filter(|l| *l <= max_length)
+            .ok_or_else(|| format!("Invalid prefix length in {}", value))?,
+        None => max_length,
+    };
+
+    Ok(IpRange { network, length })
+}
+
+/// Matches a host name against a pattern in which `*` stands for any run of characters.
+///
+/// # Parameters
+///
+/// * `pattern`: The pattern, e.g. `*.comcast.net`.
+/// * `name`: The host name, without its trailing dot.
+///
+/// # Returns
+///
+/// Whether the name matches, ignoring case.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let name = name.to_ascii_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<_> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard, so the whole name must have been consumed
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Checks detected addresses before they are written to the prefix lists, catching traffic
+/// that unexpectedly leaves through a VPN or an echo service that answers wrongly.
+pub struct Verifier {
+    /// The MaxMind databases the addresses are looked up in.
+    databases: Vec<Reader<Vec<u8>>>,
+    /// The country codes addresses must be in, if restricted.
+    expected_countries: Vec<String>,
+    /// The autonomous systems addresses must belong to, if restricted.
+    expected_asns: Vec<u32>,
+    /// The resolver for reverse lookups, present when PTR names are checked.
+    resolver: Option<TokioResolver>,
+    /// The patterns one of an address's PTR names must match, if restricted.
+    rdns_patterns: Vec<String>,
+    /// The ranges addresses must fall within, if restricted.
+    allowed_ranges: Vec<IpRange>,
+    /// The ranges addresses must never fall within.
+    denied_ranges: Vec<IpRange>,
+    /// Whether failed checks only log a warning instead of refusing the update.
+    warn_only: bool,
+}
+
+impl Verifier {
+    /// Creates a new instance of `Verifier`.
+    ///
+    /// # Parameters
+    ///
+    /// * `args`: The arguments passed to the program.
+    ///
+    /// # Returns
+    ///
+    /// A new instance of `Verifier`, or an error if a database cannot be opened or the system
+    /// resolver configuration cannot be read.
+    pub fn from_args(args: &crate::Args) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let databases = args
+            .geoip_databases
+            .iter()
+            .map(|path| {
+                Reader::open_readfile(path)
+                    .map_err(|e| format!("Cannot open GeoIP database {}: {}", path.display(), e))
+            })
+            .collect::<Result<_, _>>()?;
+        let resolver = if args.rdns_patterns.is_empty() {
+            None
+        } else {
+            Some(TokioResolver::builder_tokio()?.build())
+        };
+
+        Ok(Self {
+            databases,
+            expected_countries: args.expected_countries.clone(),
+            expected_asns: args.expected_asns.clone(),
+            resolver,
+            rdns_patterns: args.rdns_patterns.clone(),
+            allowed_ranges: args.allowed_ranges.clone(),
+            denied_ranges: args.denied_ranges.clone(),
+            warn_only: args.verify_warn_only,
+        })
+    }
+
+    /// Verifies detected addresses.
+    ///
+    /// # Parameters
+    ///
+    /// * `ips`: The detected IP addresses.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if every address passes or failures only warn, or an error describing the
+    /// failures. Addresses outside the allowed or inside the denied ranges are always refused.
+    pub async fn verify(
+        &self,
+        ips: &[String],
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let violations: Vec<_> = ips
+            .iter()
+            .map(|ip| ip.parse())
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter_map(|ip| self.check_ranges(ip))
+            .collect();
+        if !violations.is_empty() {
+            return Err(format!(
+                "Refusing to apply guarded address: {}",
+                violations.join("; ")
+            )
+            .into());
+        }
+
+        let mut problems = Vec::new();
+        for ip in ips {
+            let ip = ip.parse()?;
+            if let Some(problem) = self.check_geoip(ip)? {
+                problems.push(problem);
+            }
+            if let Some(problem) = self.check_rdns(ip).await? {
+                problems.push(problem);
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+        if self.warn_only {
+            warn!(
+                "Address verification failed, applying anyway: {}",
+                problems.join("; ")
+            );
+            return Ok(());
+        }
+
+        Err(format!(
+            "Refusing to apply unverified address: {}",
+            problems.join("; ")
+        )
+        .into())
+    }
+
+    /// Checks an address against the allowed and denied ranges.
+    ///
+    /// # Parameters
+    ///
+    /// * `ip`: The IP address.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the address passes, or the violation.
+    fn check_ranges(&self, ip: IpAddr) -> Option<String> {
+        if let Some(range) = self.denied_ranges.iter().find(|range| range.contains(ip)) {
+            return Some(format!("{} is in denied range {}", ip, range));
+        }
+        if !self.allowed_ranges.is_empty()
+            && !self.allowed_ranges.iter().any(|range| range.contains(ip))
+        {
+            return Some(format!(
+                "{} is outside the allowed ranges {}",
+                ip,
+                self.allowed_ranges
+                    .iter()
+                    .map(|range| range.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+
+        None
+    }
+
+    /// Checks an address against the expected countries and autonomous systems.
+    ///
+    /// # Parameters
+    ///
+    /// * `ip`: The IP address.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` if the address passes, `Ok(Some(problem))` if it does not, or an error if a
+    /// database lookup fails.
+    fn check_geoip(
+        &self,
+        ip: IpAddr,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        if self.expected_countries.is_empty() && self.expected_asns.is_empty() {
+            return Ok(None);
+        }
+
+        let mut country = None;
+        let mut asn = None;
+        let mut organization = None;
+        for database in &self.databases {
+            if let Some(record) = database.lookup::<GeoRecord>(ip)? {
+                country = country.or(record.country.and_then(|c| c.iso_code));
+                asn = asn.or(record.autonomous_system_number);
+                organization = organization.or(record.autonomous_system_organization);
+            }
+        }
+        debug!(
+            "GeoIP for {}: country {}, {}",
+            ip,
+            country.unwrap_or("unknown"),
+            asn.map(|n| format!("AS{}", n))
+                .unwrap_or_else(|| "unknown AS".to_string())
+        );
+
+        if !self.expected_countries.is_empty()
+            && !country.is_some_and(|country| {
+                self.expected_countries
+                    .iter()
+                    .any(|expected| expected.eq_ignore_ascii_case(country))
+            })
+        {
+            return Ok(Some(format!(
+                "{} is located in {} rather than {}",
+                ip,
+                country.unwrap_or("an unknown country"),
+                self.expected_countries.join(", ")
+            )));
+        }
+
+        if !self.expected_asns.is_empty() && !asn.is_some_and(|n| self.expected_asns.contains(&n)) {
+            return Ok(Some(format!(
+                "{} belongs to {} rather than {}",
+                ip,
+                match (asn, organization) {
+                    (Some(n), Some(organization)) => format!("AS{} ({})", n, organization),
+                    (Some(n), None) => format!("AS{}", n),
+                    (None, _) => "an unknown AS".to_string(),
+                },
+                self.expected_asns
+                    .iter()
+                    .map(|n| format!("AS{}", n))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+
+        Ok(None)
+    }
+
+    /// Checks the PTR names of an address against the expected patterns.
+    ///
+    /// # Parameters
+    ///
+    /// * `ip`: The IP address.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(None)` if the address passes, `Ok(Some(problem))` if it does not, or an error if the
+    /// reverse lookup fails.
+    async fn check_rdns(
+        &self,
+        ip: IpAddr,
+    ) -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let Some(resolver) = &self.resolver else {
+            return Ok(None);
+        };
+
+        let names: Vec<String> = match resolver.reverse_lookup(ip).await {
+            Ok(lookup) => lookup
+                .iter()
+                .map(|name| name.to_utf8().trim_end_matches('.').to_string())
+                .collect(),
+            Err(e) if e.is_no_records_found() => Vec::new(),
+            Err(e) => return Err(format!("Reverse lookup of {} failed: {}", ip, e).into()),
+        };
+        debug!("PTR names of {}: {}", ip, names.join(", "));
+
+        if names.is_empty() {
+            return Ok(Some(format!("{} has no PTR record", ip)));
+        }
+        if names.iter().any(|name| {
+            self.rdns_patterns
+                .iter()
+                .any(|pattern| glob_matches(pattern, name))
+        }) {
+            return Ok(None);
+        }
+
+        Ok(Some(format!(
+            "{} resolves to {} rather than {}",
+            ip,
+            names.join(", "),
+            self.rdns_patterns.join(", ")
+        )))
+    }
+}